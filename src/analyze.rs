@@ -0,0 +1,165 @@
+//! The `analyze flags` subcommand, which reports the flags every entry shares and the ones that
+//! vary across the tree, for seeding clangd's `CompileFlags.Add` and for spotting components whose
+//! settings drift from the rest of the build.
+
+use crate::{
+    CompileCommandsEntry, canonical,
+    compress::{self, Compression},
+    paths, read_compile_commands,
+};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    path::{Path, PathBuf},
+};
+
+#[derive(serde::Serialize)]
+struct FlagReport {
+    entries: usize,
+    /// Flags every entry passes, in the order they first appear
+    common: Vec<String>,
+    /// Flags only some entries pass, most widely used first
+    varying: Vec<VaryingFlag>,
+    /// Directories whose entries don't agree with the flags most entries pass
+    directories: Vec<DirectoryDelta>,
+}
+
+#[derive(serde::Serialize)]
+struct VaryingFlag {
+    flag: String,
+    /// How many entries pass the flag
+    entries: usize,
+    /// How many directories have an entry that passes the flag
+    directories: usize,
+}
+
+#[derive(serde::Serialize)]
+struct DirectoryDelta {
+    directory: PathBuf,
+    entries: usize,
+    /// Flags every entry in the directory passes that most entries in the tree don't
+    added: Vec<String>,
+    /// Flags most entries in the tree pass that no entry in the directory does
+    removed: Vec<String>,
+}
+
+/// The flags `entry` passes, normalized, without its source files or response files.
+fn flags(entry: &CompileCommandsEntry) -> Vec<String> {
+    let mut seen = HashSet::new();
+    canonical::arguments(&entry.command)
+        .into_iter()
+        .filter(|argument| argument.starts_with(['/', '-']))
+        .filter(|flag| seen.insert(flag.clone()))
+        .collect()
+}
+
+fn report(entries: &[CompileCommandsEntry]) -> FlagReport {
+    let mut order = Vec::new();
+    let mut counts: HashMap<String, usize> = HashMap::new();
+    let mut dirs_by_flag: HashMap<String, HashSet<&Path>> = HashMap::new();
+    let mut by_dir: BTreeMap<&Path, Vec<Vec<String>>> = BTreeMap::new();
+    for entry in entries {
+        let flags = flags(entry);
+        for flag in &flags {
+            let count = counts.entry(flag.clone()).or_default();
+            if *count == 0 {
+                order.push(flag.clone());
+            }
+            *count += 1;
+            dirs_by_flag
+                .entry(flag.clone())
+                .or_default()
+                .insert(&entry.directory);
+        }
+        by_dir.entry(&entry.directory).or_default().push(flags);
+    }
+
+    let common: Vec<String> = order
+        .iter()
+        .filter(|flag| counts[*flag] == entries.len())
+        .cloned()
+        .collect();
+    let mut varying: Vec<VaryingFlag> = order
+        .iter()
+        .filter(|flag| counts[*flag] < entries.len())
+        .map(|flag| VaryingFlag {
+            flag: flag.clone(),
+            entries: counts[flag],
+            directories: dirs_by_flag[flag].len(),
+        })
+        .collect();
+    varying.sort_by(|a, b| b.entries.cmp(&a.entries).then_with(|| a.flag.cmp(&b.flag)));
+
+    // The standard settings are the ones most of the tree agrees on
+    let standard: Vec<&String> = order
+        .iter()
+        .filter(|flag| counts[*flag] * 2 > entries.len())
+        .collect();
+    let directories = by_dir
+        .into_iter()
+        .filter_map(|(directory, dir_flags)| {
+            let added: Vec<String> = dir_flags[0]
+                .iter()
+                .filter(|flag| !standard.contains(flag))
+                .filter(|flag| dir_flags.iter().all(|flags| flags.contains(flag)))
+                .cloned()
+                .collect();
+            let removed: Vec<String> = standard
+                .iter()
+                .filter(|flag| !dir_flags.iter().any(|flags| flags.contains(flag)))
+                .map(|flag| flag.to_string())
+                .collect();
+            (!added.is_empty() || !removed.is_empty()).then(|| DirectoryDelta {
+                directory: directory.to_path_buf(),
+                entries: dir_flags.len(),
+                added,
+                removed,
+            })
+        })
+        .collect();
+
+    FlagReport {
+        entries: entries.len(),
+        common,
+        varying,
+        directories,
+    }
+}
+
+pub fn flags_report(output_dir: &str, json_path: Option<&Path>, compression: Option<Compression>) {
+    let compile_commands_path = paths::absolute(output_dir).join("compile_commands.json");
+    let compile_commands = read_compile_commands(&compile_commands_path);
+    let report = report(&compile_commands);
+
+    println!("Entries:      {}", report.entries);
+    println!("Common flags: {}", report.common.join(" "));
+    if !report.varying.is_empty() {
+        println!();
+        println!("{:>8} {:>11}  flag", "entries", "directories");
+        for flag in &report.varying {
+            println!(
+                "{:>8} {:>11}  {}",
+                flag.entries, flag.directories, flag.flag
+            );
+        }
+    }
+    if !report.directories.is_empty() {
+        println!();
+        println!("Directories that differ from most of the tree:");
+        for delta in &report.directories {
+            println!("{} ({} entries)", delta.directory.display(), delta.entries);
+            for flag in &delta.added {
+                println!("    + {}", flag);
+            }
+            for flag in &delta.removed {
+                println!("    - {}", flag);
+            }
+        }
+    }
+
+    if let Some(json_path) = json_path {
+        let json =
+            serde_json::to_string_pretty(&report).expect("Failed to serialize the flag report");
+        let written = compress::write(json_path, json.as_bytes(), compression);
+        println!("Wrote the report to {}", written.display());
+    }
+}
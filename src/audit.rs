@@ -0,0 +1,69 @@
+//! The `audit` subcommand, which spot-checks that each entry's paths resolve against its
+//! `directory`. Relative paths like `/I..\inc` only resolve from the directory the command ran in,
+//! so when they don't, the command was probably attributed to the wrong directory.
+
+use crate::{CompileCommandsEntry, canonical, paths, read_compile_commands};
+use std::{path::Path, process};
+
+/// Flags that name an include directory, after `canonical::normalize`.
+const INCLUDE_FLAGS: &[&str] = &["/I", "/external:I"];
+
+/// The relative include directories `entry` passes, in order.
+fn relative_includes(entry: &CompileCommandsEntry) -> Vec<String> {
+    canonical::arguments(&entry.command)
+        .into_iter()
+        .filter_map(|argument| {
+            INCLUDE_FLAGS
+                .iter()
+                .find_map(|flag| argument.strip_prefix(flag).map(str::to_string))
+        })
+        .filter(|dir| !dir.is_empty() && !Path::new(dir).is_absolute())
+        .collect()
+}
+
+pub fn audit(output_dir: &str, sample: usize) {
+    let compile_commands_path = paths::absolute(output_dir).join("compile_commands.json");
+    let compile_commands = read_compile_commands(&compile_commands_path);
+
+    let mut flagged = 0;
+    for entry in &compile_commands {
+        let missing_source = !entry.directory.join(&entry.file).is_file();
+        let includes: Vec<String> = relative_includes(entry).into_iter().take(sample).collect();
+        let unresolved: Vec<&String> = includes
+            .iter()
+            .filter(|dir| !entry.directory.join(dir).is_dir())
+            .collect();
+        if !missing_source && unresolved.is_empty() {
+            continue;
+        }
+        flagged += 1;
+        println!("{} (directory {}):", entry.file, entry.directory.display());
+        if missing_source {
+            println!("    the source doesn't exist");
+        }
+        for dir in &unresolved {
+            println!("    include directory {} doesn't resolve", dir);
+        }
+        if unresolved.is_empty() {
+            continue;
+        }
+        // Includes that all resolve from the source's own directory point at the directory the
+        // command really ran in
+        match Path::new(&entry.file).parent() {
+            Some(file_dir) if includes.iter().all(|dir| file_dir.join(dir).is_dir()) => println!(
+                "    probably attributed to the wrong directory; its includes resolve from {}",
+                file_dir.display()
+            ),
+            _ => println!("    probably attributed to the wrong directory"),
+        }
+    }
+
+    println!(
+        "Audited {} entries, {} have paths that don't resolve",
+        compile_commands.len(),
+        flagged
+    );
+    if flagged > 0 {
+        process::exit(1);
+    }
+}
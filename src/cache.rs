@@ -0,0 +1,149 @@
+//! A content-addressed cache of resolved commands, so that repeated runs over mostly-unchanged
+//! logs only resolve the commands that are new.
+//!
+//! The cache is a file of newline-delimited JSON records in the cache directory, each holding the
+//! entries a command resolved to, keyed by a hash of the command, its directory, its compiler and
+//! the tool version. Arguments with unusual extensions are sources only if they exist, so whether
+//! each of them exists is part of the key too, and a record goes unused once one is created or
+//! deleted. New records are appended; records no run has used recently are dropped when
+//! the file has grown to be mostly unused ones. Entries are cached with their secrets redacted, so
+//! the redaction patterns are part of the key as well.
+
+use crate::{CompileCommandsEntry, RawCommand, metadata::VERSION, redact::Redactor};
+use std::{
+    collections::{HashMap, HashSet},
+    fs::{self, File, OpenOptions},
+    hash::{DefaultHasher, Hash, Hasher},
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+/// Don't bother compacting caches smaller than this.
+const MIN_COMPACTION_RECORDS: usize = 1000;
+
+#[derive(serde::Serialize, serde::Deserialize, Clone)]
+struct Record {
+    key: u64,
+    entries: Vec<CompileCommandsEntry>,
+    /// How many secrets were redacted from the entries
+    #[serde(default)]
+    redactions: usize,
+}
+
+pub struct TransformCache {
+    path: PathBuf,
+    redactor: Redactor,
+    records: HashMap<u64, Record>,
+    used: HashSet<u64>,
+    file: Option<File>,
+    hits: usize,
+    misses: usize,
+}
+
+fn key(command: &RawCommand, redactor: &Redactor) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    VERSION.hash(&mut hasher);
+    redactor.key().hash(&mut hasher);
+    command.hash(&mut hasher);
+    for (input, known) in command.inputs() {
+        if !known {
+            command.dir.join(input).is_file().hash(&mut hasher);
+        }
+    }
+    hasher.finish()
+}
+
+impl TransformCache {
+    /// Opens the cache in `dir`, creating the directory if needed, to cache entries redacted by
+    /// `redactor`.
+    pub fn open(dir: &Path, redactor: Redactor) -> TransformCache {
+        fs::create_dir_all(dir)
+            .unwrap_or_else(|_| panic!("Failed to create cache directory {}", dir.display()));
+        let path = dir.join("commands.ndjson");
+        // Records torn by a crash are skipped
+        let records = fs::read_to_string(&path)
+            .unwrap_or_default()
+            .lines()
+            .filter_map(|line| serde_json::from_str::<Record>(line).ok())
+            .map(|record| (record.key, record))
+            .collect();
+        TransformCache {
+            path,
+            redactor,
+            records,
+            used: HashSet::new(),
+            file: None,
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    /// The entries `command` resolves to, from the cache if it's been resolved before, and how
+    /// many secrets were redacted from them.
+    pub fn resolve(&mut self, command: &RawCommand) -> (Vec<CompileCommandsEntry>, usize) {
+        let key = key(command, &self.redactor);
+        self.used.insert(key);
+        if let Some(record) = self.records.get(&key) {
+            self.hits += 1;
+            return (record.entries.clone(), record.redactions);
+        }
+        self.misses += 1;
+        let mut entries: Vec<_> = CompileCommandsEntry::from_raw_command(command).collect();
+        let redactions = entries
+            .iter_mut()
+            .map(|entry| self.redactor.redact_entry(entry))
+            .sum();
+        let record = Record {
+            key,
+            entries,
+            redactions,
+        };
+        self.append(&record);
+        let entries = record.entries.clone();
+        self.records.insert(key, record);
+        (entries, redactions)
+    }
+
+    fn append(&mut self, record: &Record) {
+        let path = &self.path;
+        let file = self.file.get_or_insert_with(|| {
+            OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .unwrap_or_else(|_| panic!("Failed to open cache {}", path.display()))
+        });
+        let mut line = serde_json::to_string(record).expect("Failed to serialize cache record");
+        line.push('\n');
+        file.write_all(line.as_bytes())
+            .unwrap_or_else(|_| panic!("Failed to write cache {}", path.display()));
+    }
+
+    /// Reports how much the cache helped, and compacts it if most of it went unused.
+    pub fn finish(mut self) {
+        println!(
+            "Reused {} cached commands and resolved {} new ones",
+            self.hits, self.misses
+        );
+        drop(self.file.take());
+        if self.records.len() < MIN_COMPACTION_RECORDS || self.records.len() < 2 * self.used.len() {
+            return;
+        }
+
+        let mut contents = String::new();
+        for key in &self.used {
+            let Some(record) = self.records.remove(key) else {
+                continue;
+            };
+            contents.push_str(
+                &serde_json::to_string(&record).expect("Failed to serialize cache record"),
+            );
+            contents.push('\n');
+        }
+        // Replace the cache atomically, so a concurrent run never reads a partial file
+        let temp_path = self.path.with_extension("ndjson.tmp");
+        fs::write(&temp_path, contents)
+            .and_then(|_| fs::rename(&temp_path, &self.path))
+            .unwrap_or_else(|_| panic!("Failed to compact cache {}", self.path.display()));
+    }
+}
@@ -0,0 +1,231 @@
+//! Comparing commands by what they mean rather than how they're written, so that reordered or
+//! repeated flags don't count as changes. Flags that override an earlier one, like `/DX=2` after
+//! `/DX=1` or `/O2` after `/Od`, count only where they last appear.
+
+use crate::{cmdline, compiler::Dialect, paths};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::Path,
+};
+
+/// Flags whose order changes what the compiler does, because earlier ones are searched first.
+const ORDERED_FLAGS: &[&str] = &["I", "AI", "FI", "FU", "external:I"];
+
+/// Flags that take a value where the last one given wins, like `/std:c++17` or `/arch:AVX2`.
+const LAST_VALUE_WINS_FLAGS: &[&str] = &[
+    "std:", "arch:", "fp:", "EH", "favor:", "guard:", "Fo", "Fd", "Fe", "Fp",
+];
+
+/// Groups of flags that override each other, with the last one given winning.
+const EXCLUSIVE_FLAGS: &[&[&str]] = &[
+    &["O1", "O2", "Od", "Ox"],
+    &["W0", "W1", "W2", "W3", "W4", "Wall", "w"],
+    &["MD", "MDd", "MT", "MTd", "LD", "LDd"],
+    &["Z7", "Zi", "ZI"],
+    &["TC", "TP"],
+];
+
+/// Flags naming a path, which is relative to the command's directory unless it's absolute.
+const PATH_FLAGS: &[&str] = &["I", "AI", "FI", "FU", "external:I", "Fo", "Fd", "Fp"];
+
+/// A command in canonical form.
+#[derive(PartialEq, Eq)]
+pub struct Canonical {
+    program: String,
+    /// Search paths and forced includes, in order, without repeats
+    ordered: Vec<String>,
+    /// Every other argument, without repeats, and only the last of those overriding each other
+    unordered: BTreeSet<String>,
+}
+
+/// Normalizes an argument, or a flag with its separate value, so that `-DFOO`, `/DFOO` and
+/// `/D FOO` all become `/DFOO`.
+pub fn normalize(token: &str, value: Option<&str>) -> String {
+    match Dialect::Msvc.flag(token) {
+        Some(flag) => format!("/{}{}", flag, value.unwrap_or_default()),
+        None => token.to_string(),
+    }
+}
+
+/// The arguments of `command` after the program, each normalized with its separate value if it
+/// has one.
+pub fn arguments(command: &str) -> Vec<String> {
+    normalize_all(cmdline::split(command).into_iter().skip(1))
+}
+
+/// Normalizes each of `tokens`, taking separate values along with their flags.
+pub fn normalize_all(mut tokens: impl Iterator<Item = String>) -> Vec<String> {
+    let mut arguments = Vec::new();
+    while let Some(token) = tokens.next() {
+        let value = Dialect::Msvc
+            .flag(&token)
+            .filter(|flag| Dialect::Msvc.takes_separate_value(flag))
+            .and_then(|_| tokens.next());
+        arguments.push(normalize(&token, value.as_deref()));
+    }
+    arguments
+}
+
+/// If the normalized `argument` is a flag naming a path, the flag and the path.
+pub fn path_flag(argument: &str) -> Option<(&str, &str)> {
+    let flag = argument.strip_prefix('/')?;
+    // The longest prefix, so that /FIfoo.h isn't taken for /F
+    let prefix = PATH_FLAGS
+        .iter()
+        .filter(|prefix| flag.starts_with(*prefix))
+        .max_by_key(|prefix| prefix.len())?;
+    let path = &flag[prefix.len()..];
+    (!path.is_empty()).then_some((prefix, path))
+}
+
+/// The normalized `argument` with the path it names, if it's a flag naming one, resolved against
+/// `directory`.
+pub fn resolve_path_flag(argument: String, directory: &Path) -> String {
+    match path_flag(&argument) {
+        Some((flag, path)) => format!(
+            "/{}{}",
+            flag,
+            paths::normalize(&directory.join(path)).display()
+        ),
+        None => argument,
+    }
+}
+
+/// Whether the normalized `argument` is a search path or forced include, whose order matters.
+pub fn is_ordered(argument: &str) -> bool {
+    argument.strip_prefix('/').is_some_and(|flag| {
+        ORDERED_FLAGS
+            .iter()
+            .any(|ordered_flag| flag.starts_with(ordered_flag))
+    })
+}
+
+/// The setting the normalized `argument` makes, if it's a flag, which a later flag making the same
+/// setting overrides: the macro it defines or undefines, the setting it gives a value, the group
+/// of exclusive flags it's in, or else the flag itself, with or without a trailing `-` to turn it
+/// off.
+fn setting(argument: &str) -> Option<String> {
+    let flag = argument.strip_prefix('/')?;
+    if let Some(definition) = flag.strip_prefix('D').or_else(|| flag.strip_prefix('U')) {
+        let name = definition.split(['=', '#']).next().unwrap_or_default();
+        return (!name.is_empty()).then(|| format!("macro {name}"));
+    }
+    if let Some(prefix) = LAST_VALUE_WINS_FLAGS
+        .iter()
+        .find(|prefix| flag.starts_with(*prefix))
+    {
+        return Some(format!("/{prefix}"));
+    }
+    if let Some(group) = EXCLUSIVE_FLAGS.iter().find(|group| group.contains(&flag)) {
+        return Some(format!("/{}", group.join("|")));
+    }
+    Some(format!("/{}", flag.strip_suffix('-').unwrap_or(flag)))
+}
+
+/// Splits normalized `arguments` into the search paths and forced includes, in order and without
+/// repeats, and every other argument, without repeats and with only the last of those making the
+/// same setting.
+pub fn partition(arguments: impl IntoIterator<Item = String>) -> (Vec<String>, BTreeSet<String>) {
+    let mut ordered = Vec::new();
+    let mut unordered = BTreeSet::new();
+    let mut settings = BTreeMap::new();
+    for argument in arguments {
+        if is_ordered(&argument) {
+            if !ordered.contains(&argument) {
+                ordered.push(argument);
+            }
+        } else if let Some(setting) = setting(&argument) {
+            settings.insert(setting, argument);
+        } else {
+            unordered.insert(argument);
+        }
+    }
+    unordered.extend(settings.into_values());
+    (ordered, unordered)
+}
+
+impl Canonical {
+    pub fn new(command: &str) -> Canonical {
+        let program = cmdline::split(command)
+            .into_iter()
+            .next()
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+        let (ordered, unordered) = partition(arguments(command));
+        Canonical {
+            program,
+            ordered,
+            unordered,
+        }
+    }
+
+    /// The arguments in `self` but not `other`, and those in `other` but not `self`.
+    pub fn diff<'a>(&'a self, other: &'a Canonical) -> (Vec<&'a str>, Vec<&'a str>) {
+        let arguments = |c: &'a Canonical| -> BTreeSet<&'a str> {
+            c.ordered
+                .iter()
+                .chain(&c.unordered)
+                .chain([&c.program])
+                .map(String::as_str)
+                .collect()
+        };
+        let (ours, theirs) = (arguments(self), arguments(other));
+        let mut removed: Vec<&str> = ours.difference(&theirs).copied().collect();
+        let mut added: Vec<&str> = theirs.difference(&ours).copied().collect();
+        if removed.is_empty() && added.is_empty() && self.ordered != other.ordered {
+            // Only the order of the search paths changed
+            removed = self.ordered.iter().map(String::as_str).collect();
+            added = other.ordered.iter().map(String::as_str).collect();
+        }
+        (removed, added)
+    }
+}
+
+/// Whether two commands are the same apart from the order of order-insensitive flags and repeats.
+pub fn equivalent(a: &str, b: &str) -> bool {
+    a == b || Canonical::new(a) == Canonical::new(b)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reordered_and_repeated_flags_are_equivalent() {
+        assert!(equivalent(
+            "cl /c /DA /DB /W4 a.cpp",
+            "cl /DB /c /W4 /DA /DA a.cpp"
+        ));
+        assert!(equivalent("cl /O2 /Od /O2 a.cpp", "cl /O2 a.cpp"));
+    }
+
+    #[test]
+    fn search_paths_keep_their_order() {
+        assert!(!equivalent("cl /Ia /Ib a.cpp", "cl /Ib /Ia a.cpp"));
+        assert!(equivalent("cl /Ia /Ib /Ia a.cpp", "cl /Ia /Ib a.cpp"));
+    }
+
+    #[test]
+    fn the_last_of_overriding_flags_wins() {
+        assert!(!equivalent("cl /DX=1 /DX=2 a.cpp", "cl /DX=2 /DX=1 a.cpp"));
+        assert!(!equivalent("cl /DX /UX a.cpp", "cl /UX /DX a.cpp"));
+        assert!(!equivalent("cl /O1 /O2 a.cpp", "cl /O2 /O1 a.cpp"));
+        assert!(!equivalent("cl /W3 /W4 a.cpp", "cl /W4 /W3 a.cpp"));
+        assert!(!equivalent(
+            "cl /std:c++17 /std:c++20 a.cpp",
+            "cl /std:c++20 /std:c++17 a.cpp"
+        ));
+        assert!(!equivalent("cl /GR /GR- a.cpp", "cl /GR- /GR a.cpp"));
+        assert!(equivalent("cl /DX=1 /DX=2 a.cpp", "cl /DX=2 a.cpp"));
+        assert!(equivalent("cl /W3 /DY /W4 a.cpp", "cl /DY /W4 a.cpp"));
+    }
+
+    #[test]
+    fn diff_shows_the_winning_flags() {
+        let ours = Canonical::new("cl /DX=1 /DX=2 a.cpp");
+        let theirs = Canonical::new("cl /DX=2 /DX=1 a.cpp");
+        let (removed, added) = ours.diff(&theirs);
+        assert_eq!(removed, ["/DX=2"]);
+        assert_eq!(added, ["/DX=1"]);
+    }
+}
@@ -0,0 +1,166 @@
+//! Command line tokenization following the same rules as the MSVC CRT and `CommandLineToArgvW`,
+//! so that arguments are split exactly the way cl.exe itself would see them.
+
+/// Whether `s` ends inside a quoted section, following the MSVC rule that a quote preceded by an
+/// odd number of backslashes is a literal quote rather than a delimiter.
+pub fn has_unterminated_quote(s: &str) -> bool {
+    let mut in_quotes = false;
+    let mut backslashes = 0;
+    for c in s.chars() {
+        match c {
+            '\\' => backslashes += 1,
+            '"' => {
+                if backslashes % 2 == 0 {
+                    in_quotes = !in_quotes;
+                }
+                backslashes = 0;
+            }
+            _ => backslashes = 0,
+        }
+    }
+    in_quotes
+}
+
+/// Splits a command line into arguments.
+///
+/// - Arguments are separated by spaces and tabs outside of quotes.
+/// - `2n` backslashes followed by a quote produce `n` backslashes, and the quote toggles quoting.
+/// - `2n + 1` backslashes followed by a quote produce `n` backslashes and a literal quote.
+/// - Backslashes not followed by a quote are literal.
+/// - Inside quotes, `""` produces a literal quote.
+pub fn split(command: &str) -> Vec<String> {
+    args(command).collect()
+}
+
+/// The arguments of a command line, split as by `split`, for callers that only need the first
+/// few.
+pub fn args(command: &str) -> impl Iterator<Item = String> + '_ {
+    let mut chars = command.chars().peekable();
+    std::iter::from_fn(move || {
+        while chars.next_if(|c| *c == ' ' || *c == '\t').is_some() {}
+        chars.peek()?;
+
+        let mut arg = String::new();
+        let mut in_quotes = false;
+        while let Some(c) = chars.next() {
+            match c {
+                ' ' | '\t' if !in_quotes => break,
+                '\\' => {
+                    let mut backslashes = 1;
+                    while chars.next_if_eq(&'\\').is_some() {
+                        backslashes += 1;
+                    }
+                    if chars.peek() == Some(&'"') {
+                        arg.extend(std::iter::repeat_n('\\', backslashes / 2));
+                        if backslashes % 2 == 1 {
+                            chars.next();
+                            arg.push('"');
+                        }
+                    } else {
+                        arg.extend(std::iter::repeat_n('\\', backslashes));
+                    }
+                }
+                '"' => {
+                    if in_quotes && chars.next_if_eq(&'"').is_some() {
+                        arg.push('"');
+                    } else {
+                        in_quotes = !in_quotes;
+                    }
+                }
+                _ => arg.push(c),
+            }
+        }
+        Some(arg)
+    })
+}
+
+/// Quotes `arg`, if it needs it, so that `split` turns it back into the same argument.
+pub fn quote(arg: &str) -> String {
+    if !arg.is_empty() && !arg.contains([' ', '\t', '"']) {
+        return arg.to_string();
+    }
+
+    let mut quoted = String::from('"');
+    let mut backslashes = 0;
+    for c in arg.chars() {
+        match c {
+            '\\' => backslashes += 1,
+            '"' => {
+                // Escape the backslashes before the quote as well as the quote itself
+                quoted.extend(std::iter::repeat_n('\\', backslashes * 2 + 1));
+                quoted.push('"');
+                backslashes = 0;
+            }
+            _ => {
+                quoted.extend(std::iter::repeat_n('\\', backslashes));
+                quoted.push(c);
+                backslashes = 0;
+            }
+        }
+    }
+    // Backslashes before the closing quote must be escaped so they don't escape it
+    quoted.extend(std::iter::repeat_n('\\', backslashes * 2));
+    quoted.push('"');
+    quoted
+}
+
+/// Joins arguments into a command line that `split` splits back into them.
+pub fn join(args: &[impl AsRef<str>]) -> String {
+    args.iter()
+        .map(|arg| quote(arg.as_ref()))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_like_the_crt() {
+        assert_eq!(
+            split(r#"cl /c "C:\Program Files\a b.cpp" /DX=\"y\" a\\\"b "c""d" e\\"#),
+            [
+                "cl",
+                "/c",
+                r"C:\Program Files\a b.cpp",
+                r#"/DX="y""#,
+                r#"a\"b"#,
+                r#"c"d"#,
+                r"e\\",
+            ]
+        );
+    }
+
+    #[test]
+    fn split_and_join_round_trip() {
+        let args = [
+            "cl",
+            "/c",
+            r"C:\Program Files\x.cpp",
+            r"C:\dir with space\",
+            r#"/DQUOTE="x""#,
+            r"\\server\share\a.cpp",
+            r"C:\Users\José (Work)\src\测试.cpp",
+            "",
+            "tab\there",
+        ];
+        assert_eq!(split(&join(&args)), args);
+    }
+
+    #[test]
+    fn quotes_only_what_needs_it() {
+        assert_eq!(quote(r"C:\src\a.cpp"), r"C:\src\a.cpp");
+        assert_eq!(quote(r"C:\a b\"), r#""C:\a b\\""#);
+        assert_eq!(quote(""), r#""""#);
+    }
+
+    #[test]
+    fn finds_unterminated_quotes() {
+        assert!(has_unterminated_quote(r#"cl /I"C:\Program Fi"#));
+        assert!(!has_unterminated_quote(r#"cl /I"C:\Program Files""#));
+        // An escaped quote doesn't open or close a quoted section
+        assert!(!has_unterminated_quote(r#"cl /DX=\"y"#));
+        assert!(has_unterminated_quote(r#"cl "a\\"#));
+    }
+}
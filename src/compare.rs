@@ -0,0 +1,198 @@
+//! The `compare` subcommand, which checks the database against one made another way, such as by
+//! Bear or compiledb intercepting the same build, and reports the files whose entries differ.
+//! Commands are compared by what they mean: flags are normalized, relative paths are resolved
+//! against each entry's directory, paths are compared case-insensitively as they are on Windows,
+//! and the order of flags other than search paths and those overriding each other doesn't
+//! matter.
+
+use crate::{CompileCommandsEntry, canonical, cmdline, paths, read_compile_commands};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::Path,
+    process,
+};
+
+/// How many differing files are listed in full.
+const LISTED: usize = 50;
+
+/// An entry reduced to what it means.
+struct Semantic {
+    directory: String,
+    /// The compiler's name, without its directory or extension
+    compiler: String,
+    /// Search paths and forced includes, in order
+    ordered: Vec<String>,
+    /// Every other argument, with only the last of those overriding each other
+    unordered: BTreeSet<String>,
+}
+
+/// `path` in the form paths are compared in.
+fn comparable(path: &Path) -> String {
+    paths::normalize(path)
+        .to_string_lossy()
+        .replace('\\', "/")
+        .to_lowercase()
+}
+
+/// The path `path` names relative to `directory`, in the form paths are compared in.
+fn comparable_path(path: &str, directory: &Path) -> String {
+    comparable(&directory.join(path))
+}
+
+fn compiler_name(program: &str) -> String {
+    let file_name = program.rsplit(['\\', '/']).next().unwrap_or(program);
+    let file_name = file_name.to_lowercase();
+    match file_name.strip_suffix(".exe") {
+        Some(stem) => stem.to_string(),
+        None => file_name,
+    }
+}
+
+impl Semantic {
+    fn new(entry: &CompileCommandsEntry) -> Semantic {
+        let program = cmdline::split(&entry.command)
+            .into_iter()
+            .next()
+            .unwrap_or_default();
+        let arguments = canonical::arguments(&entry.command)
+            .into_iter()
+            .map(|argument| match canonical::path_flag(&argument) {
+                Some((flag, path)) => {
+                    format!("/{}{}", flag, comparable_path(path, &entry.directory))
+                }
+                None if argument.starts_with('/') => argument,
+                // Sources, and response files
+                None => comparable_path(&argument, &entry.directory),
+            });
+        let (ordered, unordered) = canonical::partition(arguments);
+        Semantic {
+            directory: comparable(&entry.directory),
+            compiler: compiler_name(&program),
+            ordered,
+            unordered,
+        }
+    }
+}
+
+/// The entries at `path`, keyed by their file in the form paths are compared in.
+fn load(path: &Path) -> BTreeMap<String, (CompileCommandsEntry, Semantic)> {
+    if !path.is_file() {
+        panic!("There is no database at {}", path.display());
+    }
+    read_compile_commands(path)
+        .into_iter()
+        .map(|entry| {
+            let semantic = Semantic::new(&entry);
+            (
+                comparable_path(&entry.file, &entry.directory),
+                (entry, semantic),
+            )
+        })
+        .collect()
+}
+
+/// The differences between `ours` and `theirs`, one line each, or none if they mean the same.
+fn differences(ours: &Semantic, theirs: &Semantic) -> Vec<String> {
+    let mut lines = Vec::new();
+    if ours.directory != theirs.directory {
+        lines.push(format!(
+            "directory {} here, {} there",
+            ours.directory, theirs.directory
+        ));
+    }
+    if ours.compiler != theirs.compiler {
+        lines.push(format!(
+            "compiler {} here, {} there",
+            ours.compiler, theirs.compiler
+        ));
+    }
+    let our_args: BTreeSet<&String> = ours.ordered.iter().chain(&ours.unordered).collect();
+    let their_args: BTreeSet<&String> = theirs.ordered.iter().chain(&theirs.unordered).collect();
+    lines.extend(
+        our_args
+            .difference(&their_args)
+            .map(|argument| format!("+ {argument}")),
+    );
+    lines.extend(
+        their_args
+            .difference(&our_args)
+            .map(|argument| format!("- {argument}")),
+    );
+    if our_args == their_args && ours.ordered != theirs.ordered {
+        lines.push(format!(
+            "search paths in a different order: {} here, {} there",
+            ours.ordered.join(" "),
+            theirs.ordered.join(" ")
+        ));
+    }
+    lines
+}
+
+pub fn compare(output_dir: &str, against: &Path) {
+    let compile_commands_path = paths::absolute(output_dir).join("compile_commands.json");
+    let ours = load(&compile_commands_path);
+    let theirs = load(against);
+
+    let only_ours: Vec<&CompileCommandsEntry> = ours
+        .iter()
+        .filter(|(file, _)| !theirs.contains_key(*file))
+        .map(|(_, (entry, _))| entry)
+        .collect();
+    let only_theirs: Vec<&CompileCommandsEntry> = theirs
+        .iter()
+        .filter(|(file, _)| !ours.contains_key(*file))
+        .map(|(_, (entry, _))| entry)
+        .collect();
+    let differing: Vec<(&CompileCommandsEntry, Vec<String>)> = ours
+        .iter()
+        .filter_map(|(file, (entry, semantic))| {
+            let (_, their_semantic) = theirs.get(file)?;
+            let differences = differences(semantic, their_semantic);
+            (!differences.is_empty()).then_some((entry, differences))
+        })
+        .collect();
+    let matching = ours.len() - only_ours.len() - differing.len();
+
+    if !differing.is_empty() {
+        println!(
+            "Entries that differ (+ only here, - only in {}):",
+            against.display()
+        );
+        for (entry, differences) in differing.iter().take(LISTED) {
+            println!("{}", entry.file);
+            for line in differences {
+                println!("    {}", line);
+            }
+        }
+        if differing.len() > LISTED {
+            println!("...and {} more", differing.len() - LISTED);
+        }
+    }
+    for (entries, location) in [
+        (&only_ours, String::from("here")),
+        (&only_theirs, format!("in {}", against.display())),
+    ] {
+        if entries.is_empty() {
+            continue;
+        }
+        println!("Files with an entry only {}:", location);
+        for entry in entries.iter().take(LISTED) {
+            println!("    {}", entry.file);
+        }
+        if entries.len() > LISTED {
+            println!("    ...and {} more", entries.len() - LISTED);
+        }
+    }
+
+    println!(
+        "{} entries match, {} differ, {} are only here and {} are only in {}",
+        matching,
+        differing.len(),
+        only_ours.len(),
+        only_theirs.len(),
+        against.display()
+    );
+    if !differing.is_empty() || !only_ours.is_empty() || !only_theirs.is_empty() {
+        process::exit(1);
+    }
+}
@@ -0,0 +1,271 @@
+//! The compilers whose invocations become entries: cl, clang-cl and nvcc, plus any defined in the
+//! config file.
+//!
+//! ```toml
+//! [[compiler]]
+//! name = "codegen"          # matches codegen or codegen.exe, with or without a directory
+//! regex = "^codegen\\d*$"   # or match the program against a regex instead
+//! extensions = ["gen", "idl"]
+//! dialect = "msvc"          # msvc (default, cl-like flags) or gnu
+//! exclusive = true          # ignore other compilers' invocations naming a .gen or .idl file,
+//!                           # like the host compiler calls a front end makes
+//! intermediates = ["gen_*"] # and ones naming the temporary files the front end passes them
+//! ```
+
+use crate::{SOURCE_EXTENSIONS, cmdline, has_extension, modules::MODULE_FLAGS};
+use regex::Regex;
+use std::{env, path::Path, rc::Rc};
+
+/// Flags whose value may be passed as the following token, e.g. `/I inc` or `/D FOO`.
+const MSVC_FLAGS_WITH_SEPARATE_VALUE: &[&str] = &["D", "U", "I", "FI", "FU", "AI", "external:I"];
+
+/// Flags whose value may be passed as the following token, e.g. `-o foo.o` or `-I inc`.
+const GNU_FLAGS_WITH_SEPARATE_VALUE: &[&str] = &[
+    "o", "D", "U", "I", "include", "isystem", "iquote", "x", "MF", "MT", "MQ",
+];
+
+/// The command line syntax a compiler accepts.
+#[derive(serde::Deserialize, Clone, Copy, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum Dialect {
+    /// cl-like flags, introduced by `/` or `-`
+    #[default]
+    Msvc,
+    /// gcc-like flags, introduced by `-`
+    Gnu,
+}
+
+impl Dialect {
+    /// The flag `token` passes without its prefix, or `None` if it isn't a flag.
+    pub fn flag(self, token: &str) -> Option<&str> {
+        match self {
+            Dialect::Msvc => token.strip_prefix(['/', '-']),
+            Dialect::Gnu => token.strip_prefix('-'),
+        }
+    }
+
+    /// Whether `flag` (without its prefix) takes its value from the following token when it has
+    /// none of its own.
+    pub fn takes_separate_value(self, flag: &str) -> bool {
+        match self {
+            Dialect::Msvc => {
+                MSVC_FLAGS_WITH_SEPARATE_VALUE.contains(&flag) || MODULE_FLAGS.contains(&flag)
+            }
+            Dialect::Gnu => GNU_FLAGS_WITH_SEPARATE_VALUE.contains(&flag),
+        }
+    }
+}
+
+#[derive(serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CompilerConfig {
+    name: String,
+    regex: Option<String>,
+    extensions: Option<Vec<String>>,
+    #[serde(default)]
+    dialect: Dialect,
+    #[serde(default)]
+    exclusive: bool,
+    #[serde(default)]
+    intermediates: Vec<String>,
+}
+
+/// A compiler, and how to recognize its invocations.
+#[derive(Clone)]
+pub struct CompilerDefinition {
+    regex: Option<Regex>,
+    pub compiler: Rc<Compiler>,
+    /// Whether only this compiler's invocations give entries for files with its extensions. Front
+    /// ends like nvcc run the host compiler on the file too, and those calls don't belong in the
+    /// database.
+    exclusive: bool,
+    /// File names of the temporary files the compiler passes to other compilers, whose
+    /// invocations on them don't belong in the database either
+    intermediates: Vec<glob::Pattern>,
+}
+
+#[derive(PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Compiler {
+    pub name: String,
+    extensions: Vec<String>,
+    pub dialect: Dialect,
+}
+
+impl CompilerDefinition {
+    fn builtin(name: &str) -> CompilerDefinition {
+        CompilerDefinition {
+            regex: None,
+            compiler: Rc::new(Compiler {
+                name: name.to_string(),
+                extensions: SOURCE_EXTENSIONS.iter().map(|e| e.to_string()).collect(),
+                dialect: Dialect::Msvc,
+            }),
+            exclusive: false,
+            intermediates: Vec::new(),
+        }
+    }
+
+    /// nvcc, which preprocesses .cu files with the host compiler and then compiles the host code
+    /// it generates, as tmpxft_*.cpp files in the temp directory, with it too.
+    fn nvcc() -> CompilerDefinition {
+        CompilerDefinition {
+            regex: None,
+            compiler: Rc::new(Compiler {
+                name: String::from("nvcc"),
+                extensions: vec![String::from("cu")],
+                dialect: Dialect::Gnu,
+            }),
+            exclusive: true,
+            intermediates: vec![glob::Pattern::new("tmpxft_*").unwrap()],
+        }
+    }
+
+    /// The built-in compilers.
+    pub fn builtins() -> Vec<CompilerDefinition> {
+        vec![
+            CompilerDefinition::builtin("cl"),
+            CompilerDefinition::builtin("clang-cl"),
+            CompilerDefinition::nvcc(),
+        ]
+    }
+
+    pub fn from_config(config: &CompilerConfig) -> CompilerDefinition {
+        CompilerDefinition {
+            regex: config.regex.as_ref().map(|regex| {
+                Regex::new(regex)
+                    .unwrap_or_else(|e| panic!("Invalid regex for compiler {}: {}", config.name, e))
+            }),
+            compiler: Rc::new(Compiler {
+                name: config.name.clone(),
+                extensions: config
+                    .extensions
+                    .clone()
+                    .unwrap_or_else(|| SOURCE_EXTENSIONS.iter().map(|e| e.to_string()).collect()),
+                dialect: config.dialect,
+            }),
+            exclusive: config.exclusive,
+            intermediates: config
+                .intermediates
+                .iter()
+                .map(|pattern| {
+                    glob::Pattern::new(pattern).unwrap_or_else(|e| {
+                        panic!("Invalid intermediate pattern for {}: {}", config.name, e)
+                    })
+                })
+                .collect(),
+        }
+    }
+
+    /// Whether `program`, the first token of a command, invokes this compiler.
+    pub fn matches(&self, program: &str) -> bool {
+        let program = program.trim_matches('"');
+        if let Some(regex) = &self.regex {
+            return regex.is_match(program);
+        }
+        let file_name = program.rsplit(['\\', '/']).next().unwrap_or(program);
+        // The name may end in non-ASCII text, so the split has to land on a char boundary
+        let stem = file_name
+            .len()
+            .checked_sub(4)
+            .filter(|&split| split > 0)
+            .and_then(|split| Some((file_name.get(..split)?, file_name.get(split..)?)))
+            .filter(|(_, extension)| extension.eq_ignore_ascii_case(".exe"))
+            .map_or(file_name, |(stem, _)| stem);
+        stem.eq_ignore_ascii_case(&self.compiler.name)
+    }
+}
+
+/// Whether `compiler`'s invocation `command` was made on behalf of one of the front ends among
+/// `compilers`: it names a file only an exclusive front end compiles, or a front end's
+/// intermediate file.
+pub fn is_front_end_internal(
+    compilers: &[CompilerDefinition],
+    compiler: &Compiler,
+    command: &str,
+) -> bool {
+    let front_ends: Vec<&CompilerDefinition> = compilers
+        .iter()
+        .filter(|definition| *definition.compiler != *compiler)
+        .filter(|definition| definition.exclusive || !definition.intermediates.is_empty())
+        .collect();
+    if front_ends.is_empty() {
+        return false;
+    }
+    cmdline::split(command).iter().skip(1).any(|token| {
+        let file_name = token.rsplit(['\\', '/']).next().unwrap_or(token);
+        front_ends.iter().any(|front_end| {
+            (front_end.exclusive && front_end.compiler.is_source(token))
+                || front_end
+                    .intermediates
+                    .iter()
+                    .any(|pattern| pattern.matches(file_name))
+        })
+    })
+}
+
+impl Compiler {
+    /// Whether the compiler compiles `file` as a source file based on its extension alone.
+    pub fn is_source(&self, file: &str) -> bool {
+        has_extension(file, &self.extensions)
+    }
+}
+
+/// The flag dialect of the program `command` runs, if it's one of the built-in compilers or
+/// `compilers`. Commands translated for clangd, like nvcc's, run clang++, which takes gcc-like
+/// flags like any other program that isn't a known compiler.
+pub fn dialect_of(compilers: &[CompilerDefinition], command: &str) -> Dialect {
+    let Some(program) = cmdline::args(command).next() else {
+        return Dialect::Gnu;
+    };
+    CompilerDefinition::builtins()
+        .iter()
+        .chain(compilers)
+        .find(|definition| definition.matches(&program))
+        .map_or(Dialect::Gnu, |definition| definition.compiler.dialect)
+}
+
+/// Whether `program`, the first token of a command, exists on disk: at its path if it has a
+/// directory, or in a directory on PATH otherwise. A `.exe` extension may be left off.
+pub fn program_exists(program: &str) -> bool {
+    let program = program.trim_matches('"');
+    let exists = |path: &Path| path.is_file() || path.with_extension("exe").is_file();
+    if program.contains(['\\', '/']) {
+        return exists(Path::new(program));
+    }
+    env::var_os("PATH")
+        .is_some_and(|paths| env::split_paths(&paths).any(|dir| exists(&dir.join(program))))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cl() -> CompilerDefinition {
+        CompilerDefinition::builtin("cl")
+    }
+
+    #[test]
+    fn matches_with_or_without_directory_and_extension() {
+        assert!(cl().matches("cl"));
+        assert!(cl().matches("CL.EXE"));
+        assert!(cl().matches(r"C:\VC\bin\cl.exe"));
+        assert!(cl().matches(r#""C:\Program Files\VC\bin\cl.exe""#));
+        assert!(!cl().matches("clang-cl.exe"));
+        assert!(!cl().matches("cl.exe.bak"));
+        assert!(!cl().matches(".exe"));
+    }
+
+    #[test]
+    fn non_ascii_programs_do_not_match() {
+        assert!(!cl().matches("正在编译"));
+        assert!(!cl().matches("é.exe"));
+        assert!(!cl().matches("clé"));
+    }
+
+    #[test]
+    fn dialect_of_commands() {
+        assert!(dialect_of(&[], r"C:\VC\cl.exe /c a.cpp") == Dialect::Msvc);
+        assert!(dialect_of(&[], "nvcc -c k.cu") == Dialect::Gnu);
+        assert!(dialect_of(&[], "clang++ -x cuda -c k.cu") == Dialect::Gnu);
+    }
+}
@@ -0,0 +1,116 @@
+//! Compressing the outputs that grow large on full-tree runs (the metadata sidecar, NDJSON and
+//! reports). compile_commands.json itself is never compressed, since editors read it directly.
+
+use flate2::{Compression as GzipLevel, read::GzDecoder, write::GzEncoder};
+use std::{
+    fs,
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+};
+
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+const ZSTD_MAGIC: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd];
+
+#[derive(clap::ValueEnum, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Compression {
+    /// Written with a .gz extension
+    Gzip,
+    /// Written with a .zst extension, smaller and faster than gzip
+    Zstd,
+}
+
+impl Compression {
+    const ALL: [Compression; 2] = [Compression::Gzip, Compression::Zstd];
+
+    fn extension(self) -> &'static str {
+        match self {
+            Compression::Gzip => "gz",
+            Compression::Zstd => "zst",
+        }
+    }
+}
+
+/// The path the file for `path` is written to with `compression`: `path` with the compression's
+/// extension added, or `path` itself if it isn't compressed.
+pub fn path_with(path: &Path, compression: Option<Compression>) -> PathBuf {
+    match compression {
+        Some(compression) => {
+            let mut name = path.as_os_str().to_owned();
+            name.push(".");
+            name.push(compression.extension());
+            PathBuf::from(name)
+        }
+        None => path.to_path_buf(),
+    }
+}
+
+/// The paths the file for `path` could have been written to, uncompressed first.
+fn variants(path: &Path) -> impl Iterator<Item = PathBuf> {
+    [None]
+        .into_iter()
+        .chain(Compression::ALL.map(Some))
+        .map(move |compression| path_with(path, compression))
+}
+
+/// The file written for `path` with any compression, if there is one.
+pub fn find(path: &Path) -> Option<PathBuf> {
+    variants(path).find(|variant| variant.exists())
+}
+
+/// The compression the file for `path` was written with, going by which file exists.
+pub fn written_with(path: &Path) -> Option<Compression> {
+    Compression::ALL
+        .into_iter()
+        .find(|&compression| path_with(path, Some(compression)).exists())
+}
+
+/// Removes the file written for `path` with any compression, and any links in their place.
+pub fn remove(path: &Path) {
+    for variant in variants(path) {
+        if variant.is_symlink() || variant.exists() {
+            fs::remove_file(&variant)
+                .unwrap_or_else(|_| panic!("Failed to remove {}", variant.display()));
+        }
+    }
+}
+
+/// Writes `contents` for `path` with `compression`, replacing the file written with any other
+/// compression (or a link in its place) so readers don't find a stale one, and returns the path
+/// written.
+pub fn write(path: &Path, contents: &[u8], compression: Option<Compression>) -> PathBuf {
+    remove(path);
+    let written = path_with(path, compression);
+
+    let bytes = match compression {
+        Some(Compression::Gzip) => {
+            let mut encoder = GzEncoder::new(Vec::new(), GzipLevel::default());
+            encoder
+                .write_all(contents)
+                .and_then(|_| encoder.finish())
+                .expect("Failed to compress with gzip")
+        }
+        Some(Compression::Zstd) => {
+            zstd::encode_all(contents, 0).expect("Failed to compress with zstd")
+        }
+        None => contents.to_vec(),
+    };
+    fs::write(&written, bytes).unwrap_or_else(|_| panic!("Failed to write {}", written.display()));
+    written
+}
+
+/// Reads the file written for `path` with any compression, telling which from its contents rather
+/// than its extension.
+pub fn read_to_string(path: &Path) -> io::Result<String> {
+    let path = find(path).ok_or(io::ErrorKind::NotFound)?;
+    let bytes = fs::read(&path)?;
+    let mut contents = String::new();
+    if bytes.starts_with(GZIP_MAGIC) {
+        GzDecoder::new(bytes.as_slice()).read_to_string(&mut contents)?;
+    } else if bytes.starts_with(ZSTD_MAGIC) {
+        zstd::Decoder::new(bytes.as_slice())?.read_to_string(&mut contents)?;
+    } else {
+        contents =
+            String::from_utf8(bytes).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    }
+    Ok(contents)
+}
@@ -0,0 +1,186 @@
+//! The `.buildexe2cc.toml` config file.
+//!
+//! ```toml
+//! [[project]]
+//! name = "kernel"
+//! log = "src/kernel/buildfre*.log"
+//! output = "src/kernel"
+//! exclude = ["**/unittest/**"]
+//! ```
+//!
+//! Relative paths are relative to the directory containing the config file. See
+//! [`crate::compiler`] for `[[compiler]]` tables, [`crate::launcher`] for `launchers`,
+//! [`crate::objroot`] for `[[objroot]]` tables, [`crate::prefix`] for `thread_prefix`,
+//! [`crate::redact`] for `redact`, and [`crate::scratch`] for `scratch`.
+//!
+//! A `.ccdbignore` file next to the config file lists more globs of source files that shouldn't
+//! get entries, one per line, for every project and for single logs too.
+
+use crate::{
+    compiler::{CompilerConfig, CompilerDefinition},
+    environment::Environment,
+    launcher::Launchers,
+    objroot::{ObjRoot, ObjRootConfig},
+    paths,
+    prefix::ThreadPrefix,
+    redact::Redactor,
+    scratch::Scratch,
+};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+pub const DEFAULT_PATH: &str = ".buildexe2cc.toml";
+
+/// The name of the ignore file, which lives next to the config file.
+pub const IGNORE_PATH: &str = ".ccdbignore";
+
+#[derive(serde::Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// Programs that wrap the compiler, in addition to the built-in ones
+    #[serde(default)]
+    launchers: Vec<String>,
+    /// A regex for the prefix naming the thread that wrote each line, if it isn't build.exe's
+    thread_prefix: Option<String>,
+    /// Patterns for secrets to redact from commands, in addition to the built-in ones
+    #[serde(default)]
+    redact: Vec<String>,
+    /// Globs of scratch sources, in addition to the built-in ones
+    #[serde(default)]
+    scratch: Vec<String>,
+    #[serde(default, rename = "project")]
+    pub projects: Vec<Project>,
+    #[serde(default, rename = "compiler")]
+    compilers: Vec<CompilerConfig>,
+    #[serde(default, rename = "objroot")]
+    objroots: Vec<ObjRootConfig>,
+}
+
+impl Config {
+    /// The compilers defined in addition to the built-in ones.
+    pub fn compilers(&self) -> Vec<CompilerDefinition> {
+        self.compilers
+            .iter()
+            .map(CompilerDefinition::from_config)
+            .collect()
+    }
+
+    /// The built-in launchers and the ones defined in the config.
+    pub fn launchers(&self) -> Launchers {
+        Launchers::new(&self.launchers)
+    }
+
+    /// The rules inferring directories from object directories, in the order they're tried.
+    pub fn objroots(&self) -> Vec<ObjRoot> {
+        self.objroots.iter().map(ObjRoot::from_config).collect()
+    }
+
+    /// The built-in patterns for secrets to redact and the ones in the config.
+    pub fn redactor(&self) -> Redactor {
+        Redactor::new(&self.redact)
+    }
+
+    /// The built-in globs of scratch sources, the build's temp directories in `variables` and the
+    /// globs in the config.
+    pub fn scratch(&self, variables: &Environment) -> Scratch {
+        Scratch::new(&self.scratch, variables)
+    }
+
+    /// The format of the thread prefix on each line of the logs.
+    pub fn thread_prefix(&self) -> ThreadPrefix {
+        self.thread_prefix
+            .as_deref()
+            .map_or_else(ThreadPrefix::default, ThreadPrefix::new)
+    }
+}
+
+#[derive(serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Project {
+    name: Option<String>,
+    /// Glob matching the project's build.exe logs
+    log: String,
+    /// Directory where the project's compile_commands.json should be output or updated
+    pub output: PathBuf,
+    /// Globs matching source files that shouldn't get entries
+    #[serde(default)]
+    exclude: Vec<String>,
+}
+
+impl Project {
+    pub fn name(&self) -> &str {
+        self.name.as_deref().unwrap_or(&self.log)
+    }
+
+    /// The logs matching the project's log glob, in sorted order.
+    pub fn log_paths(&self) -> Vec<PathBuf> {
+        let mut paths: Vec<PathBuf> = glob::glob(&self.log)
+            .unwrap_or_else(|_| panic!("Invalid log glob {}", self.log))
+            .map(|path| path.unwrap_or_else(|e| panic!("Failed to read {}", e.path().display())))
+            .collect();
+        if paths.is_empty() {
+            panic!("No logs match {}", self.log);
+        }
+        paths.sort();
+        paths
+    }
+
+    pub fn exclude_patterns(&self) -> Vec<glob::Pattern> {
+        self.exclude
+            .iter()
+            .map(|pattern| exclude_pattern(pattern))
+            .collect()
+    }
+}
+
+fn exclude_pattern(pattern: &str) -> glob::Pattern {
+    glob::Pattern::new(pattern).unwrap_or_else(|_| panic!("Invalid exclude pattern {}", pattern))
+}
+
+/// The patterns in the ignore file next to the config at `config_path`, if there is one.
+pub fn ignore_patterns(config_path: &str) -> Vec<glob::Pattern> {
+    let root = config_root(config_path);
+    let ignore_path = root.join(IGNORE_PATH);
+    let Ok(contents) = fs::read_to_string(&ignore_path) else {
+        return Vec::new();
+    };
+    contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| exclude_pattern(&root.join(line).to_string_lossy()))
+        .collect()
+}
+
+/// The directory containing the config at `path`, which relative paths in it are relative to.
+fn config_root(path: &str) -> PathBuf {
+    paths::absolute(path)
+        .parent()
+        .map(Path::to_path_buf)
+        .unwrap_or_default()
+}
+
+/// Loads the config at `path`, or the default config if there is no file there.
+pub fn load_optional(path: &str) -> Config {
+    if Path::new(path).exists() {
+        load(path)
+    } else {
+        Config::default()
+    }
+}
+
+pub fn load(path: &str) -> Config {
+    let contents =
+        fs::read_to_string(path).unwrap_or_else(|_| panic!("Failed to read config from {}", path));
+    let mut config: Config = toml::from_str(&contents)
+        .unwrap_or_else(|e| panic!("Failed to parse config from {}: {}", path, e));
+
+    let root = config_root(path);
+    for project in &mut config.projects {
+        project.output = root.join(&project.output);
+        project.log = root.join(&project.log).to_string_lossy().to_string();
+    }
+    config
+}
@@ -0,0 +1,133 @@
+//! Translating nvcc commands into clang's CUDA syntax. clangd and other clang-based tools don't
+//! understand nvcc's own flags (like `-arch` or `-Xcompiler`), but clang compiles CUDA itself, so
+//! an nvcc command becomes a clang++ command with the include paths, defines and GPU architecture
+//! it names, and nvcc's other flags dropped.
+
+use crate::{CompileCommandsEntry, cmdline};
+
+/// nvcc flags whose value may be passed as the following token.
+const FLAGS_WITH_SEPARATE_VALUE: &[&str] = &[
+    "-I",
+    "-D",
+    "-U",
+    "-o",
+    "-x",
+    "-std",
+    "-isystem",
+    "-include",
+    "-arch",
+    "-code",
+    "-gencode",
+    "-ccbin",
+    "-odir",
+    "-maxrregcount",
+    "-Xcompiler",
+    "-Xlinker",
+    "-Xptxas",
+    "-Xcudafe",
+    "-Xnvlink",
+    "-Xarchive",
+];
+
+/// nvcc's long options, with the short ones they're the same as.
+const LONG_OPTIONS: &[(&str, &str)] = &[
+    ("--include-path", "-I"),
+    ("--define-macro", "-D"),
+    ("--undefine-macro", "-U"),
+    ("--output-file", "-o"),
+    ("--x", "-x"),
+    ("--std", "-std"),
+    ("--system-include", "-isystem"),
+    ("--pre-include", "-include"),
+    ("--gpu-architecture", "-arch"),
+    ("--gpu-code", "-code"),
+    ("--generate-code", "-gencode"),
+    ("--compiler-bindir", "-ccbin"),
+    ("--output-directory", "-odir"),
+    ("--maxrregcount", "-maxrregcount"),
+    ("--compiler-options", "-Xcompiler"),
+    ("--linker-options", "-Xlinker"),
+    ("--ptxas-options", "-Xptxas"),
+    ("--compile", "-c"),
+];
+
+/// Whether `command` runs nvcc.
+pub fn is_nvcc(command: &str) -> bool {
+    cmdline::split(command).first().is_some_and(|program| {
+        let file_name = program.rsplit(['\\', '/']).next().unwrap_or(program);
+        file_name.eq_ignore_ascii_case("nvcc") || file_name.eq_ignore_ascii_case("nvcc.exe")
+    })
+}
+
+/// The GPU architecture a `-arch`, `-code` or `-gencode` value names, as clang spells it
+/// (`sm_70`, where nvcc also accepts `compute_70`).
+fn gpu_arch(value: &str) -> Option<String> {
+    let arch = value
+        .split(',')
+        .map(|part| part.rsplit('=').next().unwrap_or(part))
+        .find(|arch| arch.starts_with("sm_") || arch.starts_with("compute_"))?;
+    Some(arch.replacen("compute_", "sm_", 1))
+}
+
+/// Translates `entry`'s nvcc command into a clang++ one.
+pub fn translate(entry: &mut CompileCommandsEntry) {
+    let mut tokens = cmdline::split(&entry.command).into_iter().skip(1);
+    let mut translated: Vec<String> = ["clang++", "-x", "cuda"].map(String::from).to_vec();
+    let mut arches = Vec::new();
+    while let Some(token) = tokens.next() {
+        if !token.starts_with('-') {
+            translated.push(token);
+            continue;
+        }
+        let (flag, value) = match token.split_once('=') {
+            Some((flag, value)) => (flag, Some(value.to_string())),
+            None => (token.as_str(), None),
+        };
+        let flag = LONG_OPTIONS
+            .iter()
+            .find(|(long, _)| *long == flag)
+            .map_or(flag, |(_, short)| short);
+        // -I, -D and -U may have their value attached
+        if let Some(short) = ["-I", "-D", "-U"]
+            .into_iter()
+            .find(|short| flag.len() > 2 && flag.starts_with(short))
+        {
+            translated.push(format!("{}{}", short, &token[2..]));
+            continue;
+        }
+        let value = match value {
+            Some(value) => Some(value),
+            None if FLAGS_WITH_SEPARATE_VALUE.contains(&flag) => tokens.next(),
+            None => None,
+        };
+        match (flag, value) {
+            ("-c", _) => translated.push(String::from("-c")),
+            ("-I" | "-D" | "-U", Some(value)) => translated.push(format!("{flag}{value}")),
+            ("-o" | "-isystem" | "-include", Some(value)) => {
+                translated.extend([flag.to_string(), value])
+            }
+            ("-std", Some(value)) => translated.push(format!("-std={value}")),
+            ("-arch" | "-code" | "-gencode", Some(value)) => arches.extend(gpu_arch(&value)),
+            // The host compiler's defines still apply to the host side of the code
+            ("-Xcompiler", Some(value)) => translated.extend(
+                value
+                    .split([',', ' '])
+                    .filter_map(|option| option.strip_prefix(['/', '-']))
+                    .filter(|option| option.starts_with('D') && option.len() > 1)
+                    .map(|define| format!("-{define}")),
+            ),
+            // nvcc's other flags have no clang equivalent
+            _ => {}
+        }
+    }
+    arches.sort();
+    arches.dedup();
+    // After `-x cuda`, ahead of the inputs
+    translated.splice(
+        3..3,
+        arches
+            .into_iter()
+            .map(|arch| format!("--cuda-gpu-arch={arch}")),
+    );
+    entry.command = cmdline::join(&translated);
+}
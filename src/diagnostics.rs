@@ -0,0 +1,55 @@
+//! Reading build.exe's warning and error logs (such as buildfre.wrn and buildfre.err), which list
+//! the compiler diagnostics produced during the build.
+
+use crate::paths;
+use regex::Regex;
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+#[derive(Default, Clone, Copy)]
+pub struct DiagnosticCounts {
+    pub warnings: usize,
+    pub errors: usize,
+}
+
+/// The warning and error logs build.exe writes next to `log_path`.
+pub fn logs_for(log_path: &Path) -> impl Iterator<Item = PathBuf> {
+    ["wrn", "err"]
+        .into_iter()
+        .map(|extension| log_path.with_extension(extension))
+        .filter(|path| path.exists())
+}
+
+/// Counts the diagnostics in a warning or error log by the absolute path of the file they're in.
+pub fn read(path: &Path, counts: &mut HashMap<String, DiagnosticCounts>) {
+    let log = fs::read_to_string(path)
+        .unwrap_or_else(|_| panic!("Failed to read diagnostics from {}", path.display()));
+
+    // Diagnostics are grouped under the directory they were produced in, and may name files
+    // relative to it
+    let dir_re = Regex::new(r"^(?:\d+>)?(?:errors|warnings) in directory (.+)$").unwrap();
+    let diagnostic_re = Regex::new(
+        r"^(?:\d+>)?\s*(.+?)\(\d+(?:,\d+)?\)\s*:\s*(?:fatal )?(error|warning) [A-Z]+\d+",
+    )
+    .unwrap();
+
+    let mut dir = PathBuf::new();
+    for line in log.lines() {
+        if let Some(caps) = dir_re.captures(line) {
+            dir = PathBuf::from(caps[1].trim());
+        } else if let Some(caps) = diagnostic_re.captures(line) {
+            let file = paths::absolute(dir.join(&caps[1]));
+            let count = counts
+                .entry(file.to_string_lossy().to_string())
+                .or_default();
+            if &caps[2] == "error" {
+                count.errors += 1;
+            } else {
+                count.warnings += 1;
+            }
+        }
+    }
+}
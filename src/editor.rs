@@ -0,0 +1,122 @@
+//! Editor configuration derived from the database, for teams that use the VS Code C/C++
+//! extension or Visual Studio's Open Folder mode instead of clangd.
+
+use crate::{CompileCommandsEntry, canonical, paths};
+use serde_json::{Value, json};
+use std::{
+    collections::{HashMap, HashSet},
+    env, fs,
+    path::Path,
+};
+
+#[derive(clap::ValueEnum, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum EditorConfig {
+    /// .vscode/c_cpp_properties.json, pointing the C/C++ extension at the database
+    Vscode,
+    /// CppProperties.json, with the include paths and defines most entries share
+    Vs,
+}
+
+/// Replaces the configuration named `name` in the `configurations` array of the JSON file at
+/// `path`, or adds it, keeping the rest of the file (and any other configurations) as it is.
+fn update_configuration(path: &Path, name: &str, configuration: Value, defaults: Value) {
+    let mut file: Value = match fs::read_to_string(path) {
+        Ok(json) => serde_json::from_str(&json)
+            .unwrap_or_else(|_| panic!("Failed to parse {}", path.display())),
+        Err(_) => defaults,
+    };
+    let configurations = file
+        .as_object_mut()
+        .unwrap_or_else(|| panic!("Expected an object in {}", path.display()))
+        .entry("configurations")
+        .or_insert_with(|| json!([]))
+        .as_array_mut()
+        .unwrap_or_else(|| {
+            panic!(
+                "Expected configurations to be an array in {}",
+                path.display()
+            )
+        });
+    match configurations
+        .iter_mut()
+        .find(|existing| existing["name"] == name)
+    {
+        Some(existing) => *existing = configuration,
+        None => configurations.push(configuration),
+    }
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .unwrap_or_else(|_| panic!("Failed to create directory {}", parent.display()));
+    }
+    let json = serde_json::to_string_pretty(&file).expect("Failed to serialize editor config");
+    fs::write(path, json).unwrap_or_else(|_| panic!("Failed to write {}", path.display()));
+    println!("Wrote the {} configuration to {}", name, path.display());
+}
+
+/// The values of `flag` (like `/I`) that at least `threshold` of `entries` pass, in the order
+/// they first appear, with include directories made absolute.
+fn common_values(entries: &[CompileCommandsEntry], flag: &str, threshold: usize) -> Vec<String> {
+    let mut counts: HashMap<String, usize> = HashMap::new();
+    let mut order = Vec::new();
+    for entry in entries {
+        let mut seen = HashSet::new();
+        let values: Vec<String> = canonical::arguments(&entry.command)
+            .into_iter()
+            .filter_map(|argument| argument.strip_prefix(flag).map(str::to_string))
+            .filter(|value| !value.is_empty())
+            .map(|value| match flag {
+                "/I" => paths::absolute(entry.directory.join(&value))
+                    .to_string_lossy()
+                    .to_string(),
+                _ => value,
+            })
+            .filter(|value| seen.insert(value.clone()))
+            .collect();
+        for value in values {
+            let count = counts.entry(value.clone()).or_default();
+            if *count == 0 {
+                order.push(value);
+            }
+            *count += 1;
+        }
+    }
+    order.retain(|value| counts[value] >= threshold);
+    order
+}
+
+/// Writes the editor configuration of `kind` for the database at `database_path` into the
+/// current directory, the enlistment root editors open.
+pub fn emit(kind: EditorConfig, database_path: &Path, entries: &[CompileCommandsEntry]) {
+    let root = env::current_dir().expect("Failed to get the current directory");
+    let name = database_path
+        .strip_prefix(&root)
+        .unwrap_or(database_path)
+        .to_string_lossy()
+        .replace('\\', "/");
+    match kind {
+        EditorConfig::Vscode => update_configuration(
+            &root.join(".vscode").join("c_cpp_properties.json"),
+            &name,
+            json!({
+                "name": name,
+                "compileCommands": database_path,
+            }),
+            json!({ "version": 4 }),
+        ),
+        EditorConfig::Vs => update_configuration(
+            &root.join("CppProperties.json"),
+            &name,
+            json!({
+                "name": name,
+                "inheritEnvironments": ["msvc_x64"],
+                "intelliSenseMode": "windows-msvc-x64",
+                // Every include directory any entry uses, since a missing one breaks IntelliSense
+                // more than an extra one does, but only the defines most entries agree on
+                "includePath": common_values(entries, "/I", 1),
+                "defines": common_values(entries, "/D", entries.len().div_ceil(2)),
+            }),
+            json!({}),
+        ),
+    }
+}
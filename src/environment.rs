@@ -0,0 +1,81 @@
+//! Capturing the environment compiles ran with, for the nonstandard `environment` field that
+//! `--emit-env` adds to entries, for resolving flags that name variables, and for the flags
+//! cl.exe takes from the `CL` and `_CL_` variables, which the log doesn't show.
+
+use crate::{CompileCommandsEntry, cmdline, compiler::CompilerDefinition, compiler::Dialect};
+use std::{collections::BTreeMap, env, fs, path::Path};
+
+/// The variables that change how cl.exe compiles, and so are captured.
+const VARIABLES: &[&str] = &["INCLUDE", "LIB", "LIBPATH", "CL", "_CL_"];
+
+pub type Environment = BTreeMap<String, String>;
+
+/// Reads every variable from a dump of `set` (or `env`) output saved in the build window, or
+/// from this process's environment if there's no dump, such as when run from the Razzle window
+/// that did the build.
+pub fn read(env_file: Option<&Path>) -> Environment {
+    match env_file {
+        Some(path) => fs::read_to_string(path)
+            .unwrap_or_else(|_| panic!("Failed to read environment from {}", path.display()))
+            .lines()
+            .filter_map(|line| line.split_once('='))
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect(),
+        None => env::vars().collect(),
+    }
+}
+
+/// The value of `name` in `variables`. Variable names aren't case sensitive on Windows.
+pub fn lookup<'a>(variables: &'a Environment, name: &str) -> Option<&'a str> {
+    variables
+        .iter()
+        .find(|(variable, _)| variable.eq_ignore_ascii_case(name))
+        .map(|(_, value)| value.as_str())
+}
+
+/// Captures the variables that change how cl.exe compiles from `variables`.
+pub fn capture(variables: &Environment) -> Environment {
+    VARIABLES
+        .iter()
+        .filter_map(|name| Some((name.to_string(), lookup(variables, name)?.to_string())))
+        .collect()
+}
+
+/// The flags in the variable `name`. The variables can't contain `=`, so cl.exe takes `#` in its
+/// place.
+fn injected_flags(variables: &Environment, name: &str) -> Vec<String> {
+    lookup(variables, name)
+        .map(|value| cmdline::split(&value.replace('#', "=")))
+        .unwrap_or_default()
+}
+
+/// Adds the flags from `CL` and `_CL_` to `entry`'s command if it runs an MSVC-style compiler,
+/// built in or among `extra_compilers`, where cl.exe adds them: `CL`'s ahead of the command's own
+/// arguments, and `_CL_`'s after them.
+pub fn inject_cl(
+    entry: &mut CompileCommandsEntry,
+    variables: &Environment,
+    extra_compilers: &[CompilerDefinition],
+) {
+    let prepended = injected_flags(variables, "CL");
+    let appended = injected_flags(variables, "_CL_");
+    if prepended.is_empty() && appended.is_empty() {
+        return;
+    }
+    let mut tokens = cmdline::split(&entry.command);
+    let Some(program) = tokens.first() else {
+        return;
+    };
+    if !CompilerDefinition::builtins()
+        .iter()
+        .chain(extra_compilers)
+        .any(|definition| {
+            definition.compiler.dialect == Dialect::Msvc && definition.matches(program)
+        })
+    {
+        return;
+    }
+    tokens.splice(1..1, prepended);
+    tokens.extend(appended);
+    entry.command = cmdline::join(&tokens);
+}
@@ -0,0 +1,214 @@
+//! The `explain` subcommand, which answers "why does (or doesn't) this file have an entry?" by
+//! re-parsing the log and reporting every invocation that mentions the file, and then which of
+//! generation's filters and pruning, if any, would drop the entry the log produces.
+
+use crate::{
+    Filter, GenerateOptions, LoggedCommand, RawCommand, filtered_by, is_generated, logged_commands,
+    logio,
+    metadata::{self, Metadata},
+    paths, reroot, store,
+};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+/// How an invocation relates to the file being explained.
+enum Match {
+    /// The invocation compiles the file
+    Compiles,
+    /// The invocation compiles a file with the same name in a different directory
+    SameName(PathBuf),
+    /// The invocation names the file, but its thread's directory wasn't known
+    Unresolved,
+}
+
+/// Why generating with `options` from the log at `log_path` leaves the entry it produces for
+/// `target` out of the database at `database_path`, if it does.
+fn dropped_by(
+    target: &Path,
+    log_path: &Path,
+    database_path: &Path,
+    options: &GenerateOptions,
+) -> Option<String> {
+    let file = target.to_string_lossy();
+    match filtered_by(&file, options) {
+        Some(Filter::Excluded) => {
+            return Some(String::from(
+                "it matches an exclude pattern of the config's project or .ccdbignore",
+            ));
+        }
+        Some(Filter::Scratch) => {
+            return Some(String::from("it's a scratch source in a temp directory"));
+        }
+        Some(Filter::OutsideOnly) => {
+            let only = options.only.as_deref().unwrap_or(Path::new(""));
+            return Some(format!("it's outside --only {}", only.display()));
+        }
+        None => {}
+    }
+    // A chunked log was last written when its last chunk was
+    let last_chunk = logio::chunks(log_path)
+        .pop()
+        .unwrap_or(log_path.to_path_buf());
+    if let Some(age) = options.prune_older_than
+        && metadata::log_timestamp(&last_chunk) < metadata::now().saturating_sub(age.as_secs())
+    {
+        return Some(format!(
+            "--prune-older-than prunes it, since the log is older than {}",
+            metadata::format_age(age)
+        ));
+    }
+    if let Some(grace_runs) = options.prune_missing
+        && !target.is_file()
+    {
+        let missing_runs = Metadata::load(&metadata::path_for(database_path))
+            .entries
+            .get(file.as_ref())
+            .map_or(0, |entry| entry.missing_runs);
+        if !is_generated(target) || missing_runs + 1 > grace_runs {
+            return Some(String::from(
+                "--prune-missing prunes it, since the file doesn't exist",
+            ));
+        }
+        println!(
+            "The file doesn't exist, but --prune-missing keeps it for now, since it's a generated source"
+        );
+    }
+    None
+}
+
+pub fn explain(file: &str, log_path: &str, output_dir: &str, options: &GenerateOptions) {
+    let target = paths::absolute(file);
+    let target_name = target.file_name();
+    let target_key = options.key_case.key(&target.to_string_lossy());
+
+    let mut seen_commands: HashMap<RawCommand, usize> = HashMap::new();
+    // The pass and line number of the invocation that will end up as the file's entry
+    let mut winner: Option<(u32, usize)> = None;
+    let mut mentioned = false;
+    for LoggedCommand {
+        compiler,
+        thread,
+        line_number,
+        pass,
+        dir,
+        launcher,
+        lines,
+    } in logged_commands(Path::new(log_path), options)
+    {
+        let resolved = dir.is_some();
+        let command = RawCommand {
+            dir: dir.unwrap_or_default(),
+            lines,
+            compiler,
+        };
+        let matches: Vec<Match> = command
+            .source_files()
+            .into_iter()
+            .filter_map(|source_file| {
+                if !resolved {
+                    return target.ends_with(&source_file).then_some(Match::Unresolved);
+                }
+                let absolute = reroot(&paths::absolute(command.dir.join(&source_file)), options);
+                if options.key_case.key(&absolute.to_string_lossy()) == target_key {
+                    Some(Match::Compiles)
+                } else if absolute.file_name() == target_name {
+                    Some(Match::SameName(absolute))
+                } else {
+                    None
+                }
+            })
+            .collect();
+
+        for m in &matches {
+            mentioned = true;
+            print!("line {}, thread {}, pass {}: ", line_number, thread, pass);
+            match m {
+                Match::Compiles => {
+                    print!("compiled in {}", command.dir.display());
+                    if let Some(launcher) = &launcher {
+                        print!(" through {}", launcher);
+                    }
+                    if let Some(first) = seen_commands.get(&command) {
+                        println!(
+                            ", dropped as a duplicate of the identical command on line {first}"
+                        );
+                    } else if let Some((previous_pass, previous)) = winner
+                        && previous_pass > pass
+                    {
+                        println!(
+                            ", ignored because the command on line {previous} is from a later pass"
+                        );
+                    } else if let Some((_, previous)) = winner.replace((pass, line_number)) {
+                        println!(", replacing the command on line {previous}");
+                    } else {
+                        println!();
+                    }
+                }
+                Match::SameName(other) => {
+                    println!(
+                        "compiled a file with the same name as {} (did this thread's directory get misattributed?)",
+                        other.display()
+                    );
+                }
+                Match::Unresolved => {
+                    println!(
+                        "named in a command, but no directory banner had been seen for this thread, so its directory couldn't be determined"
+                    );
+                }
+            }
+        }
+
+        if resolved {
+            seen_commands.entry(command).or_insert(line_number);
+        }
+    }
+
+    if !mentioned {
+        println!(
+            "{} does not appear in any cl invocation in the log",
+            target.display()
+        );
+    }
+    let compile_commands_path = paths::absolute(output_dir).join("compile_commands.json");
+    match winner {
+        Some((_, line_number)) => match dropped_by(
+            &target,
+            Path::new(log_path),
+            &compile_commands_path,
+            options,
+        ) {
+            Some(reason) => println!(
+                "The command on line {} would be the entry for {}, but it's dropped because {}",
+                line_number,
+                target.display(),
+                reason
+            ),
+            None => println!(
+                "The entry for {} comes from the command on line {}",
+                target.display(),
+                line_number
+            ),
+        },
+        None => println!("The log does not produce an entry for {}", target.display()),
+    }
+
+    if store::find_entry(
+        &compile_commands_path,
+        &target.to_string_lossy(),
+        options.key_case,
+    )
+    .is_some()
+    {
+        println!(
+            "{} currently has an entry for it",
+            compile_commands_path.display()
+        );
+    } else {
+        println!(
+            "{} currently has no entry for it",
+            compile_commands_path.display()
+        );
+    }
+}
@@ -0,0 +1,80 @@
+//! External headers, whose include roots newer toolsets pass as `/external:I <dir>`, or as
+//! `/external:env:<variable>` naming a variable that lists them (like `/external:env:INCLUDE`).
+
+use crate::{
+    CompileCommandsEntry, cmdline,
+    environment::{self, Environment},
+};
+
+/// The variable an `/external:env:<variable>` token names.
+fn env_variable(token: &str) -> Option<&str> {
+    token
+        .strip_prefix(['/', '-'])?
+        .strip_prefix("external:env:")
+        .filter(|variable| !variable.is_empty())
+}
+
+/// Replaces each `/external:env:<variable>` with an `/external:I` for every directory the
+/// variable lists in `variables`, so the command no longer depends on the environment. Returns
+/// the variables that aren't set, whose flags are left as they are.
+pub fn resolve_env(entry: &mut CompileCommandsEntry, variables: &Environment) -> Vec<String> {
+    let tokens = cmdline::split(&entry.command);
+    if !tokens.iter().any(|token| env_variable(token).is_some()) {
+        return Vec::new();
+    }
+    let mut unset = Vec::new();
+    let mut resolved = Vec::new();
+    for token in tokens {
+        let Some(variable) = env_variable(&token) else {
+            resolved.push(token);
+            continue;
+        };
+        match environment::lookup(variables, variable) {
+            Some(value) => {
+                for dir in value.split(';').filter(|dir| !dir.is_empty()) {
+                    resolved.push("/external:I".to_string());
+                    resolved.push(dir.to_string());
+                }
+            }
+            None => {
+                unset.push(variable.to_string());
+                resolved.push(token);
+            }
+        }
+    }
+    entry.command = cmdline::join(&resolved);
+    unset
+}
+
+/// Rewrites `/external:I` as `-isystem`, which clangd understands, so that warnings in external
+/// headers are suppressed the same way they are when building.
+pub fn to_isystem(entry: &mut CompileCommandsEntry) {
+    let external_dir = |token: &str| -> Option<String> {
+        Some(
+            token
+                .strip_prefix(['/', '-'])?
+                .strip_prefix("external:I")?
+                .to_string(),
+        )
+    };
+    let tokens = cmdline::split(&entry.command);
+    if !tokens.iter().any(|token| external_dir(token).is_some()) {
+        return;
+    }
+    let mut converted = Vec::new();
+    let mut tokens = tokens.into_iter();
+    while let Some(token) = tokens.next() {
+        match external_dir(&token) {
+            Some(dir) => {
+                converted.push("-isystem".to_string());
+                if dir.is_empty() {
+                    converted.extend(tokens.next());
+                } else {
+                    converted.push(dir);
+                }
+            }
+            None => converted.push(token),
+        }
+    }
+    entry.command = cmdline::join(&converted);
+}
@@ -0,0 +1,97 @@
+//! The `compile-flags` subcommand, which writes a fixed compilation database: a compile_flags.txt
+//! listing the flags every entry under a directory shares, which clang tools apply to every file
+//! under it. Flags that only some of the entries pass are dropped, since they'd be wrong for the
+//! rest, and so are the ones naming each entry's own outputs.
+
+use crate::{
+    CompileCommandsEntry, canonical, is_under, key::KeyCase, paths, read_compile_commands,
+};
+use std::{collections::HashMap, fs, path::Path};
+
+/// What the fixed database is called.
+const FILE_NAME: &str = "compile_flags.txt";
+
+/// Flags naming one entry's outputs or precompiled header, which never belong to every file.
+const PER_FILE_FLAGS: &[&str] = &["Fo", "Fd", "Fa", "Fe", "Fp", "Fm", "Fr", "FR", "Yc", "Yu"];
+
+/// The flags `entry` passes, normalized, with relative paths made absolute.
+fn flags(entry: &CompileCommandsEntry) -> Vec<String> {
+    let mut flags = Vec::new();
+    for argument in canonical::arguments(&entry.command) {
+        let Some(flag) = argument.strip_prefix('/') else {
+            // Source files, and response files the database doesn't include
+            continue;
+        };
+        if PER_FILE_FLAGS.iter().any(|prefix| flag.starts_with(prefix)) {
+            continue;
+        }
+        // A relative search path is relative to the entry's directory
+        let argument = canonical::resolve_path_flag(argument, &entry.directory);
+        if !flags.contains(&argument) {
+            flags.push(argument);
+        }
+    }
+    flags
+}
+
+pub fn write(output_dir: &str, root: Option<&Path>, key_case: KeyCase) {
+    let compile_commands_path = paths::absolute(output_dir).join("compile_commands.json");
+    let root = paths::absolute(root.unwrap_or(Path::new(output_dir)));
+    let entries: Vec<CompileCommandsEntry> = read_compile_commands(&compile_commands_path)
+        .into_iter()
+        .filter(|entry| is_under(&entry.file, &root, key_case))
+        .collect();
+    if entries.is_empty() {
+        panic!(
+            "{} has no entries under {}",
+            compile_commands_path.display(),
+            root.display()
+        );
+    }
+
+    let entry_flags: Vec<Vec<String>> = entries.iter().map(flags).collect();
+    let mut counts: HashMap<&str, usize> = HashMap::new();
+    let mut order = Vec::new();
+    for flags in &entry_flags {
+        for flag in flags {
+            let count = counts.entry(flag).or_default();
+            if *count == 0 {
+                order.push(flag.as_str());
+            }
+            *count += 1;
+        }
+    }
+    let (common, dropped): (Vec<&str>, Vec<&str>) = order
+        .into_iter()
+        .partition(|flag| counts[flag] == entries.len());
+
+    // The flags are normalized to cl's syntax, which clang only accepts in its cl mode
+    let mut contents = String::from("--driver-mode=cl\n");
+    for flag in &common {
+        contents.push_str(flag);
+        contents.push('\n');
+    }
+    let path = root.join(FILE_NAME);
+    fs::write(&path, contents).unwrap_or_else(|_| panic!("Failed to write {}", path.display()));
+    println!(
+        "Wrote {} flags shared by {} entries to {}",
+        common.len(),
+        entries.len(),
+        path.display()
+    );
+
+    if !dropped.is_empty() {
+        println!(
+            "Warning: dropped {} flags that only some of the entries pass:",
+            dropped.len()
+        );
+        for flag in dropped {
+            println!(
+                "    {} ({} of {} entries)",
+                flag,
+                counts[flag],
+                entries.len()
+            );
+        }
+    }
+}
@@ -0,0 +1,97 @@
+//! Build flavors: free (retail) builds log to buildfre.log, and checked (debug) builds, which
+//! define DBG=1, log to buildchk.log.
+
+use crate::{compress, metadata};
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+#[derive(clap::ValueEnum, Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub enum Flavor {
+    /// Free (retail) builds, logged to buildfre.log
+    #[default]
+    Fre,
+    /// Checked (debug) builds, logged to buildchk.log
+    Chk,
+}
+
+impl Flavor {
+    pub fn name(self) -> &'static str {
+        match self {
+            Flavor::Fre => "fre",
+            Flavor::Chk => "chk",
+        }
+    }
+
+    /// The name of the database for this flavor when databases are written for each flavor.
+    pub fn database_name(self) -> String {
+        format!("compile_commands.{}.json", self.name())
+    }
+}
+
+/// The build logs of each flavor in `dir`.
+pub fn discover(dir: &Path) -> Vec<(Flavor, PathBuf)> {
+    [Flavor::Fre, Flavor::Chk]
+        .into_iter()
+        .map(|flavor| (flavor, dir.join(format!("build{}.log", flavor.name()))))
+        .filter(|(_, path)| path.is_file())
+        .collect()
+}
+
+/// The `flavor` logs anywhere under `root`, in sorted order.
+pub fn discover_per_dir(root: &Path, flavor: Flavor) -> Vec<PathBuf> {
+    let pattern = root.join("**").join(format!("build{}.log", flavor.name()));
+    let mut paths: Vec<PathBuf> = glob::glob(&pattern.to_string_lossy())
+        .unwrap_or_else(|_| panic!("Invalid log directory {}", root.display()))
+        .filter_map(Result::ok)
+        .collect();
+    paths.sort();
+    paths
+}
+
+/// Points compile_commands.json and its metadata sidecar in `output_dir` at `flavor`'s, with
+/// symlinks where possible and copies otherwise (creating symlinks on Windows needs extra
+/// privileges).
+pub fn link_preferred(output_dir: &Path, flavor: Flavor) {
+    let database = PathBuf::from("compile_commands.json");
+    let flavor_database = PathBuf::from(flavor.database_name());
+    // The sidecar may be compressed, so its link is named for the same compression, replacing
+    // one named for another so that it isn't read instead
+    let sidecar = metadata::path_for(&database);
+    let compression =
+        compress::written_with(&output_dir.join(metadata::path_for(&flavor_database)));
+    compress::remove(&output_dir.join(&sidecar));
+    let files = [
+        (
+            compress::path_with(&sidecar, compression),
+            compress::path_with(&metadata::path_for(&flavor_database), compression),
+        ),
+        (database, flavor_database),
+    ];
+    for (name, target) in &files {
+        let link = output_dir.join(name);
+        if link.is_symlink() || link.exists() {
+            fs::remove_file(&link)
+                .unwrap_or_else(|_| panic!("Failed to remove {}", link.display()));
+        }
+        symlink(target, &link)
+            .or_else(|_| fs::copy(output_dir.join(target), &link).map(|_| ()))
+            .unwrap_or_else(|_| panic!("Failed to create {}", link.display()));
+    }
+    println!(
+        "Pointed compile_commands.json in {} at the {} database",
+        output_dir.display(),
+        flavor.name()
+    );
+}
+
+#[cfg(unix)]
+fn symlink(target: &Path, link: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(target, link)
+}
+
+#[cfg(windows)]
+fn symlink(target: &Path, link: &Path) -> io::Result<()> {
+    std::os::windows::fs::symlink_file(target, link)
+}
@@ -0,0 +1,101 @@
+//! Resolving forced includes (`/FI precomp.h`) and precompiled header names (`/Yuprecomp.h`) to
+//! absolute paths. cl.exe finds them the way it finds `#include "precomp.h"`: in the source
+//! file's directory, then the directory it ran in, then the `/I` directories and then the
+//! directories INCLUDE lists. Tools that run the command from elsewhere, or don't search the same
+//! way, miss them unless they're absolute.
+
+use crate::{
+    CompileCommandsEntry, cmdline,
+    compiler::Dialect,
+    environment::{self, Environment},
+    paths,
+};
+use std::path::{Path, PathBuf};
+
+/// The directories cl.exe searches for a quoted include in `entry`, in order.
+fn search_dirs(
+    entry: &CompileCommandsEntry,
+    tokens: &[String],
+    variables: &Environment,
+) -> Vec<PathBuf> {
+    let mut dirs = Vec::new();
+    dirs.extend(Path::new(&entry.file).parent().map(Path::to_path_buf));
+    dirs.push(entry.directory.clone());
+    let mut tokens = tokens.iter().skip(1);
+    while let Some(token) = tokens.next() {
+        let Some(flag) = Dialect::Msvc.flag(token) else {
+            continue;
+        };
+        let dir = ["I", "external:I"]
+            .into_iter()
+            .find_map(|prefix| flag.strip_prefix(prefix))
+            .map(|dir| match dir {
+                "" => tokens.next().map(String::as_str),
+                dir => Some(dir),
+            });
+        if let Some(Some(dir)) = dir {
+            dirs.push(entry.directory.join(dir));
+        }
+    }
+    if let Some(include) = environment::lookup(variables, "INCLUDE") {
+        dirs.extend(
+            include
+                .split(';')
+                .filter(|dir| !dir.is_empty())
+                .map(PathBuf::from),
+        );
+    }
+    dirs
+}
+
+/// The absolute path of the header `name` found in `dirs`, if it's found.
+fn find(name: &str, dirs: &[PathBuf]) -> Option<String> {
+    if Path::new(name).is_absolute() {
+        return Some(name.to_string());
+    }
+    dirs.iter()
+        .map(|dir| dir.join(name))
+        .find(|path| path.is_file())
+        .map(|path| {
+            paths::normalize(&paths::absolute(path))
+                .to_string_lossy()
+                .to_string()
+        })
+}
+
+/// Rewrites `entry`'s /FI and /Yu header names as absolute paths. Returns the names that weren't
+/// found, which are left as they are.
+pub fn resolve(entry: &mut CompileCommandsEntry, variables: &Environment) -> Vec<String> {
+    let mut tokens = cmdline::split(&entry.command);
+    let names_header = |token: &str| {
+        Dialect::Msvc
+            .flag(token)
+            .is_some_and(|flag| flag.starts_with("FI") || flag.starts_with("Yu"))
+    };
+    if !tokens.iter().skip(1).any(|token| names_header(token)) {
+        return Vec::new();
+    }
+    let dirs = search_dirs(entry, &tokens, variables);
+    let mut unresolved = Vec::new();
+    let mut i = 1;
+    while i < tokens.len() {
+        let token = &tokens[i];
+        let (index, prefix, name) = match Dialect::Msvc.flag(token) {
+            Some("FI") if i + 1 < tokens.len() => (i + 1, String::new(), tokens[i + 1].clone()),
+            Some(flag) if flag.len() > 2 && (flag.starts_with("FI") || flag.starts_with("Yu")) => {
+                (i, token[..3].to_string(), flag[2..].to_string())
+            }
+            _ => {
+                i += 1;
+                continue;
+            }
+        };
+        match find(&name, &dirs) {
+            Some(path) => tokens[index] = format!("{prefix}{path}"),
+            None => unresolved.push(name),
+        }
+        i = index + 1;
+    }
+    entry.command = cmdline::join(&tokens);
+    unresolved
+}
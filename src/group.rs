@@ -0,0 +1,106 @@
+//! Grouping entries by directory or by the target they build, for tools that work a component at
+//! a time, like running clang-tidy over one driver, and the `list-dirs` subcommand listing the
+//! groups. Each group has an id derived from its key alone, so a job sharded by group id gets the
+//! same files on every run and every machine.
+
+use crate::{CompileCommandsEntry, output_flag, paths, read_compile_commands};
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+#[derive(clap::ValueEnum, Clone, Copy, Default, PartialEq, Eq)]
+pub enum GroupBy {
+    /// The directory each command ran in
+    #[default]
+    Directory,
+    /// The executable the command links (/Fe), or else the directory it writes its object to (/Fo)
+    Target,
+}
+
+#[derive(serde::Serialize)]
+pub struct Group<'a> {
+    /// A short hash of the key
+    pub id: String,
+    /// The directory, or the path of the target
+    pub key: PathBuf,
+    #[serde(rename = "files", serialize_with = "serialize_files")]
+    pub entries: Vec<&'a CompileCommandsEntry>,
+}
+
+fn serialize_files<S: serde::Serializer>(
+    entries: &[&CompileCommandsEntry],
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.collect_seq(entries.iter().map(|entry| &entry.file))
+}
+
+/// The id of the group with `key`: FNV-1a of the key, lowercased since paths on Windows are
+/// case-insensitive. Unlike the standard library's hasher it's the same in every build.
+fn group_id(key: &Path) -> String {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for byte in key.to_string_lossy().to_lowercase().bytes() {
+        hash ^= u64::from(byte);
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    format!("{hash:016x}")
+}
+
+/// The target `entry` builds: the executable it links, without its extension, or else the
+/// directory its object file goes in.
+pub fn target(entry: &CompileCommandsEntry) -> PathBuf {
+    let target = match output_flag(&entry.command, "Fe") {
+        Some(executable) => entry.directory.join(executable).with_extension(""),
+        None => entry
+            .object_file()
+            .parent()
+            .map_or_else(|| entry.directory.clone(), Path::to_path_buf),
+    };
+    paths::normalize(&target)
+}
+
+/// The key of the group `entry` is in.
+pub fn key(entry: &CompileCommandsEntry, by: GroupBy) -> PathBuf {
+    match by {
+        GroupBy::Directory => paths::normalize(&entry.directory),
+        GroupBy::Target => target(entry),
+    }
+}
+
+/// `entries` grouped `by` their directory or target, in order of their keys.
+pub fn group(entries: &[CompileCommandsEntry], by: GroupBy) -> Vec<Group<'_>> {
+    let mut groups: BTreeMap<PathBuf, Vec<&CompileCommandsEntry>> = BTreeMap::new();
+    for entry in entries {
+        groups.entry(key(entry, by)).or_default().push(entry);
+    }
+    groups
+        .into_iter()
+        .map(|(key, entries)| Group {
+            id: group_id(&key),
+            key,
+            entries,
+        })
+        .collect()
+}
+
+/// The `list-dirs` subcommand.
+pub fn list(output_dir: &str, by: GroupBy, json: bool) {
+    let compile_commands_path = paths::absolute(output_dir).join("compile_commands.json");
+    let entries = read_compile_commands(&compile_commands_path);
+    let groups = group(&entries, by);
+    if json {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&groups).expect("Failed to serialize groups to JSON")
+        );
+        return;
+    }
+    for group in &groups {
+        println!(
+            "{}  {:>5}  {}",
+            group.id,
+            group.entries.len(),
+            group.key.display()
+        );
+    }
+}
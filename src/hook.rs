@@ -0,0 +1,132 @@
+//! The `install-hook` subcommand, which makes Razzle run this tool after every `build`, so the
+//! database stays up to date without anyone running it by hand.
+//!
+//! Razzle defines its aliases from `cue.pri` in the user's profile directory (`%INIT%`), so for
+//! cmd the hook is a script there plus an alias running it after build.exe. PowerShell windows
+//! don't read cue.pri, so for PowerShell the hook is a script defining a `build` function, which
+//! the user's profile dot-sources. Each run merges the new log into the existing database, so the
+//! hook never needs more than the output directory (or the config's projects).
+
+use crate::{config, paths};
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+/// What the hook scripts are called.
+const SCRIPT_NAME: &str = "buildexe2cc-hook";
+
+/// Razzle's alias file, in the hook directory.
+const ALIASES_NAME: &str = "cue.pri";
+
+#[derive(clap::ValueEnum, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Shell {
+    /// A .cmd script, run by an alias in cue.pri
+    Cmd,
+    /// A .ps1 script defining a `build` function, for PowerShell Razzle windows
+    Powershell,
+}
+
+/// The command the hook runs after a build: every project in the config, if there's a config
+/// with projects, or else the log build.exe just wrote in the current directory.
+fn tool_command(config_path: &str, output_dir: &str) -> String {
+    let exe = env::current_exe().expect("Failed to find this tool's executable");
+    let config = config::load_optional(config_path);
+    if config.projects.is_empty() {
+        let output_dir = paths::absolute(output_dir);
+        format!(
+            r#""{}" --output-dir "{}""#,
+            exe.display(),
+            output_dir.display()
+        )
+    } else {
+        let config_path = paths::absolute(config_path);
+        format!(
+            r#""{}" --config "{}" --all-projects"#,
+            exe.display(),
+            config_path.display()
+        )
+    }
+}
+
+/// The directory to install the hook in: `hook_dir`, or the Razzle profile directory.
+fn hook_dir(hook_dir: Option<&Path>) -> PathBuf {
+    match hook_dir {
+        Some(dir) => dir.to_path_buf(),
+        None => env::var_os("INIT").map(PathBuf::from).unwrap_or_else(|| {
+            panic!("INIT isn't set, so run this in a Razzle window or pass --hook-dir")
+        }),
+    }
+}
+
+/// Adds `line` to the file at `path` unless it's already there, creating the file if needed, and
+/// returns whether it was added.
+fn append_line(path: &Path, line: &str) -> bool {
+    let contents = fs::read_to_string(path).unwrap_or_default();
+    if contents.lines().any(|existing| existing.trim() == line) {
+        return false;
+    }
+    let mut contents = contents;
+    if !contents.is_empty() && !contents.ends_with('\n') {
+        contents.push_str("\r\n");
+    }
+    contents.push_str(line);
+    contents.push_str("\r\n");
+    fs::write(path, contents).unwrap_or_else(|_| panic!("Failed to write {}", path.display()));
+    true
+}
+
+pub fn install(shell: Shell, dir: Option<&Path>, config_path: &str, output_dir: &str) {
+    let dir = hook_dir(dir);
+    fs::create_dir_all(&dir)
+        .unwrap_or_else(|_| panic!("Failed to create directory {}", dir.display()));
+    let command = tool_command(config_path, output_dir);
+
+    match shell {
+        Shell::Cmd => {
+            let script_path = dir.join(format!("{SCRIPT_NAME}.cmd"));
+            // The build's exit code is kept, so that scripts checking it still see failures
+            let script = format!(
+                "@echo off\r\n\
+                 rem Updates compile_commands.json after each build. Written by install-hook.\r\n\
+                 setlocal\r\n\
+                 set BUILD_ERRORLEVEL=%ERRORLEVEL%\r\n\
+                 {command}\r\n\
+                 exit /b %BUILD_ERRORLEVEL%\r\n"
+            );
+            fs::write(&script_path, script)
+                .unwrap_or_else(|_| panic!("Failed to write {}", script_path.display()));
+            println!("Wrote {}", script_path.display());
+
+            let aliases_path = dir.join(ALIASES_NAME);
+            let alias = format!(r#"build build.exe $* $T "{}""#, script_path.display());
+            if append_line(&aliases_path, &alias) {
+                println!(
+                    "Added a build alias to {}, which takes effect in new Razzle windows",
+                    aliases_path.display()
+                );
+            } else {
+                println!("{} already has the build alias", aliases_path.display());
+            }
+        }
+        Shell::Powershell => {
+            let script_path = dir.join(format!("{SCRIPT_NAME}.ps1"));
+            let script = format!(
+                "# Updates compile_commands.json after each build. Written by install-hook.\r\n\
+                 function build {{\r\n\
+                 \x20   build.exe @args\r\n\
+                 \x20   $buildExitCode = $LASTEXITCODE\r\n\
+                 \x20   & {command}\r\n\
+                 \x20   $global:LASTEXITCODE = $buildExitCode\r\n\
+                 }}\r\n"
+            );
+            fs::write(&script_path, script)
+                .unwrap_or_else(|_| panic!("Failed to write {}", script_path.display()));
+            println!("Wrote {}", script_path.display());
+            println!(
+                "Add this to your PowerShell profile to run it after each build:\n    . \"{}\"",
+                script_path.display()
+            );
+        }
+    }
+}
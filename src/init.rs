@@ -0,0 +1,107 @@
+//! The `init` subcommand, which scaffolds a config file and editor integration for an enlistment.
+
+use crate::config;
+use std::{
+    env, fs,
+    io::{self, BufRead, Write},
+    path::Path,
+};
+
+/// Asks `question` on the terminal, returning the answer or `default` if none was given.
+fn ask(question: &str, default: &str) -> String {
+    print!("{} [{}]: ", question, default);
+    io::stdout().flush().expect("Failed to write to stdout");
+    let mut answer = String::new();
+    io::stdin()
+        .lock()
+        .read_line(&mut answer)
+        .expect("Failed to read from stdin");
+    let answer = answer.trim();
+    if answer.is_empty() {
+        default.to_string()
+    } else {
+        answer.to_string()
+    }
+}
+
+/// Writes `contents` to `path` unless something is already there, so re-running init never
+/// clobbers files the user has customized.
+fn write_new(path: &Path, contents: &str) {
+    if path.exists() {
+        println!("Skipped {}, which already exists", path.display());
+        return;
+    }
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .unwrap_or_else(|_| panic!("Failed to create directory {}", parent.display()));
+    }
+    fs::write(path, contents).unwrap_or_else(|_| panic!("Failed to write {}", path.display()));
+    println!("Wrote {}", path.display());
+}
+
+/// The flavor of the build log in `dir`, preferring fre if there are logs for both. Per-pass logs
+/// like buildfre_pass1.log don't count, since the project reads the main log.
+fn detect_flavor(dir: &Path) -> Option<&'static str> {
+    ["fre", "chk"]
+        .into_iter()
+        .find(|flavor| dir.join(format!("build{}.log", flavor)).is_file())
+}
+
+pub fn init() {
+    let root = env::current_dir().expect("Failed to get the current directory");
+    let name = root
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_else(|| String::from("enlistment"));
+
+    let flavor = ask(
+        "Build flavor (fre or chk)",
+        detect_flavor(&root).unwrap_or("fre"),
+    );
+    let output = ask("Directory to write compile_commands.json to", ".");
+    let output = output.replace('\\', "/");
+
+    write_new(
+        &root.join(config::DEFAULT_PATH),
+        &format!(
+            r#"[[project]]
+name = "{name}"
+log = "build{flavor}.log"
+output = "{output}"
+"#
+        ),
+    );
+    write_new(
+        &root.join(".clangd"),
+        &format!(
+            r#"CompileFlags:
+  CompilationDatabase: {output}
+"#
+        ),
+    );
+    write_new(
+        &root.join(config::IGNORE_PATH),
+        r#"# Globs matching source files that shouldn't get entries, one per line, relative to this
+# directory. Uncomment or add lines to use them, like:
+# **/unittest/**
+# **/test/data/**
+"#,
+    );
+
+    let settings = format!(
+        r#"{{
+  "clangd.arguments": ["--compile-commands-dir=${{workspaceFolder}}/{output}"]
+}}
+"#
+    );
+    let settings_path = root.join(".vscode").join("settings.json");
+    if settings_path.exists() {
+        println!(
+            "{} already exists, so add this to it to point clangd at compile_commands.json:",
+            settings_path.display()
+        );
+        print!("{}", settings);
+    } else {
+        write_new(&settings_path, &settings);
+    }
+}
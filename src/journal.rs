@@ -0,0 +1,310 @@
+//! Crash-safe journal of a run's progress, so that an interrupted run can resume from its last
+//! checkpoint instead of re-parsing (and re-resolving) the whole log.
+//!
+//! The journal is a file of newline-delimited JSON records. The first record identifies the log
+//! being processed and each following record is a checkpoint holding the entries resolved since
+//! the previous one. Checkpoints are synced to disk as they are written, so a crash can at worst
+//! leave a torn final record, which is ignored when resuming.
+
+use crate::{CompileCommandsEntry, paths};
+use std::{
+    collections::{HashMap, HashSet},
+    fs::{self, File, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+#[derive(serde::Serialize, serde::Deserialize)]
+struct Header {
+    log_path: PathBuf,
+}
+
+/// What the parser knows about the build between commands, which is all of its state a checkpoint
+/// needs to save.
+#[derive(serde::Serialize, serde::Deserialize, Default)]
+pub struct ParseContext {
+    /// The directory each thread is currently processing
+    pub dirs: HashMap<String, PathBuf>,
+    /// The line each thread's current directory banner was on
+    #[serde(default)]
+    pub banner_lines: HashMap<String, usize>,
+    /// Every directory a banner has been seen for
+    #[serde(default)]
+    pub banner_dirs: HashSet<PathBuf>,
+    /// The build pass (pass0 generates files, pass1 compiles, pass2 links) the log is in
+    #[serde(default)]
+    pub pass: u32,
+    /// Directories and files `build /why` reported as up to date, which weren't rebuilt because
+    /// nothing changed rather than because they're gone
+    #[serde(default)]
+    pub up_to_date: HashSet<PathBuf>,
+    /// The launcher each file's command was wrapped in, for commands that had one
+    #[serde(default)]
+    pub launchers: HashMap<String, String>,
+    /// The first spelling of each directory the log named, keyed by its case-folded form
+    #[serde(default)]
+    pub dir_spellings: HashMap<String, PathBuf>,
+}
+
+impl ParseContext {
+    /// `dir` as the log first spelled it. Threads print the same directory as `c:\src\Foo` and
+    /// later as `C:\SRC\foo`, and entries should get the same `directory` either way.
+    pub fn spelling(&mut self, dir: PathBuf) -> PathBuf {
+        let dir = paths::normalize(&dir);
+        let key = dir.to_string_lossy().replace('/', "\\").to_lowercase();
+        self.dir_spellings.entry(key).or_insert(dir).clone()
+    }
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+struct Checkpoint {
+    /// Byte offset into the log up to which everything has been processed
+    offset: usize,
+    /// Number of lines up to `offset`
+    line_number: usize,
+    /// CRC32 of the log up to `offset`, to detect a log that has since been replaced
+    prefix_hash: u32,
+    /// The parser's context at `offset`
+    #[serde(flatten)]
+    context: ParseContext,
+    /// Entries resolved since the previous checkpoint, with the pass each was compiled in
+    entries: Vec<(CompileCommandsEntry, u32)>,
+    /// How many secrets were redacted from the entries
+    #[serde(default)]
+    redactions: usize,
+}
+
+/// Progress restored from a journal.
+pub struct Resumed {
+    pub offset: usize,
+    pub line_number: usize,
+    pub context: ParseContext,
+    pub entries: Vec<(CompileCommandsEntry, u32)>,
+    pub redactions: usize,
+}
+
+pub struct Journal {
+    path: PathBuf,
+    file: File,
+}
+
+/// Restores progress from the journal at `path`, if it exists and its last checkpoint still
+/// matches the log. `prefix_hash` takes the CRC32 of the given length of the log, if it's that
+/// long.
+pub fn resume(
+    path: &Path,
+    log_path: &Path,
+    prefix_hash: impl FnOnce(usize) -> Option<u32>,
+) -> Option<Resumed> {
+    let contents = fs::read_to_string(path).ok()?;
+    let mut records = contents.lines();
+
+    let header: Header = serde_json::from_str(records.next()?).ok()?;
+    if header.log_path != log_path {
+        return None;
+    }
+
+    let mut resumed = Resumed {
+        offset: 0,
+        line_number: 0,
+        context: ParseContext::default(),
+        entries: Vec::new(),
+        redactions: 0,
+    };
+    let mut checkpoint_hash = None;
+    // A record that fails to parse was torn by a crash while it was being written. Any records
+    // after it were written by a run that resumed from before it.
+    for checkpoint in records.filter_map(|record| serde_json::from_str::<Checkpoint>(record).ok()) {
+        resumed.offset = checkpoint.offset;
+        resumed.line_number = checkpoint.line_number;
+        resumed.context = checkpoint.context;
+        resumed.entries.extend(checkpoint.entries);
+        resumed.redactions += checkpoint.redactions;
+        checkpoint_hash = Some(checkpoint.prefix_hash);
+    }
+
+    if prefix_hash(resumed.offset)? != checkpoint_hash? {
+        return None;
+    }
+    Some(resumed)
+}
+
+impl Journal {
+    /// Starts a new journal at `path`, replacing any existing one.
+    pub fn create(path: &Path, log_path: &Path) -> Journal {
+        let mut journal = Journal {
+            path: path.to_path_buf(),
+            file: File::create(path)
+                .unwrap_or_else(|_| panic!("Failed to create journal {}", path.display())),
+        };
+        let header = Header {
+            log_path: log_path.to_path_buf(),
+        };
+        journal.append(&header);
+        journal
+    }
+
+    /// Continues the journal at `path` after resuming from it.
+    pub fn reopen(path: &Path) -> Journal {
+        let mut file = OpenOptions::new()
+            .append(true)
+            .open(path)
+            .unwrap_or_else(|_| panic!("Failed to open journal {}", path.display()));
+        // If the previous run crashed mid-record, start on a fresh line so new records stay intact
+        file.write_all(b"\n")
+            .unwrap_or_else(|_| panic!("Failed to write journal {}", path.display()));
+        Journal {
+            path: path.to_path_buf(),
+            file,
+        }
+    }
+
+    pub fn checkpoint(
+        &mut self,
+        offset: usize,
+        line_number: usize,
+        prefix_hash: u32,
+        context: &ParseContext,
+        entries: &[(CompileCommandsEntry, u32)],
+        redactions: usize,
+    ) {
+        #[derive(serde::Serialize)]
+        struct CheckpointRef<'a> {
+            offset: usize,
+            line_number: usize,
+            prefix_hash: u32,
+            #[serde(flatten)]
+            context: &'a ParseContext,
+            entries: &'a [(CompileCommandsEntry, u32)],
+            redactions: usize,
+        }
+        self.append(&CheckpointRef {
+            offset,
+            line_number,
+            prefix_hash,
+            context,
+            entries,
+            redactions,
+        });
+    }
+
+    /// Removes the journal once the run's output has been written.
+    pub fn finish(self) {
+        drop(self.file);
+        fs::remove_file(&self.path)
+            .unwrap_or_else(|_| panic!("Failed to remove journal {}", self.path.display()));
+    }
+
+    fn append<T: serde::Serialize>(&mut self, record: &T) {
+        let mut line = serde_json::to_string(record).expect("Failed to serialize journal record");
+        line.push('\n');
+        self.file
+            .write_all(line.as_bytes())
+            .and_then(|_| self.file.sync_data())
+            .unwrap_or_else(|_| panic!("Failed to write journal {}", self.path.display()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A journal path of its own for the test `name`, with no journal there yet.
+    fn journal_path(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!(
+            "buildexe-to-compilecommands.{}.{}.journal",
+            std::process::id(),
+            name
+        ));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    fn entry(file: &str) -> (CompileCommandsEntry, u32) {
+        let entry = CompileCommandsEntry {
+            directory: PathBuf::from(r"C:\src"),
+            command: format!("cl /c {file}"),
+            file: format!(r"C:\src\{file}"),
+            environment: None,
+        };
+        (entry, 1)
+    }
+
+    /// Writes a journal for `log` with checkpoints after 10 and 25 bytes.
+    fn write_journal(path: &Path, log: &Path) {
+        let mut journal = Journal::create(path, log);
+        let mut context = ParseContext::default();
+        context
+            .dirs
+            .insert(String::from("0001"), PathBuf::from(r"C:\src"));
+        context.banner_dirs.insert(PathBuf::from(r"C:\src"));
+        context.pass = 1;
+        journal.checkpoint(10, 1, 0x1111, &context, &[entry("a.cpp")], 1);
+        context
+            .dirs
+            .insert(String::from("0002"), PathBuf::from(r"C:\src\net"));
+        context.banner_lines.insert(String::from("0002"), 2);
+        context.banner_dirs.insert(PathBuf::from(r"C:\src\net"));
+        context.pass = 2;
+        journal.checkpoint(25, 3, 0x2222, &context, &[entry("b.cpp")], 0);
+    }
+
+    #[test]
+    fn resumes_from_the_last_checkpoint() {
+        let path = journal_path("round-trip");
+        let log = Path::new(r"C:\logs\buildfre.log");
+        write_journal(&path, log);
+
+        let resumed = resume(&path, log, |len| (len == 25).then_some(0x2222)).unwrap();
+        assert_eq!(resumed.offset, 25);
+        assert_eq!(resumed.line_number, 3);
+        assert_eq!(resumed.context.pass, 2);
+        assert_eq!(resumed.context.dirs.len(), 2);
+        assert_eq!(resumed.context.dirs["0002"], PathBuf::from(r"C:\src\net"));
+        assert_eq!(resumed.context.banner_lines["0002"], 2);
+        assert!(
+            resumed
+                .context
+                .banner_dirs
+                .contains(Path::new(r"C:\src\net"))
+        );
+        let files: Vec<_> = resumed.entries.iter().map(|(e, _)| &e.file).collect();
+        assert_eq!(files, [r"C:\src\a.cpp", r"C:\src\b.cpp"]);
+        assert_eq!(resumed.redactions, 1);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn ignores_a_torn_final_checkpoint() {
+        let path = journal_path("torn");
+        let log = Path::new(r"C:\logs\buildfre.log");
+        write_journal(&path, log);
+        let contents = fs::read_to_string(&path).unwrap();
+        fs::write(&path, &contents[..contents.len() - 20]).unwrap();
+
+        let resumed = resume(&path, log, |len| (len == 10).then_some(0x1111)).unwrap();
+        assert_eq!(resumed.offset, 10);
+        assert_eq!(resumed.entries.len(), 1);
+
+        // A run resuming from there appends after the torn record
+        let mut journal = Journal::reopen(&path);
+        journal.checkpoint(40, 5, 0x3333, &resumed.context, &[entry("c.cpp")], 0);
+        let resumed = resume(&path, log, |len| (len == 40).then_some(0x3333)).unwrap();
+        let files: Vec<_> = resumed.entries.iter().map(|(e, _)| &e.file).collect();
+        assert_eq!(files, [r"C:\src\a.cpp", r"C:\src\c.cpp"]);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn starts_over_for_another_or_changed_log() {
+        let path = journal_path("changed");
+        let log = Path::new(r"C:\logs\buildfre.log");
+        write_journal(&path, log);
+
+        assert!(resume(&path, Path::new(r"C:\logs\buildchk.log"), |_| Some(0x2222)).is_none());
+        // The log was replaced, or truncated to before the checkpoint
+        assert!(resume(&path, log, |_| Some(0x9999)).is_none());
+        assert!(resume(&path, log, |_| None).is_none());
+        fs::remove_file(&path).unwrap();
+    }
+}
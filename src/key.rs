@@ -0,0 +1,67 @@
+//! Matching entries up by file. Whether two entries are for the same file, when merging, pruning,
+//! querying and reporting what changed, is decided by a key made from the file's path; the entries
+//! themselves keep the path as it was spelled.
+
+#[derive(clap::ValueEnum, Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub enum KeyCase {
+    /// Paths that differ only in case or separators name the same file, as on Windows
+    Insensitive,
+    /// Paths that differ at all name different files, as on Linux, for databases used from WSL
+    Sensitive,
+    /// Windows paths, with a drive letter or a UNC prefix, are insensitive and others sensitive
+    #[default]
+    Auto,
+}
+
+/// Whether `file` is a Windows path, like `C:\src\a.cpp`, `C:/src/a.cpp` or `\\server\share\a.cpp`.
+fn is_windows_path(file: &str) -> bool {
+    let bytes = file.as_bytes();
+    (bytes.len() >= 2 && bytes[0].is_ascii_alphabetic() && bytes[1] == b':')
+        || file.starts_with(r"\\")
+}
+
+impl KeyCase {
+    /// The key `file` is matched by. Case-insensitive keys fold non-ASCII letters too, as in user
+    /// profile names, and either separator.
+    pub fn key(self, file: &str) -> String {
+        let insensitive = match self {
+            KeyCase::Insensitive => true,
+            KeyCase::Sensitive => false,
+            KeyCase::Auto => is_windows_path(file),
+        };
+        if insensitive {
+            file.replace('\\', "/").to_lowercase()
+        } else {
+            file.to_string()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keys_follow_the_case() {
+        assert_eq!(KeyCase::Insensitive.key(r"C:\Src\A.cpp"), "c:/src/a.cpp");
+        assert_eq!(KeyCase::Insensitive.key("/home/Me/A.cpp"), "/home/me/a.cpp");
+        assert_eq!(KeyCase::Sensitive.key(r"C:\Src\A.cpp"), r"C:\Src\A.cpp");
+        // Non-ASCII letters fold too
+        assert_eq!(
+            KeyCase::Insensitive.key(r"C:\Users\JOSÉ\a.cpp"),
+            "c:/users/josé/a.cpp"
+        );
+    }
+
+    #[test]
+    fn auto_is_insensitive_for_windows_paths_only() {
+        assert_eq!(KeyCase::Auto.key(r"C:\Src\A.cpp"), "c:/src/a.cpp");
+        assert_eq!(KeyCase::Auto.key("d:/Src/A.cpp"), "d:/src/a.cpp");
+        assert_eq!(
+            KeyCase::Auto.key(r"\\Server\Share\A.cpp"),
+            "//server/share/a.cpp"
+        );
+        assert_eq!(KeyCase::Auto.key("/mnt/c/Src/A.cpp"), "/mnt/c/Src/A.cpp");
+        assert_eq!(KeyCase::Auto.key(r"Src\A.cpp"), r"Src\A.cpp");
+    }
+}
@@ -0,0 +1,105 @@
+//! Launchers that wrap the compiler on a command line, like `timethis cl ...`, which are peeled off
+//! so that entries start at the compiler itself. Besides the built-in ones, the config file can
+//! name more:
+//!
+//! ```toml
+//! launchers = ["nt_wrapper.cmd"]   # matches nt_wrapper, with or without a directory or extension
+//! ```
+
+/// Launchers recognized without any configuration.
+const BUILTIN: &[&str] = &["timethis", "teecmd"];
+
+/// Extensions a launcher's program may be named with.
+const EXTENSIONS: &[&str] = &[".exe", ".cmd", ".bat"];
+
+#[derive(Clone, Hash)]
+pub struct Launchers {
+    names: Vec<String>,
+}
+
+/// `program` without its directory or extension.
+fn stem(program: &str) -> &str {
+    let file_name = program.rsplit(['\\', '/']).next().unwrap_or(program);
+    EXTENSIONS
+        .iter()
+        .find_map(|extension| {
+            // The name may end in non-ASCII text, so the split has to land on a char boundary
+            let split = file_name.len().checked_sub(extension.len())?;
+            let suffix = file_name.get(split..)?;
+            (split > 0 && suffix.eq_ignore_ascii_case(extension)).then(|| &file_name[..split])
+        })
+        .unwrap_or(file_name)
+}
+
+impl Default for Launchers {
+    fn default() -> Launchers {
+        Launchers::new(&[])
+    }
+}
+
+impl Launchers {
+    /// The built-in launchers and `extra`.
+    pub fn new(extra: &[String]) -> Launchers {
+        Launchers {
+            names: BUILTIN
+                .iter()
+                .map(|name| name.to_string())
+                .chain(extra.iter().map(|name| stem(name).to_string()))
+                .collect(),
+        }
+    }
+
+    /// Whether `program`, the first token of a command, is one of the launchers.
+    fn matches(&self, program: &str) -> bool {
+        let program = stem(program.trim_matches('"'));
+        self.names
+            .iter()
+            .any(|name| name.eq_ignore_ascii_case(program))
+    }
+
+    /// Splits the launchers off the front of `command`, returning the innermost one, if there
+    /// were any, and the command it launches.
+    pub fn peel<'a>(&self, command: &'a str) -> (Option<&'a str>, &'a str) {
+        let mut launcher = None;
+        let mut rest = command.trim_start();
+        while let Some((program, launched)) = rest.split_once(char::is_whitespace)
+            && self.matches(program)
+        {
+            launcher = Some(program);
+            rest = launched.trim_start();
+        }
+        (launcher, rest)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stem_drops_directory_and_extension() {
+        assert_eq!(stem(r"C:\tools\timethis.exe"), "timethis");
+        assert_eq!(stem("nt_wrapper.CMD"), "nt_wrapper");
+        assert_eq!(stem("tools/teecmd"), "teecmd");
+        // A bare extension isn't a name
+        assert_eq!(stem(".exe"), ".exe");
+    }
+
+    #[test]
+    fn stem_of_non_ascii_names() {
+        assert_eq!(stem("正在编译"), "正在编译");
+        assert_eq!(stem("编译.exe"), "编译");
+        assert_eq!(stem("é"), "é");
+    }
+
+    #[test]
+    fn peels_launchers() {
+        let launchers = Launchers::new(&[String::from(r"C:\bin\nt_wrapper.cmd")]);
+        assert_eq!(
+            launchers.peel("timethis nt_wrapper cl /c a.cpp"),
+            (Some("nt_wrapper"), "cl /c a.cpp")
+        );
+        assert_eq!(launchers.peel("cl /c a.cpp"), (None, "cl /c a.cpp"));
+        assert_eq!(launchers.peel("正在编译 foo"), (None, "正在编译 foo"));
+    }
+}
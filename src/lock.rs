@@ -0,0 +1,62 @@
+//! Advisory locking of a database, so that runs against the same output (like two developers'
+//! post-build hooks finishing at once) take turns reading, merging and writing it instead of
+//! overwriting each other's changes.
+
+use std::{
+    fs::{File, OpenOptions, TryLockError},
+    path::{Path, PathBuf},
+    thread,
+    time::{Duration, Instant},
+};
+
+/// How long a run waits for another to finish with the database by default, in seconds.
+pub const DEFAULT_TIMEOUT: u64 = 300;
+
+/// How often a run waiting for the lock checks whether it's been released.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// A held lock, released when it's dropped (or when the process exits, however it exits).
+pub struct DatabaseLock {
+    _file: File,
+}
+
+/// The lock file for the database at `database_path`.
+pub fn path_for(database_path: &Path) -> PathBuf {
+    database_path.with_extension("lock")
+}
+
+/// Locks the database at `database_path`, waiting up to `timeout` for another run holding the
+/// lock to finish.
+pub fn acquire(database_path: &Path, timeout: Duration) -> DatabaseLock {
+    let path = path_for(database_path);
+    let file = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(&path)
+        .unwrap_or_else(|_| panic!("Failed to open lock file {}", path.display()));
+
+    let start = Instant::now();
+    let mut waiting = false;
+    loop {
+        match file.try_lock() {
+            Ok(()) => return DatabaseLock { _file: file },
+            Err(TryLockError::WouldBlock) if start.elapsed() < timeout => {
+                if !waiting {
+                    println!(
+                        "Waiting for another run to finish with {}",
+                        database_path.display()
+                    );
+                    waiting = true;
+                }
+                thread::sleep(POLL_INTERVAL);
+            }
+            Err(TryLockError::WouldBlock) => panic!(
+                "Timed out after {} seconds waiting for another run to finish with {}",
+                timeout.as_secs(),
+                database_path.display()
+            ),
+            Err(TryLockError::Error(e)) => panic!("Failed to lock {}: {}", path.display(), e),
+        }
+    }
+}
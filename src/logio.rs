@@ -0,0 +1,288 @@
+//! Reading build.exe logs, either streamed a line at a time or memory mapped.
+//!
+//! A log that was rotated into chunks, `buildfre.log.001`, `buildfre.log.002` and so on, is read as
+//! the one log they were cut from, so lines and commands can span chunks. It can be given as the
+//! directory holding the chunks, or as the name they share when there's no file by that name.
+
+use memmap2::Mmap;
+use std::{
+    fs::{self, File},
+    io::{self, BufRead, BufReader, Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+};
+
+#[derive(clap::ValueEnum, Clone, Copy, Default)]
+pub enum IoMode {
+    /// Read the log a line at a time, keeping memory use low
+    #[default]
+    Stream,
+    /// Map the log into memory and parse it in place, without copying lines. The log must not be
+    /// modified while it is being parsed.
+    Mmap,
+}
+
+enum Source {
+    Stream(BufReader<Chunks>),
+    Mmap(Mmap),
+}
+
+/// The chunk number of a chunk's file name, like 2 for `buildfre.log.002`.
+fn chunk_number(path: &Path) -> Option<u32> {
+    let extension = path.extension()?.to_str()?;
+    if extension.is_empty() || !extension.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    extension.parse().ok()
+}
+
+/// The files the log at `path` is in, in order: the chunks in `path` if it's a directory, the
+/// chunks named `path.NNN` if there's no file at `path`, or else just `path`.
+pub fn chunks(path: &Path) -> Vec<PathBuf> {
+    let candidates: Vec<PathBuf> = if path.is_dir() {
+        fs::read_dir(path)
+            .unwrap_or_else(|_| panic!("Failed to list the log chunks in {}", path.display()))
+            .filter_map(|dir_entry| Some(dir_entry.ok()?.path()))
+            .collect()
+    } else if !path.exists() {
+        let (Some(dir), Some(name)) = (path.parent(), path.file_name()) else {
+            return vec![path.to_path_buf()];
+        };
+        let dir = if dir.as_os_str().is_empty() {
+            Path::new(".")
+        } else {
+            dir
+        };
+        let Ok(dir_entries) = fs::read_dir(dir) else {
+            return vec![path.to_path_buf()];
+        };
+        dir_entries
+            .filter_map(|dir_entry| Some(dir_entry.ok()?.path()))
+            .filter(|chunk| chunk.file_stem() == Some(name))
+            .collect()
+    } else {
+        return vec![path.to_path_buf()];
+    };
+    let mut chunks: Vec<(u32, PathBuf)> = candidates
+        .into_iter()
+        .filter(|chunk| chunk.is_file())
+        .filter_map(|chunk| Some((chunk_number(&chunk)?, chunk)))
+        .collect();
+    if chunks.is_empty() {
+        if path.is_dir() {
+            panic!(
+                "There are no log chunks like buildfre.log.001 in {}",
+                path.display()
+            );
+        }
+        return vec![path.to_path_buf()];
+    }
+    chunks.sort();
+    chunks.into_iter().map(|(_, chunk)| chunk).collect()
+}
+
+/// A log's chunks read one after the other, as one file.
+struct Chunks {
+    /// Each chunk's file and the offset in the log it starts at
+    files: Vec<(File, u64)>,
+    current: usize,
+}
+
+impl Chunks {
+    fn open(paths: &[PathBuf]) -> Chunks {
+        let mut files = Vec::new();
+        let mut start = 0;
+        for path in paths {
+            let file = File::open(path)
+                .unwrap_or_else(|_| panic!("Failed to read build.exe log from {}", path.display()));
+            let len = file.metadata().map(|m| m.len()).unwrap_or_default();
+            files.push((file, start));
+            start += len;
+        }
+        Chunks { files, current: 0 }
+    }
+}
+
+impl Read for Chunks {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while let Some((file, _)) = self.files.get_mut(self.current) {
+            let n = file.read(buf)?;
+            if n > 0 || buf.is_empty() || self.current + 1 == self.files.len() {
+                return Ok(n);
+            }
+            self.current += 1;
+            self.files[self.current].0.seek(SeekFrom::Start(0))?;
+        }
+        Ok(0)
+    }
+}
+
+impl Seek for Chunks {
+    /// Only seeks from the start are supported, which are all the log is read with.
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let SeekFrom::Start(offset) = pos else {
+            return Err(io::Error::from(io::ErrorKind::Unsupported));
+        };
+        self.current = self
+            .files
+            .iter()
+            .rposition(|(_, start)| *start <= offset)
+            .unwrap_or_default();
+        let (file, start) = &mut self.files[self.current];
+        file.seek(SeekFrom::Start(offset - *start))?;
+        Ok(offset)
+    }
+}
+
+pub struct LogFile {
+    path: PathBuf,
+    source: Source,
+}
+
+impl LogFile {
+    /// Opens the log at `path`, which may be a set of chunks. Chunked logs are always streamed.
+    pub fn open(path: &Path, mode: IoMode) -> LogFile {
+        let chunks = chunks(path);
+        let source = match mode {
+            IoMode::Mmap if chunks.len() == 1 => {
+                let file = File::open(&chunks[0]).unwrap_or_else(|_| {
+                    panic!("Failed to read build.exe log from {}", path.display())
+                });
+                // Safety: the mapping is only read, and the user is told the log must not be
+                // modified while it's mapped
+                let mmap = unsafe { Mmap::map(&file) };
+                Source::Mmap(mmap.unwrap_or_else(|_| {
+                    panic!("Failed to map build.exe log from {}", path.display())
+                }))
+            }
+            _ => Source::Stream(BufReader::new(Chunks::open(&chunks))),
+        };
+        LogFile {
+            path: path.to_path_buf(),
+            source,
+        }
+    }
+
+    /// Takes the CRC32 of the first `len` bytes of the log, or returns `None` if the log is
+    /// shorter than that. The checksum is the same however the bytes are split up, so continuing
+    /// to feed the returned hasher the rest of the log a line at a time gives the same result as
+    /// hashing a longer prefix at once, and it's the same in every build of the tool.
+    pub fn prefix_hasher(&mut self, len: usize) -> Option<crc32fast::Hasher> {
+        let mut hasher = crc32fast::Hasher::new();
+        match &mut self.source {
+            Source::Stream(reader) => {
+                reader.seek(SeekFrom::Start(0)).ok()?;
+                let mut remaining = len;
+                let mut buf = [0; 64 * 1024];
+                while remaining > 0 {
+                    let chunk = remaining.min(buf.len());
+                    let n = reader.read(&mut buf[..chunk]).ok()?;
+                    if n == 0 {
+                        return None;
+                    }
+                    hasher.update(&buf[..n]);
+                    remaining -= n;
+                }
+            }
+            Source::Mmap(mmap) => hasher.update(mmap.get(..len)?),
+        }
+        Some(hasher)
+    }
+
+    /// Calls `f` with each line of the log from byte `offset` on, including its line terminator.
+    pub fn for_each_line(&mut self, offset: usize, mut f: impl FnMut(&str)) {
+        let read_error = format!("Failed to read build.exe log from {}", self.path.display());
+        match &mut self.source {
+            Source::Stream(reader) => {
+                reader
+                    .seek(SeekFrom::Start(offset as u64))
+                    .expect(&read_error);
+                let mut line = String::new();
+                while reader.read_line(&mut line).expect(&read_error) > 0 {
+                    f(&line);
+                    line.clear();
+                }
+            }
+            Source::Mmap(mmap) => {
+                let log = std::str::from_utf8(&mmap[offset..]).expect(&read_error);
+                log.split_inclusive('\n').for_each(f);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A directory of its own for the test `name`, empty.
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "buildexe-to-compilecommands.{}.{}",
+            std::process::id(),
+            name
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn hashes_prefixes_the_same_however_they_are_read() {
+        let dir = test_dir("prefix-hash");
+        let log = "0001>BUILDMSG: Processing C:\\src\n0001>cl /c a.cpp\n0001>cl /c b.cpp\n";
+        let path = dir.join("buildfre.log");
+        fs::write(&path, log).unwrap();
+
+        for mode in [IoMode::Stream, IoMode::Mmap] {
+            let mut file = LogFile::open(&path, mode);
+            for len in [0, 10, log.len()] {
+                let hasher = file.prefix_hasher(len).unwrap();
+                assert_eq!(hasher.finalize(), crc32fast::hash(&log.as_bytes()[..len]));
+            }
+            // Continuing a line at a time gives the hash of the longer prefix
+            let first_line = log.find('\n').unwrap() + 1;
+            let mut hasher = file.prefix_hasher(first_line).unwrap();
+            file.for_each_line(first_line, |line| hasher.update(line.as_bytes()));
+            assert_eq!(hasher.finalize(), crc32fast::hash(log.as_bytes()));
+            assert!(file.prefix_hasher(log.len() + 1).is_none());
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn joins_lines_split_across_chunks() {
+        let dir = test_dir("chunks");
+        // The second command is cut mid-token, between `/Fo` and its path
+        fs::write(
+            dir.join("buildfre.log.001"),
+            "0001>cl /c a.cpp\n0001>cl /c b.cpp /Fo",
+        )
+        .unwrap();
+        fs::write(
+            dir.join("buildfre.log.002"),
+            "obj\\b.obj\n0001>cl /c c.cpp\n",
+        )
+        .unwrap();
+        let expected = [
+            "0001>cl /c a.cpp\n",
+            "0001>cl /c b.cpp /Foobj\\b.obj\n",
+            "0001>cl /c c.cpp\n",
+        ];
+
+        for path in [dir.clone(), dir.join("buildfre.log")] {
+            for mode in [IoMode::Stream, IoMode::Mmap] {
+                let mut file = LogFile::open(&path, mode);
+                let mut lines = Vec::new();
+                file.for_each_line(0, |line| lines.push(line.to_string()));
+                assert_eq!(lines, expected);
+
+                // Resuming from an offset in the second chunk
+                let offset = expected[..2].iter().map(|line| line.len()).sum();
+                let mut lines = Vec::new();
+                file.for_each_line(offset, |line| lines.push(line.to_string()));
+                assert_eq!(lines, expected[2..]);
+            }
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
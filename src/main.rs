@@ -1,211 +1,3093 @@
+mod analyze;
+mod audit;
+mod cache;
+mod canonical;
+mod cmdline;
+mod compare;
+mod compiler;
+mod compress;
+mod config;
+mod cuda;
+mod diagnostics;
+mod editor;
+mod environment;
+mod explain;
+mod external;
+mod fixed;
+mod flavor;
+mod force_include;
+mod group;
+mod hook;
+mod init;
+mod journal;
+mod key;
+mod launcher;
+mod lock;
+mod logio;
+mod metadata;
+mod modules;
+mod objroot;
+mod overrides;
+mod paths;
+mod prefix;
+mod priority;
+mod query;
+mod redact;
+mod repack;
+mod reroot;
+mod sanitize;
+mod scratch;
+mod share;
+mod sink;
+mod standard;
+mod stats;
+mod store;
+mod subst;
+mod template;
+mod timings;
+mod trace;
+
+use cache::TransformCache;
 use clap::Parser;
+use compiler::{Compiler, CompilerDefinition, Dialect};
+use compress::Compression;
+use editor::EditorConfig;
+use flavor::Flavor;
+use journal::{Journal, ParseContext};
+use key::KeyCase;
+use launcher::Launchers;
+use logio::{IoMode, LogFile};
+use metadata::Metadata;
+use objroot::ObjRoot;
+use overrides::Overrides;
+use prefix::ThreadPrefix;
+use redact::Redactor;
 use regex::Regex;
+use reroot::RootMapping;
+use sanitize::Sanitizer;
+use scratch::Scratch;
+use sink::SinkKind;
 use std::{
-    collections::{HashMap, HashSet},
-    fs, mem,
-    path::{self, PathBuf},
+    collections::{BTreeSet, HashMap, HashSet},
+    fs,
+    hash::{DefaultHasher, Hash, Hasher},
+    mem,
+    path::{Path, PathBuf},
+    process,
+    rc::Rc,
+    thread,
+    time::{Duration, Instant},
 };
+use store::Store;
+use timings::{Stage, Timings};
+use trace::{LineKind, ParseTrace};
+
+/// How many changed files --check lists
+const CHANGES_LISTED: usize = 20;
+
+/// How often progress is saved to the journal while parsing
+const CHECKPOINT_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Extensions cl.exe compiles as C or C++ without needing /Tp or /Tc.
+const SOURCE_EXTENSIONS: &[&str] = &["c", "cpp", "cc", "cxx"];
+
+/// Extensions of bare inputs that cl.exe passes through to the linker.
+const NON_SOURCE_EXTENSIONS: &[&str] = &["obj", "lib", "res", "def", "exp", "pdb"];
+
+fn has_extension(file: &str, extensions: &[impl AsRef<str>]) -> bool {
+    Path::new(file)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| {
+            extensions
+                .iter()
+                .any(|e| e.as_ref().eq_ignore_ascii_case(ext))
+        })
+}
 
 #[derive(PartialOrd, Ord, PartialEq, Eq, Hash)]
 struct RawCommand {
     dir: PathBuf,
     lines: Vec<String>,
+    compiler: Rc<Compiler>,
 }
 
 impl RawCommand {
     fn full_command(&self) -> String {
-        self.lines.join(" ")
+        let mut command = String::new();
+        for line in &self.lines {
+            // build.exe hard-wraps very long lines, sometimes in the middle of a quoted path. If
+            // the command so far has an open quote, this line continues the same token.
+            if !command.is_empty() && !cmdline::has_unterminated_quote(&command) {
+                command.push(' ');
+            }
+            command.push_str(line);
+        }
+        command
     }
 
+    /// The source files the command compiles.
     fn source_files(&self) -> Vec<String> {
-        let mut source_files = Vec::new();
-        for line in &self.lines {
-            for token in line.split_whitespace() {
-                if token.ends_with(".cpp") || token.ends_with(".c") {
-                    source_files.push(token.to_string());
+        self.inputs()
+            .into_iter()
+            .filter(|(token, known)| *known || self.dir.join(token).is_file())
+            .map(|(token, _)| token)
+            .collect()
+    }
+
+    /// The inputs that may be source files, each with whether it's known to be one from the
+    /// command alone. The rest are sources only if they exist.
+    fn inputs(&self) -> Vec<(String, bool)> {
+        let mut inputs = Vec::new();
+        // The first token is the compiler itself
+        let dialect = self.compiler.dialect;
+        let mut tokens = cmdline::split(&self.full_command()).into_iter().skip(1);
+        while let Some(token) = tokens.next() {
+            if let Some(flag) = dialect.flag(&token) {
+                if dialect == Dialect::Msvc {
+                    // Everything after /link is passed to the linker
+                    if flag.eq_ignore_ascii_case("link") {
+                        break;
+                    }
+
+                    // /Tp and /Tc name a source file regardless of its extension, with or without
+                    // a space before the file name. /TP and /TC (upper case) take no file name.
+                    if let Some(file) = flag.strip_prefix("Tp").or_else(|| flag.strip_prefix("Tc"))
+                    {
+                        if file.is_empty() {
+                            inputs.extend(tokens.next().map(|file| (file, true)));
+                        } else {
+                            inputs.push((file.to_string(), true));
+                        }
+                        continue;
+                    }
                 }
+                if dialect.takes_separate_value(flag) {
+                    tokens.next();
+                }
+            } else if token.starts_with('@') {
+                // Response file
+            } else if self.compiler.is_source(&token) {
+                inputs.push((token, true));
+            } else if !has_extension(&token, NON_SOURCE_EXTENSIONS) {
+                // A bare input with an unusual extension is still a source if it exists
+                inputs.push((token, false));
             }
         }
-        source_files
+        inputs
     }
 }
 
-#[derive(serde::Serialize, serde::Deserialize)]
+#[derive(serde::Serialize, serde::Deserialize, Clone)]
+#[serde(from = "StoredEntry")]
 struct CompileCommandsEntry {
     directory: PathBuf,
     command: String,
     file: String,
+    /// The environment the command ran with. Not part of the compile_commands.json format, so
+    /// only written with --emit-env.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    environment: Option<environment::Environment>,
+}
+
+/// An entry as read back, whose command may have been written as `arguments` instead.
+#[derive(serde::Deserialize)]
+struct StoredEntry {
+    directory: PathBuf,
+    #[serde(default)]
+    command: Option<String>,
+    #[serde(default)]
+    arguments: Option<Vec<String>>,
+    file: String,
+    #[serde(default)]
+    environment: Option<environment::Environment>,
+}
+
+impl From<StoredEntry> for CompileCommandsEntry {
+    fn from(stored: StoredEntry) -> Self {
+        CompileCommandsEntry {
+            directory: stored.directory,
+            command: stored
+                .command
+                .unwrap_or_else(|| cmdline::join(&stored.arguments.unwrap_or_default())),
+            file: stored.file,
+            environment: stored.environment,
+        }
+    }
 }
 
 impl CompileCommandsEntry {
+    /// Whether `self` and `other` are the same entry, even if their commands are quoted
+    /// differently, such as after a round trip through the `arguments` form.
+    fn same_as(&self, other: &CompileCommandsEntry) -> bool {
+        self.file == other.file
+            && self.directory == other.directory
+            && self.environment == other.environment
+            && (self.command == other.command
+                || cmdline::split(&self.command) == cmdline::split(&other.command))
+    }
+
     fn from_raw_command(command: &RawCommand) -> impl Iterator<Item = CompileCommandsEntry> {
         let full_command = command.full_command();
         let source_files = command.source_files();
         source_files.into_iter().map(move |source_file| {
             let joined = command.dir.join(&source_file);
-            let absolute = path::absolute(&joined)
-                .unwrap_or_else(|_| panic!("Failed to resolve path for {}", joined.display()))
-                .to_string_lossy()
-                .to_string();
+            let absolute = paths::absolute(&joined).to_string_lossy().to_string();
             CompileCommandsEntry {
                 directory: command.dir.clone(),
                 command: full_command.clone(),
                 file: absolute,
+                environment: None,
             }
         })
     }
+
+    /// The object file the command writes. A /Fo value ending in a path separator names a
+    /// directory, a /Fo value without an extension gets .obj appended, and without /Fo the object
+    /// file goes in the working directory.
+    fn object_file(&self) -> PathBuf {
+        let object_flag = object_flag(&self.command);
+        let stem = Path::new(&self.file).file_stem().unwrap_or_default();
+        let default_name = Path::new(stem).with_extension("obj");
+        match object_flag {
+            Some(dir) if dir.ends_with(['\\', '/']) => self.directory.join(dir).join(default_name),
+            Some(file) if Path::new(&file).extension().is_none() => {
+                self.directory.join(file).with_extension("obj")
+            }
+            Some(file) => self.directory.join(file),
+            None => self.directory.join(default_name),
+        }
+    }
+}
+
+/// The value of the last /Fo flag in `command`, naming the object file or directory it writes to.
+fn object_flag(command: &str) -> Option<String> {
+    output_flag(command, "Fo")
+}
+
+/// The value of the last of `command`'s compiler flags naming an output, like /Fo or /Fe, which
+/// can be written `/Fovalue`, `/Fo:value` or `/Fo: value`.
+fn output_flag(command: &str, name: &str) -> Option<String> {
+    let mut output_flag = None;
+    let mut tokens = cmdline::split(command).into_iter().skip(1);
+    while let Some(token) = tokens.next() {
+        let Some(flag) = token.strip_prefix(['/', '-']) else {
+            continue;
+        };
+        if flag.eq_ignore_ascii_case("link") {
+            break;
+        }
+        if let Some(value) = flag.strip_prefix(name) {
+            let value = value.strip_prefix(':').unwrap_or(value);
+            output_flag = if value.is_empty() {
+                tokens.next()
+            } else {
+                Some(value.to_string())
+            };
+        }
+    }
+    output_flag
+}
+
+/// Finds object files written by more than one source file, which means the build is
+/// misconfigured. Returns each such object file with the files that write it.
+fn duplicate_outputs(entries: &[CompileCommandsEntry]) -> Vec<(PathBuf, Vec<&str>)> {
+    let mut by_object: HashMap<String, (PathBuf, Vec<&str>)> = HashMap::new();
+    for entry in entries {
+        let object = entry.object_file();
+        // Object paths are compared case-insensitively, as they are on Windows
+        let key = object.to_string_lossy().to_lowercase();
+        by_object
+            .entry(key)
+            .or_insert_with(|| (object, Vec::new()))
+            .1
+            .push(&entry.file);
+    }
+    let mut duplicates: Vec<_> = by_object
+        .into_values()
+        .filter(|(_, files)| files.len() > 1)
+        .collect();
+    duplicates.sort();
+    duplicates
+}
+
+/// A compiler invocation as it appeared in the log.
+struct LoggedCommand {
+    compiler: Rc<Compiler>,
+    thread: String,
+    /// Line number the command started on
+    line_number: usize,
+    /// The build pass the command ran in
+    pass: u32,
+    /// The directory the thread was processing, if a banner for it had been seen
+    dir: Option<PathBuf>,
+    /// The launcher the command was wrapped in, if any
+    launcher: Option<String>,
+    lines: Vec<String>,
+}
+
+impl LoggedCommand {
+    /// The directory of the first source file the command names by absolute path, if any.
+    fn infer_dir(&self) -> Option<PathBuf> {
+        cmdline::split(&self.lines.join(" "))
+            .iter()
+            .skip(1)
+            .map(Path::new)
+            .find(|token| token.is_absolute() && self.compiler.is_source(&token.to_string_lossy()))
+            .and_then(Path::parent)
+            .map(Path::to_path_buf)
+    }
+
+    /// The source directory one of the `objroots` rules maps the command's object directory to,
+    /// if any.
+    fn infer_dir_from_object(&self, objroots: &[ObjRoot]) -> Option<PathBuf> {
+        if objroots.is_empty() || self.compiler.dialect != Dialect::Msvc {
+            return None;
+        }
+        let object = object_flag(&self.lines.join(" "))?;
+        let object_dir = match object.strip_suffix(['\\', '/']) {
+            Some(dir) => dir,
+            None => object.rsplit_once(['\\', '/'])?.0,
+        };
+        objroot::infer(objroots, object_dir)
+    }
+
+    fn into_raw_command(self) -> RawCommand {
+        let dir = self
+            .dir
+            .unwrap_or_else(|| panic!("Unable to determine directory for thread {}", self.thread));
+        RawCommand {
+            dir,
+            lines: self.lines,
+            compiler: self.compiler,
+        }
+    }
 }
 
-fn get_raw_commands(log: String) -> HashSet<RawCommand> {
-    let mut raw_commands: HashSet<RawCommand> = HashSet::new();
-
-    let dir_regexes = vec![
-        Regex::new(r"^(\d{4})>BUILDMSG: Processing (.+)$").unwrap(),
-        Regex::new(r"^(\d{4})>Compiling (.+) \*+$").unwrap(),
-    ];
-
-    let mut dirs: HashMap<String, PathBuf> = HashMap::new();
-
-    enum State {
-        LookingForCommand,
-        ReadingCommand,
-    }
-    let command_re = Regex::new(r"^(\d{4})>cl\s").unwrap();
-    let mut state = State::LookingForCommand;
-    let mut cur_command = Vec::new();
-    let mut command_prefix = String::new();
-    let mut cur_thread = String::new();
-    for line in log.lines() {
-        match state {
-            State::LookingForCommand => {
-                // Does this line begin a compilation command?
-                if let Some(caps) = command_re.captures(line) {
-                    let thread = caps.get(1).unwrap().as_str();
-                    cur_thread = thread.to_string();
-                    command_prefix = format!("{}>   ", thread);
-                    cur_command.push(line[5..].trim().to_string());
-                    state = State::ReadingCommand;
+enum ParserState {
+    LookingForCommand,
+    ReadingCommand,
+}
+
+/// Incrementally extracts compiler invocations from a build.exe log, one line at a time.
+struct LogParser {
+    thread_prefix: ThreadPrefix,
+    dir_regexes: Vec<Regex>,
+    pass_re: Regex,
+    /// `build /why` explaining that a target is out of date with respect to a dependency
+    why_out_of_date_re: Regex,
+    /// `build /why` explaining that a directory or target is up to date
+    why_up_to_date_re: Regex,
+    compilers: Vec<CompilerDefinition>,
+    launchers: Launchers,
+    context: ParseContext,
+    /// How many lines a thread's directory banner is trusted for. Past that, the thread may have
+    /// been reused after sitting idle, so a command's directory is inferred from the command
+    /// itself if possible.
+    banner_window: Option<usize>,
+    /// The directory commands ran in when their thread has no banner, for logs that build.exe
+    /// wrote into the directory they cover
+    default_dir: Option<PathBuf>,
+    /// Rules mapping object directories to the source directories they were built from
+    objroots: Vec<ObjRoot>,
+    /// Commands whose directory was inferred from the command, for lack of a recent banner
+    inferred_dirs: usize,
+    /// Commands attributed to a banner older than the window, because inference failed
+    stale_dirs: usize,
+    /// Only accept commands whose compiler exists on disk, as well as having a known name
+    verify_compilers: bool,
+    /// Lines naming a known compiler that doesn't exist on disk, with --verify-compilers
+    unverified_compilers: usize,
+    /// Commands a front end like nvcc ran on its own behalf, which were skipped
+    front_end_calls: usize,
+    /// What the last line fed to the parser was taken to be
+    line_kind: LineKind,
+    /// The line the command the last line completed started on, if it completed one
+    completed: Option<usize>,
+    state: ParserState,
+    cur_command: Vec<String>,
+    cur_line_number: usize,
+    cur_thread: String,
+    cur_compiler: Option<Rc<Compiler>>,
+    cur_launcher: Option<String>,
+}
+
+impl LogParser {
+    /// Creates a parser that recognizes the built-in compilers and `extra_compilers`, run directly
+    /// or through one of `launchers`, in lines prefixed with their thread by `thread_prefix`.
+    fn new(
+        extra_compilers: &[CompilerDefinition],
+        launchers: Launchers,
+        thread_prefix: ThreadPrefix,
+    ) -> Self {
+        LogParser {
+            thread_prefix,
+            // These match the rest of the line after the thread prefix
+            dir_regexes: vec![
+                // Pass logs qualify the banner with the pass, as `Processing (pass1) <dir>` or
+                // `Pass1: Processing <dir>`
+                Regex::new(r"^BUILDMSG: Processing (?:\(pass ?\d+\) )?(.+)$").unwrap(),
+                Regex::new(r"^BUILDMSG: Pass ?\d+: Processing (.+)$").unwrap(),
+                Regex::new(r"^Compiling (.+) \*+$").unwrap(),
+            ],
+            pass_re: Regex::new(r"(?i)^BUILD: .*\bpass ?(\d+)\b").unwrap(),
+            why_out_of_date_re: Regex::new(
+                r"^BUILD: (\S.*?) (?:is )?out of date with respect to (\S.*?)\.?$",
+            )
+            .unwrap(),
+            why_up_to_date_re: Regex::new(r"^BUILD: (\S.*?) is up to date\.?$").unwrap(),
+            compilers: CompilerDefinition::builtins()
+                .into_iter()
+                .chain(extra_compilers.iter().cloned())
+                .collect(),
+            launchers,
+            context: ParseContext::default(),
+            banner_window: None,
+            default_dir: None,
+            objroots: Vec::new(),
+            inferred_dirs: 0,
+            stale_dirs: 0,
+            verify_compilers: false,
+            unverified_compilers: 0,
+            front_end_calls: 0,
+            line_kind: LineKind::Ignored,
+            completed: None,
+            state: ParserState::LookingForCommand,
+            cur_command: Vec::new(),
+            cur_line_number: 0,
+            cur_thread: String::new(),
+            cur_compiler: None,
+            cur_launcher: None,
+        }
+    }
+
+    /// Whether the parser is between commands, i.e. all of its state is in `dirs`.
+    fn is_idle(&self) -> bool {
+        matches!(self.state, ParserState::LookingForCommand)
+    }
+
+    fn trace_state(&self) -> trace::State {
+        match self.state {
+            ParserState::LookingForCommand => trace::State::LookingForCommand,
+            ParserState::ReadingCommand => trace::State::ReadingCommand,
+        }
+    }
+
+    /// Feeds the next line of the log, returning a command once it has been read completely.
+    fn parse_line(&mut self, line_number: usize, line: &str) -> Option<LoggedCommand> {
+        self.completed = None;
+        match self.state {
+            ParserState::LookingForCommand => {
+                self.look_for_command(line_number, line);
+                None
+            }
+
+            ParserState::ReadingCommand => {
+                let (thread, payload) = self.thread_prefix.split(line);
+                if thread == Some(self.cur_thread.as_str()) && payload.starts_with("   ") {
+                    self.cur_command.push(payload.trim().to_string());
+                    self.line_kind = LineKind::Continuation;
+                    None
                 } else {
-                    // Check for messages that indicate a thread is processing a directory
-                    for dir_regex in &dir_regexes {
-                        if let Some(caps) = dir_regex.captures(line) {
-                            let number = caps.get(1).unwrap().as_str();
-                            let dir = caps.get(2).unwrap().as_str();
-                            dirs.insert(number.to_string(), PathBuf::from(dir));
-                            break;
-                        }
-                    }
+                    let command = self.take_command();
+                    // The line that ended the command may itself be a banner or another command
+                    self.look_for_command(line_number, line);
+                    self.unless_front_end_call(command)
                 }
             }
+        }
+    }
 
-            State::ReadingCommand => {
-                if line.starts_with(&command_prefix) {
-                    cur_command.push(line[5..].trim().to_string());
-                } else {
-                    let cur_dir = dirs.get(&cur_thread).unwrap_or_else(|| {
-                        panic!("Unable to determine directory for thread {}", cur_thread)
-                    });
-                    raw_commands.insert(RawCommand {
-                        dir: cur_dir.clone(),
-                        lines: mem::take(&mut cur_command),
-                    });
-                    state = State::LookingForCommand;
+    /// Ends the log, returning the command that was being read when it ended, if any. The log
+    /// may have been cut off partway through it, such as when the build was interrupted.
+    fn finish(&mut self) -> Option<LoggedCommand> {
+        match self.state {
+            ParserState::LookingForCommand => None,
+            ParserState::ReadingCommand => {
+                let command = self.take_command();
+                self.unless_front_end_call(command)
+            }
+        }
+    }
+
+    /// `command`, unless a front end like nvcc ran it on its own behalf, such as to preprocess a
+    /// .cu file or compile the host code generated from one.
+    fn unless_front_end_call(&mut self, command: LoggedCommand) -> Option<LoggedCommand> {
+        if compiler::is_front_end_internal(
+            &self.compilers,
+            &command.compiler,
+            &command.lines.join(" "),
+        ) {
+            self.front_end_calls += 1;
+            return None;
+        }
+        Some(command)
+    }
+
+    fn take_command(&mut self) -> LoggedCommand {
+        self.state = ParserState::LookingForCommand;
+        self.completed = Some(self.cur_line_number);
+        let mut command = LoggedCommand {
+            compiler: self.cur_compiler.take().unwrap(),
+            thread: self.cur_thread.clone(),
+            line_number: self.cur_line_number,
+            pass: self.context.pass,
+            dir: None,
+            launcher: self.cur_launcher.take(),
+            lines: mem::take(&mut self.cur_command),
+        };
+        command.dir = self.command_dir(&command);
+        command
+    }
+
+    /// The directory `command` ran in: its thread's directory if the banner for it is recent
+    /// enough, or else the directory inferred from the command's source files or its object
+    /// directory.
+    fn command_dir(&mut self, command: &LoggedCommand) -> Option<PathBuf> {
+        let dir = self.context.dirs.get(&command.thread);
+        let banner_line = self.context.banner_lines.get(&command.thread);
+        let recent = match (self.banner_window, banner_line) {
+            (Some(window), Some(banner_line)) => command.line_number - banner_line <= window,
+            _ => true,
+        };
+        if dir.is_some() && recent {
+            return dir.cloned();
+        }
+        if dir.is_none() && self.default_dir.is_some() {
+            return self.default_dir.clone();
+        }
+        if let Some(inferred) = command
+            .infer_dir()
+            .or_else(|| command.infer_dir_from_object(&self.objroots))
+        {
+            self.inferred_dirs += 1;
+            return Some(inferred);
+        }
+        if dir.is_some() {
+            self.stale_dirs += 1;
+        }
+        dir.cloned()
+    }
+
+    /// The compiler `program` invokes, if it's one of the known compilers (and, with
+    /// --verify-compilers, exists on disk). Only a known name is enough to start reading a
+    /// command, so that lines like `clean_target: ...` or tools whose names merely start with a
+    /// compiler's name aren't mistaken for one.
+    fn verified_compiler(&mut self, program: &str) -> Option<Rc<Compiler>> {
+        let compiler = self.compilers.iter().find(|c| c.matches(program))?;
+        if self.verify_compilers && !compiler::program_exists(program) {
+            self.unverified_compilers += 1;
+            return None;
+        }
+        Some(compiler.compiler.clone())
+    }
+
+    /// If `line` begins a compilation command, its thread, the launcher it's wrapped in (if any),
+    /// the command with the launcher peeled off, and the compiler it invokes.
+    fn command_start<'a>(
+        &mut self,
+        line: &'a str,
+    ) -> Option<(&'a str, Option<&'a str>, &'a str, Rc<Compiler>)> {
+        let (thread, payload) = self.thread_prefix.split(line);
+        let thread = thread?;
+        if payload.starts_with(char::is_whitespace) {
+            return None;
+        }
+        let (launcher, command) = self.launchers.peel(payload);
+        // The compiler's path may be quoted, and have spaces in it, as under Program Files
+        let mut args = cmdline::args(command);
+        let program = args.next()?;
+        args.next()?;
+        let compiler = self.verified_compiler(&program)?;
+        Some((thread, launcher, command, compiler))
+    }
+
+    fn look_for_command(&mut self, line_number: usize, line: &str) {
+        self.line_kind = LineKind::Ignored;
+        // Does this line begin a compilation command?
+        if let Some((thread, launcher, command, compiler)) = self.command_start(line) {
+            self.line_kind = LineKind::CommandStart;
+            self.cur_compiler = Some(compiler);
+            self.cur_launcher = launcher.map(str::to_string);
+            self.cur_thread = thread.to_string();
+            self.cur_line_number = line_number;
+            self.cur_command.push(command.trim().to_string());
+            self.state = ParserState::ReadingCommand;
+            return;
+        }
+        let (thread, payload) = self.thread_prefix.split(line);
+        if let Some(caps) = self.pass_re.captures(payload) {
+            self.line_kind = LineKind::Pass;
+            self.context.pass = caps[1].parse().unwrap_or(self.context.pass);
+        } else if let Some(caps) = self.why_out_of_date_re.captures(payload) {
+            self.line_kind = LineKind::WhyOutOfDate;
+            // The source a target depends on shows where its thread is working, which helps for
+            // threads whose banner scrolled by before the log was captured
+            let source = [&caps[1], &caps[2]]
+                .into_iter()
+                .find(|path| has_extension(path, SOURCE_EXTENSIONS))
+                .and_then(|source| Path::new(source).parent());
+            if let (Some(thread), Some(dir)) = (thread, source)
+                && dir.is_absolute()
+                && !self.context.dirs.contains_key(thread)
+            {
+                let dir = self.context.spelling(dir.to_path_buf());
+                self.context.dirs.insert(thread.to_string(), dir);
+            }
+        } else if let Some(caps) = self.why_up_to_date_re.captures(payload) {
+            self.line_kind = LineKind::WhyUpToDate;
+            let path = self.context.spelling(banner_dir(&caps[1]));
+            self.context.up_to_date.insert(path);
+        } else if let Some(number) = thread {
+            // Check for messages that indicate a thread is processing a directory
+            for dir_regex in &self.dir_regexes {
+                if let Some(caps) = dir_regex.captures(payload) {
+                    let dir = self
+                        .context
+                        .spelling(banner_dir(caps.get(1).unwrap().as_str()));
+                    self.context.dirs.insert(number.to_string(), dir.clone());
+                    self.context
+                        .banner_lines
+                        .insert(number.to_string(), line_number);
+                    self.context.banner_dirs.insert(dir);
+                    self.line_kind = LineKind::Banner;
+                    break;
                 }
             }
         }
     }
-    raw_commands
 }
 
+/// `path` rewritten by the --source-root-map mappings in `options`, as generating would.
+fn reroot(path: &Path, options: &GenerateOptions) -> PathBuf {
+    options
+        .source_root_map
+        .iter()
+        .fold(path.to_path_buf(), |path, mapping| {
+            mapping.apply_to_path(&path)
+        })
+}
+
+/// A parser for the log at `absolute_log_path`, set up the way `options` asks, starting from
+/// `context`.
+fn log_parser(
+    absolute_log_path: &Path,
+    options: &GenerateOptions,
+    context: ParseContext,
+) -> LogParser {
+    let mut parser = LogParser::new(
+        &options.compilers,
+        options.launchers.clone(),
+        options.thread_prefix.clone(),
+    );
+    parser.context = context;
+    parser.banner_window = options.banner_window;
+    parser.verify_compilers = options.verify_compilers;
+    parser.objroots = options.objroots.clone();
+    if options.logs_in_dirs {
+        parser.default_dir = absolute_log_path.parent().map(Path::to_path_buf);
+    }
+    parser
+}
+
+/// Every command the log at `main_log_path` and the pass logs `options` asks for logged, read and
+/// parsed the way generating does, for the subcommands that look back at the log.
+fn logged_commands(main_log_path: &Path, options: &GenerateOptions) -> Vec<LoggedCommand> {
+    let mut logged_commands = Vec::new();
+    let mut dirs = HashMap::new();
+    for (pass, log_path) in log_group(main_log_path, options) {
+        let context = ParseContext {
+            dirs,
+            pass: pass.unwrap_or_default(),
+            ..ParseContext::default()
+        };
+        let mut parser = log_parser(&paths::absolute(&log_path), options, context);
+        let mut sanitizer = Sanitizer::new(&options.thread_prefix);
+        let mut line_number = 0;
+        LogFile::open(&log_path, options.io_mode).for_each_line(0, |line| {
+            line_number += 1;
+            sanitizer.sanitize(line.trim_end_matches(['\r', '\n']), |line| {
+                logged_commands.extend(parser.parse_line(line_number, line));
+            });
+        });
+        if !options.drop_partial {
+            logged_commands.extend(parser.finish());
+        }
+        dirs = parser.context.dirs;
+    }
+    logged_commands
+}
+
+/// The directory a banner names. Paths with spaces (like user profiles) may be quoted, and
+/// trailing whitespace isn't part of the path.
+fn banner_dir(captured: &str) -> PathBuf {
+    let dir = captured.trim();
+    let dir = dir
+        .strip_prefix('"')
+        .and_then(|dir| dir.strip_suffix('"'))
+        .unwrap_or(dir);
+    PathBuf::from(dir)
+}
+
+/// Reads the compile commands at `path`, or returns no commands if it doesn't exist.
+fn read_compile_commands(path: &Path) -> Vec<CompileCommandsEntry> {
+    if !path.exists() {
+        return Vec::new();
+    }
+    let existing_json = fs::read_to_string(path).unwrap_or_else(|_| {
+        panic!(
+            "Failed to read existing compile commands from {}",
+            path.display()
+        )
+    });
+    serde_json::from_str(&existing_json).unwrap_or_else(|_| {
+        panic!(
+            "Failed to parse existing compile commands from {}",
+            path.display()
+        )
+    })
+}
+
+/// Merges newly seen commands, each with the time it was seen, into the existing commands, keeping
+/// `metadata` up to date.
 fn merge_new_compile_commands(
     existing: Vec<CompileCommandsEntry>,
-    new: Vec<CompileCommandsEntry>,
+    new: Vec<(CompileCommandsEntry, u64)>,
+    metadata: &mut Metadata,
+    key_case: KeyCase,
 ) -> Vec<CompileCommandsEntry> {
     let mut by_file: HashMap<String, CompileCommandsEntry> = HashMap::new();
     // Add existing to the map before new, so that new commands will overwrite existing ones for
     // the same file
     // This also works to deduplicate
-    for command in existing.into_iter().chain(new.into_iter()) {
-        // TODO: also check if the file exists on disk to remove stale entries
-        by_file.insert(command.file.clone(), command);
+    for command in existing {
+        by_file.insert(key_case.key(&command.file), command);
+    }
+    for (command, seen) in new {
+        let key = key_case.key(&command.file);
+        let previous_file = by_file.get(&key).map(|previous| previous.file.clone());
+        // An entry takes the spelling of the command that replaces it, and its metadata goes
+        // with it
+        if let Some(previous_file) = &previous_file
+            && *previous_file != command.file
+            && let Some(moved) = metadata.entries.remove(previous_file)
+        {
+            if seen < moved.last_seen {
+                metadata.entries.insert(previous_file.clone(), moved);
+                continue;
+            }
+            metadata.entries.insert(command.file.clone(), moved);
+        }
+        let entry_metadata = metadata.entries.entry(command.file.clone()).or_default();
+        // A command from a log older than the one the entry was last seen in is stale, even if it
+        // comes later in this run
+        if seen < entry_metadata.last_seen && previous_file.is_some() {
+            continue;
+        }
+        entry_metadata.last_seen = seen;
+        entry_metadata.templated_from = None;
+        entry_metadata.warnings = 0;
+        entry_metadata.errors = 0;
+        entry_metadata.launcher = None;
+        by_file.insert(key, command);
     }
+    let files: HashSet<&str> = by_file.values().map(|entry| entry.file.as_str()).collect();
+    metadata
+        .entries
+        .retain(|file, _| files.contains(file.as_str()));
     by_file.into_values().collect()
 }
 
+/// Whether `file` is a generated source, in an object directory, which a clean build deletes
+/// until it generates it again.
+fn is_generated(file: &Path) -> bool {
+    file.parent().and_then(object_dir_parent).is_some()
+}
+
+/// Removes the entries under `only` whose source files don't exist. Generated sources are kept
+/// until they've been missing for more than `grace_runs` runs in a row, which their metadata
+/// counts. Returns how many entries were removed and how many missing ones were kept.
+fn prune_missing(
+    compile_commands: &mut Vec<CompileCommandsEntry>,
+    metadata: &mut Metadata,
+    grace_runs: u32,
+    only: Option<&Path>,
+    key_case: KeyCase,
+) -> (usize, usize) {
+    let mut missing = HashSet::new();
+    let mut kept = 0;
+    for entry in compile_commands.iter() {
+        if only.is_some_and(|only| !is_under(&entry.file, only, key_case)) {
+            continue;
+        }
+        let file = entry.directory.join(&entry.file);
+        let entry_metadata = metadata.entries.entry(entry.file.clone()).or_default();
+        if file.is_file() {
+            entry_metadata.missing_runs = 0;
+            continue;
+        }
+        entry_metadata.missing_runs += 1;
+        if is_generated(&file) && entry_metadata.missing_runs <= grace_runs {
+            kept += 1;
+        } else {
+            missing.insert(entry.file.clone());
+        }
+    }
+    compile_commands.retain(|entry| !missing.contains(&entry.file));
+    metadata.entries.retain(|file, _| !missing.contains(file));
+    (missing.len(), kept)
+}
+
 #[derive(clap::Parser)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// Path to the config file
+    #[arg(long, global = true, default_value_t = String::from(config::DEFAULT_PATH))]
+    config: String,
+
+    /// Fail unless this is the given version of the tool. Leading components are enough, so 1.2
+    /// allows any 1.2.x.
+    #[arg(long, global = true, value_name = "VERSION")]
+    require_version: Option<String>,
+
+    /// How entries are matched up by file, when merging, pruning, querying and reporting changes.
+    /// The entries keep the file's spelling either way.
+    #[arg(long, global = true, value_enum, default_value_t)]
+    key_case: KeyCase,
+
+    #[command(flatten)]
+    args: Args,
+}
+
+#[derive(clap::Subcommand)]
+enum Command {
+    /// Report on the entries in an existing compile_commands.json
+    Analyze {
+        #[command(subcommand)]
+        analysis: Analysis,
+    },
+
+    /// Check that each entry's source and relative include directories resolve against its
+    /// directory, to catch commands attributed to the wrong directory
+    Audit {
+        /// Path to the directory containing compile_commands.json
+        #[arg(short, long, default_value_t = String::from("."))]
+        output_dir: String,
+
+        /// How many relative include directories of each entry to check
+        #[arg(long, default_value_t = 3)]
+        sample: usize,
+    },
+
+    /// Compare the database with one made another way, such as by Bear or compiledb, and report
+    /// the files whose entries mean something different. Exits with 1 if any do.
+    Compare {
+        /// Path to the directory containing compile_commands.json
+        #[arg(short, long, default_value_t = String::from("."))]
+        output_dir: String,
+
+        /// The other database
+        #[arg(long, value_name = "DATABASE")]
+        against: PathBuf,
+    },
+
+    /// Write a compile_flags.txt with the flags every entry under a directory passes, for tools
+    /// that want one set of flags rather than a compile_commands.json
+    CompileFlags {
+        /// Path to the directory containing compile_commands.json
+        #[arg(short, long, default_value_t = String::from("."))]
+        output_dir: String,
+
+        /// The directory whose entries to take the flags from, and to write compile_flags.txt in,
+        /// instead of the output directory
+        #[arg(long, value_name = "DIR")]
+        root: Option<PathBuf>,
+    },
+
+    /// Explain why a source file does or doesn't have an entry
+    Explain {
+        /// The source file to explain
+        file: String,
+
+        /// Path to the build.exe log file (such as buildfre.log)
+        log_path: String,
+
+        /// Path to the directory containing compile_commands.json
+        #[arg(short, long, default_value_t = String::from("."))]
+        output_dir: String,
+
+        /// The --only directory of the run to explain
+        #[arg(long, value_name = "DIR")]
+        only: Option<PathBuf>,
+
+        /// The --prune-older-than age of the run to explain
+        #[arg(long, value_name = "AGE", value_parser = metadata::parse_age)]
+        prune_older_than: Option<Duration>,
+
+        /// Whether the run to explain used --prune-missing
+        #[arg(long)]
+        prune_missing: bool,
+
+        /// The --missing-grace-runs of the run to explain
+        #[arg(
+            long,
+            value_name = "RUNS",
+            default_value_t = 3,
+            requires = "prune_missing"
+        )]
+        missing_grace_runs: u32,
+
+        #[command(flatten)]
+        log_args: LogArgs,
+    },
+
+    /// Set up a config file, .clangd, .ccdbignore and VS Code settings in the current directory
+    Init,
+
+    /// Make Razzle run this tool after every build, updating the config's projects if there's a
+    /// config file, or else the database in --output-dir from the build's log
+    InstallHook {
+        /// The shell the Razzle window runs
+        #[arg(long, value_enum, default_value = "cmd")]
+        shell: hook::Shell,
+
+        /// Where to install the hook, instead of the Razzle profile directory (%INIT%)
+        #[arg(long, value_name = "DIR")]
+        hook_dir: Option<PathBuf>,
+
+        /// Path to the directory where the hook should update compile_commands.json
+        #[arg(short, long, default_value_t = String::from("."))]
+        output_dir: String,
+    },
+
+    /// List the entries' directories, or the targets they build, each with a stable id to shard
+    /// per-component jobs by
+    ListDirs {
+        /// Path to the directory containing compile_commands.json
+        #[arg(short, long, default_value_t = String::from("."))]
+        output_dir: String,
+
+        /// What to group the entries by
+        #[arg(long, value_enum, default_value_t)]
+        by: group::GroupBy,
+
+        /// Print the groups, with their files, as JSON
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Publish the database, with its sidecar, to a shared directory (such as a UNC path) or an
+    /// HTTP endpoint for others to fetch
+    Publish {
+        /// Path to the directory containing compile_commands.json
+        #[arg(short, long, default_value_t = String::from("."))]
+        output_dir: String,
+
+        /// The directory or http(s) URL to publish to
+        #[arg(long, value_name = "LOCATION")]
+        to: String,
+
+        /// The enlistment the database's paths are in, instead of %SDXROOT%
+        #[arg(long, value_name = "DIR")]
+        source_root: Option<PathBuf>,
+    },
+
+    /// Fetch the latest published database, re-root it onto the local enlistment and merge it
+    /// into the local database
+    Fetch {
+        /// Path to the directory containing the compile_commands.json to merge into
+        #[arg(short, long, default_value_t = String::from("."))]
+        output_dir: String,
+
+        /// The directory or http(s) URL the database was published to
+        #[arg(long, value_name = "LOCATION")]
+        from: String,
+
+        /// The local enlistment, instead of %SDXROOT%
+        #[arg(long, value_name = "DIR")]
+        local_root: Option<PathBuf>,
+    },
+
+    /// Show a source file's entry
+    Query {
+        /// The source file to show the entry of
+        file: String,
+
+        /// Path to the directory containing compile_commands.json
+        #[arg(short, long, default_value_t = String::from("."))]
+        output_dir: String,
+
+        /// Path to the build.exe log the entry was generated from, to trace flags back to it
+        #[arg(long)]
+        log: Option<String>,
+
+        /// Report where this flag in the entry came from: the log, a response file, the overrides
+        /// file, or the tool itself
+        #[arg(long, value_name = "FLAG", allow_hyphen_values = true)]
+        trace_flag: Option<String>,
+
+        #[command(flatten)]
+        log_args: LogArgs,
+    },
+
+    /// Regenerate compile_commands.json from the SQLite store kept by --sqlite-store
+    Export {
+        /// Path to the directory containing compile_commands.sqlite
+        #[arg(short, long, default_value_t = String::from("."))]
+        output_dir: String,
+
+        /// Write each command as an `arguments` array instead of a `command` string
+        #[arg(long)]
+        arguments: bool,
+    },
+
+    /// Show statistics about an existing compile_commands.json
+    Stats {
+        /// Path to the directory containing compile_commands.json
+        #[arg(short, long, default_value_t = String::from("."))]
+        output_dir: String,
+
+        /// List the entries the compiler reported warnings or errors for
+        #[arg(long)]
+        with_diagnostics: bool,
+    },
+}
+
+#[derive(clap::Subcommand)]
+enum Analysis {
+    /// Report the flags every entry passes, the flags that vary across the tree, and the
+    /// directories whose flags differ from what most entries pass
+    Flags {
+        /// Path to the directory containing compile_commands.json
+        #[arg(short, long, default_value_t = String::from("."))]
+        output_dir: String,
+
+        /// Also write the report as JSON to this file
+        #[arg(long, value_name = "PATH")]
+        json: Option<PathBuf>,
+
+        /// Compress the JSON report
+        #[arg(long, value_enum, requires = "json")]
+        compress: Option<Compression>,
+    },
+}
+
+/// How the run that generated the database read its log, for the subcommands that read the log
+/// again.
+#[derive(clap::Args)]
+struct LogArgs {
+    /// The --io-mode of the run
+    #[arg(long, value_enum, default_value_t)]
+    io_mode: IoMode,
+
+    /// The --banner-window of the run
+    #[arg(long, value_name = "LINES")]
+    banner_window: Option<usize>,
+
+    /// Whether the run used --verify-compilers
+    #[arg(long)]
+    verify_compilers: bool,
+
+    /// Whether the run used --drop-partial
+    #[arg(long)]
+    drop_partial: bool,
+
+    /// The --source-root-map mappings of the run
+    #[arg(long, value_name = "AGENT_PREFIX=LOCAL_PREFIX", value_parser = reroot::parse_mapping)]
+    source_root_map: Vec<RootMapping>,
+
+    /// Whether the run used --pass-logs
+    #[arg(long)]
+    pass_logs: bool,
+
+    /// Whether the run used --resolve-subst
+    #[arg(long)]
+    resolve_subst: bool,
+
+    /// Whether the log is one of the per-directory logs --per-dir-logs reads, whose commands ran
+    /// in the log's directory unless it says otherwise
+    #[arg(long)]
+    per_dir_log: bool,
+}
+
+impl LogArgs {
+    /// Options for reading the log the way the run did, with the parsing rules in `config`.
+    fn options(self, config: &config::Config, key_case: KeyCase) -> GenerateOptions {
+        GenerateOptions {
+            compilers: config.compilers(),
+            launchers: config.launchers(),
+            objroots: config.objroots(),
+            thread_prefix: config.thread_prefix(),
+            key_case,
+            io_mode: self.io_mode,
+            banner_window: self.banner_window,
+            verify_compilers: self.verify_compilers,
+            drop_partial: self.drop_partial,
+            pass_logs: self.pass_logs,
+            source_root_map: root_map(self.resolve_subst, &self.source_root_map),
+            logs_in_dirs: self.per_dir_log,
+            ..GenerateOptions::default()
+        }
+    }
+}
+
+#[derive(clap::Args)]
 struct Args {
     /// Path to a directory where compile_commands.json should be output or updated
     #[arg(short, long, default_value_t = String::from("."))]
     output_dir: String,
 
-    /// Path to the build.exe log file (such as buildfre.log)
-    log_path: String,
-}
+    /// How to read the log
+    #[arg(long, value_enum, default_value_t)]
+    io_mode: IoMode,
 
-fn main() {
-    let args = Args::parse();
-    let log_path = &args.log_path;
-    let output_dir = &args.output_dir;
+    /// Start over instead of resuming from the journal left behind by an interrupted run
+    #[arg(long)]
+    no_resume: bool,
 
-    let absolute_output_dir = path::absolute(output_dir)
-        .unwrap_or_else(|_| panic!("Failed to resolve path for {}", output_dir));
+    /// Fail instead of warning when the build looks misconfigured, such as when two source files
+    /// are compiled to the same object file
+    #[arg(long)]
+    strict: bool,
 
-    // TODO: handle the case where output_dir is a path to a file named compile_commands.json.
-    // in this case, check if the parent exists and is a dir.
-    if !absolute_output_dir.exists() {
-        panic!("Output directory doesn't exist!");
-    }
+    /// Synthesize entries for source files in directories that already have entries, but that
+    /// haven't been compiled yet, by cloning a sibling file's entry
+    #[arg(long)]
+    template_missing: bool,
 
-    if !absolute_output_dir.is_dir() {
-        panic!("Output directory exists, but is not a directory!");
-    }
+    /// Record which entries the compiler reported warnings or errors for, from the .wrn and .err
+    /// logs next to the build log
+    #[arg(long)]
+    diagnostics: bool,
 
-    let compile_commands_path = absolute_output_dir.join("compile_commands.json");
-    let log = fs::read_to_string(log_path)
-        .unwrap_or_else(|_| panic!("Failed to read build.exe log from {}", log_path));
+    /// Remove existing entries in directories the log rebuilt, but that didn't get a new command
+    /// in the log. Only use this with logs of full (not incremental) builds, since an incremental
+    /// build doesn't recompile every file in the directories it visits.
+    #[arg(long)]
+    gc_rebuilt_dirs: bool,
 
-    let compile_commands: Vec<CompileCommandsEntry> = get_raw_commands(log)
-        .iter()
-        .flat_map(CompileCommandsEntry::from_raw_command)
-        .collect();
+    /// Remove entries that no log has refreshed in this long, such as 30d (or s, m, h and w),
+    /// which are likely from components that were deleted or moved, even if a file by the same
+    /// name still exists
+    #[arg(long, value_name = "AGE", value_parser = metadata::parse_age)]
+    prune_older_than: Option<Duration>,
 
-    // Read in the existing compile commands, if it exists, and merge with the new commands
-    let existing_commands: Vec<CompileCommandsEntry> = if compile_commands_path.exists() {
-        let existing_json = fs::read_to_string(&compile_commands_path).unwrap_or_else(|_| {
-            panic!(
-                "Failed to read existing compile commands from {}",
-                compile_commands_path.display()
-            )
-        });
-        serde_json::from_str(&existing_json).unwrap_or_else(|_| {
-            panic!(
-                "Failed to parse existing compile commands from {}",
-                compile_commands_path.display()
-            )
-        })
-    } else {
-        Vec::new()
-    };
+    /// Remove entries whose source files no longer exist. Generated sources, in object
+    /// directories, are kept for --missing-grace-runs runs first, since a clean build deletes
+    /// them until it generates them again.
+    #[arg(long)]
+    prune_missing: bool,
 
-    println!(
-        "There are {} existing compile commands and {} new compile commands",
-        existing_commands.len(),
-        compile_commands.len()
-    );
+    /// How many runs in a row --prune-missing keeps the entry of a generated source that's missing
+    #[arg(
+        long,
+        value_name = "RUNS",
+        default_value_t = 3,
+        requires = "prune_missing"
+    )]
+    missing_grace_runs: u32,
 
-    let compile_commands = merge_new_compile_commands(existing_commands, compile_commands);
+    /// Only add, update and remove entries for files under this directory, leaving the rest of
+    /// the database as it is, for quickly regenerating one component
+    #[arg(long, value_name = "DIR")]
+    only: Option<PathBuf>,
 
-    // Write the compile commands to a JSON file
-    let json = serde_json::to_string_pretty(&compile_commands)
-        .expect("Failed to serialize compile commands to JSON");
-    fs::write(&compile_commands_path, json).unwrap_or_else(|_| {
-        panic!(
-            "Failed to write compile commands to {}",
-            compile_commands_path.display()
-        )
-    });
-    println!(
-        "Successfully wrote compile commands to {}",
-        compile_commands_path.display()
-    );
+    /// Rewrite paths starting with <AGENT_PREFIX> to start with <LOCAL_PREFIX> instead, for logs
+    /// from builds that ran on another machine. May be given more than once.
+    #[arg(long, value_name = "AGENT_PREFIX=LOCAL_PREFIX", value_parser = reroot::parse_mapping)]
+    source_root_map: Vec<RootMapping>,
+
+    /// Rewrite paths on subst'd and mapped network drives, like the W: drive a Razzle window
+    /// substs, to the paths the drives map to, so tools run outside the window can resolve them
+    #[arg(long)]
+    resolve_subst: bool,
+
+    /// Detect the source root the build ran in by matching the paths of its files against this
+    /// local enlistment, and rewrite paths to point into it
+    #[arg(long, value_name = "LOCAL_ROOT")]
+    detect_source_root: Option<PathBuf>,
+
+    /// Add an `environment` object with the INCLUDE, LIB, LIBPATH, CL and _CL_ variables to each
+    /// new entry. This isn't part of the compile_commands.json format, but some consumers use it.
+    #[arg(long)]
+    emit_env: bool,
+
+    /// Read the environment the build ran with, for --emit-env and for resolving
+    /// /external:env:<variable> flags, from this dump of `set` output from the build window
+    /// instead of using the current environment
+    #[arg(long)]
+    env_file: Option<PathBuf>,
+
+    /// Add the flags cl.exe took from the CL and _CL_ variables in the build environment (see
+    /// --env-file), which the log doesn't show, to new commands: CL's ahead of the command's own
+    /// arguments and _CL_'s after them
+    #[arg(long)]
+    capture_env: bool,
+
+    /// Add /std:<STD> (such as c++17) to C++ commands that don't choose a standard, so clangd
+    /// doesn't use its own default
+    #[arg(long, value_name = "STD")]
+    default_std: Option<String>,
+
+    /// Write compile_commands.json even if the output directory looks like an object directory,
+    /// where editors won't find it
+    #[arg(long)]
+    force: bool,
+
+    /// Adjust commands for clangd, removing flags it doesn't support yet (such as the C++ modules
+    /// flags /ifcOutput, /reference and /headerUnit)
+    #[arg(long)]
+    for_clangd: bool,
+
+    /// Rewrite the headers /FI forces and /Yu names as absolute paths, found the way cl.exe finds
+    /// them, so tools running the command from another directory find them too
+    #[arg(long)]
+    resolve_force_includes: bool,
+
+    /// Report how the database would change instead of writing it. Changes that only reorder or
+    /// repeat flags aren't counted.
+    #[arg(long)]
+    dry_run: bool,
+
+    /// Fail if the database isn't up to date with the log, without writing it. Changes that only
+    /// reorder or repeat flags don't count.
+    #[arg(long, conflicts_with = "dry_run")]
+    check: bool,
+
+    /// Keep an existing entry's command when the new one only reorders or repeats its flags, so
+    /// the database doesn't churn
+    #[arg(long)]
+    keep_equivalent: bool,
+
+    /// Only trust a thread's directory banner for this many lines. Commands further from their
+    /// thread's banner get their directory from their source file's path if it's absolute.
+    #[arg(long, value_name = "LINES")]
+    banner_window: Option<usize>,
+
+    /// Discard the command a log ends in, instead of keeping it with a warning. A log ends
+    /// partway through a command when the build was interrupted.
+    #[arg(long)]
+    drop_partial: bool,
+
+    /// Write each command as an `arguments` array instead of a `command` string
+    #[arg(long)]
+    arguments: bool,
+
+    /// Write editor configuration derived from the database into the current directory: the
+    /// VS Code C/C++ extension's c_cpp_properties.json or Visual Studio's CppProperties.json.
+    /// Repeat for both.
+    #[arg(long, value_enum, value_name = "EDITOR")]
+    emit_editor_config: Vec<EditorConfig>,
+
+    /// Also write compile_commands.priority.txt, listing the files in the order clangd's
+    /// background index should index them: recently compiled files first, then recently queried
+    /// ones
+    #[arg(long)]
+    index_priority: bool,
+
+    /// Report how long each stage of the run took (reading, parsing, resolving paths, merging,
+    /// response files and writing) and how many lines, commands and entries it handled
+    #[arg(long)]
+    timings: bool,
+
+    /// Also write the --timings report as JSON to this file
+    #[arg(long, value_name = "PATH", requires = "timings")]
+    timings_json: Option<PathBuf>,
+
+    /// Move the flags of commands longer than this many characters into a response file, for
+    /// consumers that re-run commands (CreateProcess allows at most 32767)
+    #[arg(long, value_name = "CHARS")]
+    max_command_length: Option<usize>,
+
+    /// How long to wait for another run writing the same database to finish, in seconds, before
+    /// giving up
+    #[arg(long, value_name = "SECONDS", default_value_t = lock::DEFAULT_TIMEOUT)]
+    lock_timeout: u64,
+
+    /// Only treat a line as a command if its compiler exists on disk, at the path the log names or
+    /// on PATH, as well as having a known name
+    #[arg(long)]
+    verify_compilers: bool,
+
+    /// Where to write the database. Repeat to write it to more than one place; the JSON file is
+    /// what the next run merges into.
+    #[arg(long, value_enum, default_value = "json-file")]
+    sink: Vec<SinkKind>,
+
+    /// The URL the http sink POSTs the database to
+    #[arg(long, value_name = "URL", required_if_eq("sink", "http"))]
+    sink_url: Option<String>,
+
+    /// Compress the metadata sidecar and the ndjson sink's file, which grow large on full-tree
+    /// runs. compile_commands.json itself is never compressed.
+    #[arg(long, value_enum)]
+    compress: Option<Compression>,
+
+    /// Keep the database in compile_commands.sqlite, merging into it and updating only the
+    /// entries that change, then export compile_commands.json from it
+    #[arg(long)]
+    sqlite_store: bool,
+
+    /// Which build's log to use when no log is given and both buildfre.log and buildchk.log are
+    /// in the current directory
+    #[arg(long, value_enum, default_value_t)]
+    prefer: Flavor,
+
+    /// When no log is given, write a database for each of buildfre.log and buildchk.log
+    /// (compile_commands.fre.json and compile_commands.chk.json), and point
+    /// compile_commands.json at the preferred one
+    #[arg(long, conflicts_with = "log_path")]
+    split_flavors: bool,
+
+    /// Cache resolved commands in this directory, so later runs over logs with mostly the same
+    /// commands only resolve the new ones
+    #[arg(long, value_name = "DIR")]
+    cache_dir: Option<PathBuf>,
+
+    /// Write how each line of the log was classified (banner, command start, continuation or
+    /// ignored) and what it did to the parser's state to this file, one JSON record per line, for
+    /// reporting lines the parser mishandles. Parses the whole log rather than resuming.
+    #[arg(long, value_name = "OUT.ndjson", conflicts_with = "all_projects")]
+    trace_parse: Option<PathBuf>,
+
+    /// Also parse the pass logs Razzle writes next to each log (buildfre_pass0.log and so on for
+    /// buildfre.log), after the log itself and in pass order, carrying over the directory each
+    /// build thread was in from one log to the next
+    #[arg(long)]
+    pass_logs: bool,
+
+    /// Instead of a single log, use every per-directory log (buildfre.log, or buildchk.log with
+    /// --prefer chk) under this directory, such as those written by build.exe -jpath. Commands in
+    /// each log ran in the log's directory unless the log says otherwise.
+    #[arg(long, value_name = "ROOT", conflicts_with_all = ["log_path", "split_flavors"])]
+    per_dir_logs: Option<PathBuf>,
+
+    /// Generate every project listed in the config file instead of a single log
+    #[arg(long)]
+    all_projects: bool,
+
+    /// With --all-projects, generate the projects in parallel
+    #[arg(long, requires = "all_projects")]
+    parallel: bool,
+
+    /// Path to the build.exe log file. If not given, buildfre.log or buildchk.log in the current
+    /// directory is used. A log rotated into chunks (buildfre.log.001, buildfre.log.002, ...) can
+    /// be given as the directory holding them, or as the name they share.
+    #[arg(conflicts_with = "all_projects")]
+    log_path: Option<String>,
+}
+
+fn main() {
+    let cli = Cli::parse();
+    if let Some(required) = &cli.require_version
+        && !metadata::version_matches(required)
+    {
+        panic!(
+            "Version {} is required, but this is version {}",
+            required,
+            metadata::VERSION
+        );
+    }
+    match cli.command {
+        Some(Command::Explain {
+            file,
+            log_path,
+            output_dir,
+            only,
+            prune_older_than,
+            prune_missing,
+            missing_grace_runs,
+            log_args,
+        }) => {
+            let config = config::load_optional(&cli.config);
+            let output = paths::absolute(&output_dir);
+            let options = GenerateOptions {
+                // The projects writing to the database exclude their own patterns
+                exclude: config
+                    .projects
+                    .iter()
+                    .filter(|project| paths::absolute(&project.output) == output)
+                    .flat_map(|project| project.exclude_patterns())
+                    .chain(config::ignore_patterns(&cli.config))
+                    .collect(),
+                scratch: config.scratch(&environment::read(None)),
+                only: only.as_deref().map(paths::absolute),
+                prune_older_than,
+                prune_missing: prune_missing.then_some(missing_grace_runs),
+                ..log_args.options(&config, cli.key_case)
+            };
+            explain::explain(&file, &log_path, &output_dir, &options)
+        }
+        Some(Command::Stats {
+            output_dir,
+            with_diagnostics,
+        }) => stats::stats(&output_dir, with_diagnostics),
+        Some(Command::Audit { output_dir, sample }) => audit::audit(&output_dir, sample),
+        Some(Command::Analyze {
+            analysis:
+                Analysis::Flags {
+                    output_dir,
+                    json,
+                    compress,
+                },
+        }) => analyze::flags_report(&output_dir, json.as_deref(), compress),
+        Some(Command::Compare {
+            output_dir,
+            against,
+        }) => compare::compare(&output_dir, &against),
+        Some(Command::CompileFlags { output_dir, root }) => {
+            fixed::write(&output_dir, root.as_deref(), cli.key_case)
+        }
+        Some(Command::Init) => init::init(),
+        Some(Command::InstallHook {
+            shell,
+            hook_dir,
+            output_dir,
+        }) => hook::install(shell, hook_dir.as_deref(), &cli.config, &output_dir),
+        Some(Command::ListDirs {
+            output_dir,
+            by,
+            json,
+        }) => group::list(&output_dir, by, json),
+        Some(Command::Export {
+            output_dir,
+            arguments,
+        }) => store::export(&output_dir, arguments, cli.key_case),
+        Some(Command::Publish {
+            output_dir,
+            to,
+            source_root,
+        }) => share::publish(&output_dir, &to, source_root.as_deref()),
+        Some(Command::Fetch {
+            output_dir,
+            from,
+            local_root,
+        }) => share::fetch(&output_dir, &from, local_root.as_deref(), cli.key_case),
+        Some(Command::Query {
+            file,
+            output_dir,
+            log,
+            trace_flag,
+            log_args,
+        }) => query::query(
+            &file,
+            &output_dir,
+            log.as_deref(),
+            trace_flag.as_deref(),
+            &log_args.options(&config::load_optional(&cli.config), cli.key_case),
+        ),
+        None if cli.args.all_projects => {
+            generate_all_projects(&cli.args, &cli.config, cli.key_case)
+        }
+        None => generate_logs(cli.args, &cli.config, cli.key_case),
+    }
+}
+
+/// Generates from the log on the command line, or from the buildfre.log and buildchk.log in the
+/// current directory if there isn't one.
+fn generate_logs(args: Args, config_path: &str, key_case: KeyCase) {
+    let config = config::load_optional(config_path);
+    let variables = environment::read(args.env_file.as_deref());
+    let mut options = GenerateOptions {
+        compilers: config.compilers(),
+        launchers: config.launchers(),
+        objroots: config.objroots(),
+        thread_prefix: config.thread_prefix(),
+        redactor: config.redactor(),
+        scratch: config.scratch(&variables),
+        key_case,
+        io_mode: args.io_mode,
+        no_resume: args.no_resume,
+        exclude: config::ignore_patterns(config_path),
+        strict: args.strict,
+        template_missing: args.template_missing,
+        diagnostics: args.diagnostics,
+        gc_rebuilt_dirs: args.gc_rebuilt_dirs,
+        prune_older_than: args.prune_older_than,
+        prune_missing: args.prune_missing.then_some(args.missing_grace_runs),
+        only: args.only.as_deref().map(paths::absolute),
+        source_root_map: root_map(args.resolve_subst, &args.source_root_map),
+        detect_source_root: args.detect_source_root,
+        environment: args.emit_env.then(|| environment::capture(&variables)),
+        capture_env: args.capture_env,
+        variables,
+        default_std: args.default_std,
+        force: args.force,
+        for_clangd: args.for_clangd,
+        resolve_force_includes: args.resolve_force_includes,
+        dry_run: args.dry_run,
+        check: args.check,
+        keep_equivalent: args.keep_equivalent,
+        banner_window: args.banner_window,
+        drop_partial: args.drop_partial,
+        verify_compilers: args.verify_compilers,
+        arguments: args.arguments,
+        max_command_length: args.max_command_length,
+        lock_timeout: Duration::from_secs(args.lock_timeout),
+        editor_configs: args.emit_editor_config,
+        index_priority: args.index_priority,
+        timings: args.timings,
+        sinks: args.sink,
+        sink_url: args.sink_url,
+        compress: args.compress,
+        sqlite_store: args.sqlite_store,
+        flavor: None,
+        cache_dir: args.cache_dir,
+        logs_in_dirs: args.per_dir_logs.is_some(),
+        pass_logs: args.pass_logs,
+        trace_parse: args.trace_parse,
+    };
+    if let Some(trace_path) = &options.trace_parse {
+        trace::reset(trace_path);
+    }
+    let output_dir = Path::new(&args.output_dir);
+
+    let summaries = if let Some(log_path) = args.log_path {
+        vec![generate(&[PathBuf::from(log_path)], output_dir, &options)]
+    } else if let Some(root) = &args.per_dir_logs {
+        let logs = flavor::discover_per_dir(root, args.prefer);
+        if logs.is_empty() {
+            panic!(
+                "There are no build{}.log files under {}",
+                args.prefer.name(),
+                root.display()
+            );
+        }
+        println!("Found {} per-directory logs", logs.len());
+        vec![generate(&logs, output_dir, &options)]
+    } else {
+        let logs = flavor::discover(Path::new("."));
+        if logs.is_empty() {
+            panic!("No log was given, and there's no buildfre.log or buildchk.log here");
+        }
+        if args.split_flavors {
+            let mut summaries = Vec::new();
+            for (flavor, log_path) in &logs {
+                println!("Generating the {} database", flavor.name());
+                options.flavor = Some(*flavor);
+                summaries.push(generate(
+                    std::slice::from_ref(log_path),
+                    output_dir,
+                    &options,
+                ));
+            }
+            if !options.dry_run && !options.check {
+                let preferred = logs
+                    .iter()
+                    .map(|(flavor, _)| *flavor)
+                    .find(|flavor| *flavor == args.prefer)
+                    .unwrap_or(logs[0].0);
+                flavor::link_preferred(output_dir, preferred);
+            }
+            summaries
+        } else {
+            let (flavor, log_path) = logs
+                .iter()
+                .find(|(flavor, _)| *flavor == args.prefer)
+                .unwrap_or(&logs[0]);
+            println!(
+                "Using {} from the {} build",
+                log_path.display(),
+                flavor.name()
+            );
+            vec![generate(
+                std::slice::from_ref(log_path),
+                output_dir,
+                &options,
+            )]
+        }
+    };
+    report_timings(args.timings, args.timings_json.as_deref(), &summaries);
+    exit_if_out_of_date(options.check, &summaries);
+}
+
+/// The --source-root-map mappings, after those for subst'd drives with --resolve-subst, since
+/// paths are translated off a subst'd drive before they're re-rooted.
+fn root_map(resolve_subst: bool, source_root_map: &[RootMapping]) -> Vec<RootMapping> {
+    let mut root_map = if resolve_subst {
+        subst::mappings()
+    } else {
+        Vec::new()
+    };
+    root_map.extend(source_root_map.iter().cloned());
+    root_map
+}
+
+/// With --timings, reports how long the runs took altogether.
+fn report_timings(timings: bool, json_path: Option<&Path>, summaries: &[Summary]) {
+    if !timings {
+        return;
+    }
+    let mut combined = Timings::default();
+    for summary in summaries {
+        combined.combine(&summary.timings);
+    }
+    combined.report(json_path);
+}
+
+/// With --check, exits with a failure if any database is out of date.
+fn exit_if_out_of_date(check: bool, summaries: &[Summary]) {
+    if check && summaries.iter().any(|summary| summary.out_of_date) {
+        println!("compile_commands.json is out of date, and needs to be regenerated");
+        process::exit(1);
+    }
+}
+
+/// Runs every project in the config, then reports a combined summary.
+fn generate_all_projects(args: &Args, config_path: &str, key_case: KeyCase) {
+    let config = config::load(config_path);
+    let variables = environment::read(args.env_file.as_deref());
+    let environment = args.emit_env.then(|| environment::capture(&variables));
+    let source_root_map = root_map(args.resolve_subst, &args.source_root_map);
+    let run = |project: &config::Project| {
+        println!("Generating {}", project.name());
+        let options = GenerateOptions {
+            compilers: config.compilers(),
+            launchers: config.launchers(),
+            objroots: config.objroots(),
+            thread_prefix: config.thread_prefix(),
+            redactor: config.redactor(),
+            scratch: config.scratch(&variables),
+            key_case,
+            io_mode: args.io_mode,
+            no_resume: args.no_resume,
+            exclude: [
+                project.exclude_patterns(),
+                config::ignore_patterns(config_path),
+            ]
+            .concat(),
+            strict: args.strict,
+            template_missing: args.template_missing,
+            diagnostics: args.diagnostics,
+            gc_rebuilt_dirs: args.gc_rebuilt_dirs,
+            prune_older_than: args.prune_older_than,
+            prune_missing: args.prune_missing.then_some(args.missing_grace_runs),
+            only: args.only.as_deref().map(paths::absolute),
+            source_root_map: source_root_map.clone(),
+            detect_source_root: args.detect_source_root.clone(),
+            environment: environment.clone(),
+            capture_env: args.capture_env,
+            variables: variables.clone(),
+            default_std: args.default_std.clone(),
+            force: args.force,
+            for_clangd: args.for_clangd,
+            resolve_force_includes: args.resolve_force_includes,
+            dry_run: args.dry_run,
+            check: args.check,
+            keep_equivalent: args.keep_equivalent,
+            banner_window: args.banner_window,
+            drop_partial: args.drop_partial,
+            verify_compilers: args.verify_compilers,
+            arguments: args.arguments,
+            max_command_length: args.max_command_length,
+            lock_timeout: Duration::from_secs(args.lock_timeout),
+            editor_configs: args.emit_editor_config.clone(),
+            index_priority: args.index_priority,
+            timings: args.timings,
+            sinks: args.sink.clone(),
+            sink_url: args.sink_url.clone(),
+            compress: args.compress,
+            sqlite_store: args.sqlite_store,
+            flavor: None,
+            cache_dir: args.cache_dir.clone(),
+            trace_parse: None,
+            logs_in_dirs: false,
+            pass_logs: args.pass_logs,
+        };
+        generate(&project.log_paths(), &project.output, &options)
+    };
+
+    let summaries: Vec<Summary> = if args.parallel {
+        thread::scope(|scope| {
+            let handles: Vec<_> = config
+                .projects
+                .iter()
+                .map(|project| scope.spawn(move || run(project)))
+                .collect();
+            handles
+                .into_iter()
+                .map(|handle| handle.join().expect("Project generation failed"))
+                .collect()
+        })
+    } else {
+        config.projects.iter().map(run).collect()
+    };
+
+    println!();
+    let row = |name: &str, summary: &Summary| {
+        println!(
+            "{:<24} {:>10} {:>10} {:>10} {:>10} {:>10} {:>10} {:>10} {:>10} {:>10}",
+            name,
+            summary.existing,
+            summary.new,
+            summary.excluded,
+            summary.scratch,
+            summary.added,
+            summary.updated,
+            summary.unchanged,
+            summary.removed,
+            summary.total
+        );
+    };
+    println!(
+        "{:<24} {:>10} {:>10} {:>10} {:>10} {:>10} {:>10} {:>10} {:>10} {:>10}",
+        "project",
+        "existing",
+        "new",
+        "excluded",
+        "scratch",
+        "added",
+        "updated",
+        "unchanged",
+        "removed",
+        "total"
+    );
+    let mut combined = Summary::default();
+    for (project, summary) in config.projects.iter().zip(&summaries) {
+        row(project.name(), summary);
+        combined.existing += summary.existing;
+        combined.new += summary.new;
+        combined.excluded += summary.excluded;
+        combined.scratch += summary.scratch;
+        combined.added += summary.added;
+        combined.updated += summary.updated;
+        combined.unchanged += summary.unchanged;
+        combined.removed += summary.removed;
+        combined.total += summary.total;
+    }
+    row("(all projects)", &combined);
+    report_timings(args.timings, args.timings_json.as_deref(), &summaries);
+    exit_if_out_of_date(args.check, &summaries);
+}
+
+/// Options controlling a generation run, from either the command line or a project config.
+#[derive(Default)]
+struct GenerateOptions {
+    /// How logs are read
+    io_mode: IoMode,
+    /// Compilers to recognize in addition to the built-in ones
+    compilers: Vec<CompilerDefinition>,
+    /// Programs that wrap the compiler, to peel off commands
+    launchers: Launchers,
+    /// Rules inferring a command's directory from its object directory
+    objroots: Vec<ObjRoot>,
+    /// The format of the prefix naming the thread that wrote each line
+    thread_prefix: ThreadPrefix,
+    /// Patterns for secrets to redact from commands
+    redactor: Redactor,
+    /// Which sources are scratch files that don't get entries
+    scratch: Scratch,
+    /// How entries are matched up by file
+    key_case: KeyCase,
+    /// Start over instead of resuming from a journal
+    no_resume: bool,
+    /// Files matching any of these patterns don't get entries
+    exclude: Vec<glob::Pattern>,
+    /// Fail instead of warning when the build looks misconfigured
+    strict: bool,
+    /// Synthesize entries for source files that don't have one yet
+    template_missing: bool,
+    /// Record diagnostics from the warning and error logs next to each log
+    diagnostics: bool,
+    /// Remove existing entries in directories the logs rebuilt that didn't get a new command
+    gc_rebuilt_dirs: bool,
+    /// Remove entries that no log has refreshed in this long
+    prune_older_than: Option<Duration>,
+    /// Remove entries whose source files are missing, keeping generated ones for this many runs
+    prune_missing: Option<u32>,
+    /// The directory to limit changes to, if any
+    only: Option<PathBuf>,
+    /// Rewrite paths from the machine the build ran on to point into the local enlistment
+    source_root_map: Vec<RootMapping>,
+    /// Detect a mapping to add to `source_root_map` by matching files against this directory
+    detect_source_root: Option<PathBuf>,
+    /// The environment to record in new entries, if any
+    environment: Option<environment::Environment>,
+    /// Add the flags from CL and _CL_ in `variables` to cl.exe commands
+    capture_env: bool,
+    /// The environment the build ran with, for resolving /external:env:<variable> flags and
+    /// for --capture-env
+    variables: environment::Environment,
+    /// The standard to add to C++ commands that don't choose one
+    default_std: Option<String>,
+    /// Write into an output directory that looks like an object directory
+    force: bool,
+    /// Remove flags clangd doesn't support
+    for_clangd: bool,
+    /// Make /FI and /Yu header names absolute
+    resolve_force_includes: bool,
+    /// Report changes instead of writing the database
+    dry_run: bool,
+    /// Report whether the database is out of date instead of writing it
+    check: bool,
+    /// Keep existing commands that new ones are equivalent to
+    keep_equivalent: bool,
+    /// How many lines a directory banner is trusted for
+    banner_window: Option<usize>,
+    /// Discard the command a log ends in
+    drop_partial: bool,
+    /// Only accept commands whose compiler exists on disk
+    verify_compilers: bool,
+    /// How long to wait for another run to release the database
+    lock_timeout: Duration,
+    /// Write commands as `arguments` arrays
+    arguments: bool,
+    /// Move the flags of commands longer than this into response files
+    max_command_length: Option<usize>,
+    /// Editor configuration to write
+    editor_configs: Vec<EditorConfig>,
+    /// Write the index priority file next to the database
+    index_priority: bool,
+    /// Time how long reading and parsing each line takes
+    timings: bool,
+    /// Where to write the database
+    sinks: Vec<SinkKind>,
+    /// Where the http sink posts the database
+    sink_url: Option<String>,
+    /// How to compress the sidecar and NDJSON, if at all
+    compress: Option<Compression>,
+    /// Merge into the SQLite store instead of the JSON file
+    sqlite_store: bool,
+    /// The flavor whose database to write, when writing one for each flavor
+    flavor: Option<Flavor>,
+    /// Where to cache resolved commands
+    cache_dir: Option<PathBuf>,
+    /// Where to write how each line was classified
+    trace_parse: Option<PathBuf>,
+    /// Whether each log is in the directory its commands ran in
+    logs_in_dirs: bool,
+    /// Also parse each log's pass logs
+    pass_logs: bool,
+}
+
+impl GenerateOptions {
+    /// A digest of the options that change what entries look like, to detect databases written
+    /// with different options. It's only comparable between runs of the same build of the tool.
+    fn digest(&self) -> String {
+        let mut hasher = DefaultHasher::new();
+        for compiler in &self.compilers {
+            compiler.compiler.hash(&mut hasher);
+        }
+        self.launchers.hash(&mut hasher);
+        self.redactor.key().hash(&mut hasher);
+        self.scratch.key().hash(&mut hasher);
+        for objroot in &self.objroots {
+            objroot.key().hash(&mut hasher);
+        }
+        for pattern in &self.exclude {
+            pattern.as_str().hash(&mut hasher);
+        }
+        for mapping in &self.source_root_map {
+            (&mapping.agent, &mapping.local).hash(&mut hasher);
+        }
+        self.detect_source_root.hash(&mut hasher);
+        self.environment.hash(&mut hasher);
+        if self.capture_env {
+            for name in ["CL", "_CL_"] {
+                environment::lookup(&self.variables, name).hash(&mut hasher);
+            }
+        }
+        self.default_std.hash(&mut hasher);
+        (
+            self.template_missing,
+            self.for_clangd,
+            self.resolve_force_includes,
+        )
+            .hash(&mut hasher);
+        self.max_command_length.hash(&mut hasher);
+        format!("{:016x}", hasher.finish())
+    }
+}
+
+/// Counts from a generation run.
+#[derive(Default, Clone, Copy)]
+struct Summary {
+    existing: usize,
+    new: usize,
+    excluded: usize,
+    scratch: usize,
+    total: usize,
+    /// Entries for files that didn't have one
+    added: usize,
+    /// Entries that replaced a different one for the same file
+    updated: usize,
+    /// Entries identical to the one the file already had
+    unchanged: usize,
+    /// Existing entries that are gone
+    removed: usize,
+    /// With --dry-run or --check, whether writing the database would change it
+    out_of_date: bool,
+    /// How long each stage took, with --timings
+    timings: Timings,
+}
+
+fn generate(log_paths: &[PathBuf], output_dir: &Path, options: &GenerateOptions) -> Summary {
+    let absolute_output_dir = paths::absolute(output_dir);
+
+    // TODO: handle the case where output_dir is a path to a file named compile_commands.json.
+    // in this case, check if the parent exists and is a dir.
+    if !absolute_output_dir.exists() {
+        panic!("Output directory doesn't exist!");
+    }
+
+    if !absolute_output_dir.is_dir() {
+        panic!("Output directory exists, but is not a directory!");
+    }
+
+    if let Some(source_dir) = object_dir_parent(&absolute_output_dir)
+        && !options.force
+    {
+        panic!(
+            "Output directory {} looks like an object directory, where editors won't find \
+             compile_commands.json. Use --output-dir {} instead, or --force to write it anyway.",
+            absolute_output_dir.display(),
+            source_dir.display()
+        );
+    }
+
+    let database_name = match options.flavor {
+        Some(flavor) => flavor.database_name(),
+        None => String::from("compile_commands.json"),
+    };
+    let compile_commands_path = absolute_output_dir.join(database_name);
+    // Held until the database is written, since concurrent runs would clobber each other's
+    // journals and merges
+    let _lock = lock::acquire(&compile_commands_path, options.lock_timeout);
+
+    let metadata_path = metadata::path_for(&compile_commands_path);
+
+    let mut summary = Summary::default();
+    let mut compile_commands = Vec::new();
+    let mut journals = Vec::new();
+    let mut redactions = 0;
+    let mut diagnostic_counts = HashMap::new();
+    let mut banner_dirs = HashSet::new();
+    let mut up_to_date = HashSet::new();
+    let mut launchers = HashMap::new();
+    for main_log_path in log_paths {
+        // Pass logs lack some of the banners the main log has, so each starts out knowing the
+        // directories the threads were in at the end of the log before it
+        let mut dirs = HashMap::new();
+        for (pass, log_path) in &log_group(main_log_path, options) {
+            let context = ParseContext {
+                dirs,
+                pass: pass.unwrap_or_default(),
+                ..ParseContext::default()
+            };
+            let parsed = parse_log(log_path, &absolute_output_dir, options, context);
+            // A chunked log was last written when its last chunk was
+            let last_chunk = logio::chunks(log_path).pop().unwrap_or(log_path.clone());
+            let seen = metadata::log_timestamp(&last_chunk);
+            compile_commands.extend(parsed.entries.into_iter().map(|entry| (entry, seen)));
+            banner_dirs.extend(parsed.banner_dirs);
+            up_to_date.extend(parsed.up_to_date);
+            launchers.extend(parsed.launchers);
+            journals.push(parsed.journal);
+            redactions += parsed.redactions;
+            summary.timings.combine(&parsed.timings);
+            dirs = parsed.dirs;
+
+            if options.diagnostics {
+                for diagnostics_log in diagnostics::logs_for(log_path) {
+                    diagnostics::read(&diagnostics_log, &mut diagnostic_counts);
+                }
+            }
+        }
+    }
+
+    let mut since = Instant::now();
+    if options.capture_env {
+        for (entry, _) in &mut compile_commands {
+            environment::inject_cl(entry, &options.variables, &options.compilers);
+        }
+    }
+
+    let mut unset_variables = BTreeSet::new();
+    for (entry, _) in &mut compile_commands {
+        unset_variables.extend(external::resolve_env(entry, &options.variables));
+    }
+    if !unset_variables.is_empty() {
+        println!(
+            "Warning: couldn't resolve /external:env: flags naming {}, which aren't set in the build environment",
+            unset_variables.into_iter().collect::<Vec<_>>().join(", ")
+        );
+    }
+
+    let mut root_map = options.source_root_map.clone();
+    if let Some(local_root) = &options.detect_source_root {
+        let entries = compile_commands.iter().map(|(entry, _)| entry);
+        match reroot::detect(entries, local_root) {
+            Some(mapping) => root_map.push(mapping),
+            None => println!(
+                "Warning: couldn't detect a source root matching {}",
+                local_root.display()
+            ),
+        }
+    }
+    for mapping in &root_map {
+        for (entry, _) in &mut compile_commands {
+            mapping.apply_to_entry(entry);
+        }
+        banner_dirs = banner_dirs
+            .iter()
+            .map(|dir| mapping.apply_to_path(dir))
+            .collect();
+        up_to_date = up_to_date
+            .iter()
+            .map(|path| mapping.apply_to_path(path))
+            .collect();
+        diagnostic_counts = diagnostic_counts
+            .into_iter()
+            .map(|(file, counts)| (mapping.apply(&file).into_owned(), counts))
+            .collect();
+        launchers = launchers
+            .into_iter()
+            .map(|(file, launcher)| (mapping.apply(&file).into_owned(), launcher))
+            .collect();
+    }
+
+    let mut unresolved_headers = BTreeSet::new();
+    for (entry, _) in &mut compile_commands {
+        if cuda::is_nvcc(&entry.command) {
+            cuda::translate(entry);
+        }
+        modules::resolve_paths(entry);
+        if options.resolve_force_includes {
+            unresolved_headers.extend(force_include::resolve(entry, &options.variables));
+        }
+        if options.for_clangd {
+            modules::strip(entry);
+            external::to_isystem(entry);
+        }
+    }
+    if !unresolved_headers.is_empty() {
+        println!(
+            "Warning: couldn't find the forced or precompiled headers {}, which were left as they are",
+            unresolved_headers
+                .into_iter()
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+    }
+    if let Some(environment) = &options.environment {
+        for (entry, _) in &mut compile_commands {
+            entry.environment = Some(environment.clone());
+        }
+    }
+
+    let mut outside_only = 0;
+    compile_commands.retain(|(entry, _)| {
+        match filtered_by(&entry.file, options) {
+            Some(Filter::Excluded) => summary.excluded += 1,
+            Some(Filter::Scratch) => summary.scratch += 1,
+            Some(Filter::OutsideOnly) => outside_only += 1,
+            None => return true,
+        }
+        false
+    });
+    if let Some(only) = &options.only {
+        println!(
+            "Skipped {} compile commands outside {}",
+            outside_only,
+            only.display()
+        );
+    }
+    summary.timings.lap(Stage::Paths, &mut since);
+
+    // Read in the existing compile commands, if it exists, and merge with the new commands
+    let mut store = options
+        .sqlite_store
+        .then(|| Store::open(&store::path_for(&compile_commands_path), options.key_case));
+    let mut existing_commands = match &store {
+        Some(store) => store.entries(),
+        None => read_compile_commands(&compile_commands_path),
+    };
+    summary.existing = existing_commands.len();
+    // Databases written before a file was known to be scratch lose its entry too
+    existing_commands.retain(|entry| !options.scratch.contains(&entry.file));
+    let existing_scratch = summary.existing - existing_commands.len();
+    summary.scratch += existing_scratch;
+    let previous: HashMap<String, CompileCommandsEntry> = existing_commands
+        .iter()
+        .map(|entry| (options.key_case.key(&entry.file), entry.clone()))
+        .collect();
+
+    if options.gc_rebuilt_dirs {
+        // A directory that was rebuilt but didn't compile a file this time no longer builds it,
+        // even if the file still exists, unless build /why said the file (or its directory) was
+        // up to date
+        let fresh_files: HashSet<String> = compile_commands
+            .iter()
+            .map(|(entry, _)| options.key_case.key(&entry.file))
+            .collect();
+        let before = existing_commands.len();
+        existing_commands.retain(|entry| {
+            !banner_dirs.contains(&entry.directory)
+                || options
+                    .only
+                    .as_ref()
+                    .is_some_and(|only| !is_under(&entry.file, only, options.key_case))
+                || fresh_files.contains(&options.key_case.key(&entry.file))
+                || up_to_date.contains(&entry.directory)
+                || up_to_date.contains(Path::new(&entry.file))
+        });
+        println!(
+            "Pruned {} compile commands no longer built in rebuilt directories",
+            before - existing_commands.len()
+        );
+    }
+    summary.new = compile_commands.len();
+
+    println!(
+        "There are {} existing compile commands and {} new compile commands",
+        existing_commands.len(),
+        compile_commands.len()
+    );
+    if summary.excluded > 0 {
+        println!("Excluded {} compile commands", summary.excluded);
+    }
+    if summary.scratch > 0 {
+        println!(
+            "Dropped {} compile commands for scratch sources in temp directories",
+            summary.scratch
+        );
+    }
+
+    let mut metadata = Metadata::load(&metadata_path);
+    let options_digest = options.digest();
+    metadata.check_version(&options_digest);
+    metadata.version = Some(metadata::VERSION.to_string());
+    metadata.options_digest = Some(options_digest);
+    let mut compile_commands = merge_new_compile_commands(
+        existing_commands,
+        compile_commands,
+        &mut metadata,
+        options.key_case,
+    );
+    if let Some(age) = options.prune_older_than {
+        // Entries outside --only are left alone, like the rest of the database outside it
+        let stale: HashSet<String> = metadata
+            .older_than(age)
+            .into_iter()
+            .filter(|file| {
+                options
+                    .only
+                    .as_ref()
+                    .is_none_or(|only| is_under(file, only, options.key_case))
+            })
+            .collect();
+        let before = compile_commands.len();
+        compile_commands.retain(|entry| !stale.contains(&entry.file));
+        metadata.entries.retain(|file, _| !stale.contains(file));
+        println!(
+            "Pruned {} compile commands no log has refreshed in {}",
+            before - compile_commands.len(),
+            metadata::format_age(age)
+        );
+    }
+    if let Some(grace_runs) = options.prune_missing {
+        let (pruned, kept) = prune_missing(
+            &mut compile_commands,
+            &mut metadata,
+            grace_runs,
+            options.only.as_deref(),
+            options.key_case,
+        );
+        println!(
+            "Pruned {} compile commands whose source files no longer exist",
+            pruned
+        );
+        if kept > 0 {
+            println!(
+                "Kept {} compile commands for missing generated sources, which may be generated again",
+                kept
+            );
+        }
+    }
+    for (file, launcher) in launchers {
+        if let Some(entry_metadata) = metadata.entries.get_mut(&file) {
+            entry_metadata.launcher = Some(launcher);
+        }
+    }
+    if options.diagnostics {
+        let mut with_diagnostics = 0;
+        for (file, counts) in diagnostic_counts {
+            // Diagnostics in headers don't belong to any entry
+            if let Some(entry_metadata) = metadata.entries.get_mut(&file) {
+                entry_metadata.warnings = counts.warnings;
+                entry_metadata.errors = counts.errors;
+                with_diagnostics += 1;
+            }
+        }
+        println!(
+            "Recorded diagnostics for {} compile commands",
+            with_diagnostics
+        );
+    }
+    if options.template_missing {
+        let templated =
+            template::template_missing(&compile_commands, &mut metadata, options.key_case);
+        println!(
+            "Templated {} compile commands for source files without one",
+            templated.len()
+        );
+        compile_commands.extend(templated);
+    }
+    summary.total = compile_commands.len();
+
+    // Like pruning, rewriting commands is limited to entries under --only
+    let in_scope = |entry: &CompileCommandsEntry| {
+        options
+            .only
+            .as_ref()
+            .is_none_or(|only| is_under(&entry.file, only, options.key_case))
+    };
+    let mut injected = 0;
+    for entry in &mut compile_commands {
+        if let Some(std) = &options.default_std
+            && in_scope(entry)
+        {
+            let dialect = compiler::dialect_of(&options.compilers, &entry.command);
+            injected += usize::from(standard::inject_default(entry, std, dialect));
+        }
+        if let Some(entry_metadata) = metadata.entries.get_mut(&entry.file) {
+            entry_metadata.std = standard::effective(entry);
+        }
+    }
+    if let Some(std) = &options.default_std {
+        println!(
+            "Added the standard {} to {} compile commands",
+            std, injected
+        );
+    }
+
+    let overrides = Overrides::load(&absolute_output_dir.join(overrides::FILE_NAME));
+    let overridden = compile_commands
+        .iter_mut()
+        .filter(|entry| in_scope(entry))
+        .map(|entry| overrides.apply(entry))
+        .filter(|&applied| applied)
+        .count();
+    if overridden > 0 {
+        println!("Applied overrides to {} compile commands", overridden);
+    }
+
+    // Overrides, the captured environment and merged entries may bring in secrets the commands
+    // didn't have when they were resolved
+    redactions += compile_commands
+        .iter_mut()
+        .filter(|entry| in_scope(entry))
+        .map(|entry| options.redactor.redact_entry(entry))
+        .sum::<usize>();
+    if redactions > 0 {
+        println!("Redacted {} secrets from the compile commands", redactions);
+    }
+
+    if options.keep_equivalent {
+        for entry in &mut compile_commands {
+            if let Some(previous_command) = previous
+                .get(&options.key_case.key(&entry.file))
+                .map(|e| &e.command)
+                && canonical::equivalent(previous_command, &entry.command)
+            {
+                entry.command.clone_from(previous_command);
+            }
+        }
+    }
+
+    let duplicates = duplicate_outputs(&compile_commands);
+    for (object, files) in &duplicates {
+        println!(
+            "Warning: {} is written by more than one source file: {}",
+            object.display(),
+            files.join(", ")
+        );
+    }
+    if options.strict && !duplicates.is_empty() {
+        panic!(
+            "{} object files are written by more than one source file",
+            duplicates.len()
+        );
+    }
+
+    summary.timings.lap(Stage::Merge, &mut since);
+    let response_file_dir = repack::dir_for(&compile_commands_path);
+    let mut response_files = Vec::new();
+    if let Some(max_length) = options.max_command_length {
+        response_files = compile_commands
+            .iter_mut()
+            .filter_map(|entry| repack::repack(entry, max_length, &response_file_dir))
+            .collect();
+        if !response_files.is_empty() {
+            println!(
+                "Moved the flags of {} commands longer than {} characters into response files",
+                response_files.len(),
+                max_length
+            );
+        }
+    }
+    summary.timings.lap(Stage::ResponseFiles, &mut since);
+    summary.timings.entries = compile_commands.len();
+
+    if options.dry_run || options.check {
+        summary.out_of_date = report_changes(
+            &previous,
+            &compile_commands,
+            options.dry_run,
+            options.key_case,
+        );
+        for journal in journals {
+            journal.finish();
+        }
+        return summary;
+    }
+
+    if !response_files.is_empty() {
+        fs::create_dir_all(&response_file_dir)
+            .unwrap_or_else(|_| panic!("Failed to create {}", response_file_dir.display()));
+    }
+    for response_file in &response_files {
+        response_file.write();
+    }
+    let removed = repack::collect_garbage(&response_file_dir, &compile_commands);
+    if removed > 0 {
+        println!("Removed {} response files no entry uses anymore", removed);
+    }
+    summary.timings.lap(Stage::ResponseFiles, &mut since);
+
+    let keys: HashSet<String> = compile_commands
+        .iter()
+        .map(|e| options.key_case.key(&e.file))
+        .collect();
+    for entry in &compile_commands {
+        match previous.get(&options.key_case.key(&entry.file)) {
+            None => summary.added += 1,
+            Some(previous_entry) if previous_entry.same_as(entry) => summary.unchanged += 1,
+            Some(_) => summary.updated += 1,
+        }
+    }
+    summary.removed = previous.keys().filter(|key| !keys.contains(*key)).count();
+    println!(
+        "{} added, {} updated, {} unchanged and {} removed",
+        summary.added, summary.updated, summary.unchanged, summary.removed
+    );
+    // Leave an unchanged database alone, so tools watching it don't reload for nothing
+    let changed = summary.added + summary.updated + summary.removed + existing_scratch > 0
+        || metadata.arguments != options.arguments;
+    metadata.arguments = options.arguments;
+    let up_to_date = |path: &Path| !changed && path.is_file();
+
+    if let Some(store) = &mut store
+        && !up_to_date(&store::path_for(&compile_commands_path))
+    {
+        let (written, removed) = store.update(&compile_commands);
+        println!(
+            "Updated {} entries and removed {} in {}",
+            written,
+            removed,
+            store::path_for(&compile_commands_path).display()
+        );
+    }
+    for &kind in &options.sinks {
+        if kind == SinkKind::JsonFile && up_to_date(&compile_commands_path) {
+            println!("{} is already up to date", compile_commands_path.display());
+            continue;
+        }
+        match (&store, kind) {
+            (Some(_), SinkKind::Sqlite) => continue,
+            (Some(store), SinkKind::JsonFile) => {
+                store.export(&compile_commands_path, options.arguments);
+                println!(
+                    "Successfully exported compile commands to {}",
+                    compile_commands_path.display()
+                );
+                continue;
+            }
+            _ => {}
+        }
+        let sink = sink::create(
+            kind,
+            &compile_commands_path,
+            options.sink_url.as_deref(),
+            options.arguments,
+            options.compress,
+            options.key_case,
+        );
+        let destination = sink.write(&compile_commands);
+        println!("Successfully wrote compile commands to {}", destination);
+    }
+    for &kind in &options.editor_configs {
+        editor::emit(kind, &compile_commands_path, &compile_commands);
+    }
+    // The metadata describes the JSON file, replacing rather than writing through a link to a
+    // flavor's metadata
+    if options.sinks.contains(&SinkKind::JsonFile) {
+        metadata.save(&metadata_path, options.compress);
+    }
+    if options.index_priority {
+        priority::write(&compile_commands_path, &compile_commands, &metadata);
+    }
+    summary.timings.lap(Stage::Serialize, &mut since);
+    for journal in journals {
+        journal.finish();
+    }
+    summary
+}
+
+/// A filter keeping the entry a log produces out of the database.
+#[derive(Clone, Copy)]
+enum Filter {
+    /// An exclude pattern of the project or .ccdbignore matches the file
+    Excluded,
+    /// The file is a scratch source in a temp directory
+    Scratch,
+    /// The file is outside --only
+    OutsideOnly,
+}
+
+/// The first filter that keeps a new entry for `file` out of the database, if any.
+fn filtered_by(file: &str, options: &GenerateOptions) -> Option<Filter> {
+    if options.exclude.iter().any(|pattern| pattern.matches(file)) {
+        Some(Filter::Excluded)
+    } else if options.scratch.contains(file) {
+        Some(Filter::Scratch)
+    } else if options
+        .only
+        .as_ref()
+        .is_some_and(|only| !is_under(file, only, options.key_case))
+    {
+        Some(Filter::OutsideOnly)
+    } else {
+        None
+    }
+}
+
+/// Whether `file` is in `dir` or a directory under it, comparing their keys under `key_case`.
+fn is_under(file: &str, dir: &Path, key_case: KeyCase) -> bool {
+    Path::new(&key_case.key(file)).starts_with(key_case.key(&dir.to_string_lossy()))
+}
+
+/// Prints how `compile_commands` differs from the `previous` commands, by file, ignoring changes
+/// that only reorder or repeat flags. Unless `detailed`, only the first few files are listed, and
+/// without the arguments that changed. Returns whether anything changed.
+fn report_changes(
+    previous: &HashMap<String, CompileCommandsEntry>,
+    compile_commands: &[CompileCommandsEntry],
+    detailed: bool,
+    key_case: KeyCase,
+) -> bool {
+    let mut listed = 0;
+    let mut list = |change: &str, file: &str| {
+        if detailed || listed < CHANGES_LISTED {
+            println!("{} {}", change, file);
+        }
+        listed += 1;
+    };
+
+    let mut added = 0;
+    let mut changed = 0;
+    let mut equivalent = 0;
+    for entry in compile_commands {
+        let Some(previous_command) = previous.get(&key_case.key(&entry.file)).map(|e| &e.command)
+        else {
+            list("added", &entry.file);
+            added += 1;
+            continue;
+        };
+        if *previous_command == entry.command {
+            continue;
+        }
+        let (before, after) = (
+            canonical::Canonical::new(previous_command),
+            canonical::Canonical::new(&entry.command),
+        );
+        if before == after {
+            equivalent += 1;
+            continue;
+        }
+        changed += 1;
+        list("changed", &entry.file);
+        if detailed {
+            let (dropped, gained) = before.diff(&after);
+            for argument in dropped {
+                println!("    - {}", argument);
+            }
+            for argument in gained {
+                println!("    + {}", argument);
+            }
+        }
+    }
+    let keys: HashSet<String> = compile_commands
+        .iter()
+        .map(|e| key_case.key(&e.file))
+        .collect();
+    let mut removed = 0;
+    for file in previous
+        .iter()
+        .filter(|(key, _)| !keys.contains(*key))
+        .map(|(_, e)| &e.file)
+    {
+        list("removed", file);
+        removed += 1;
+    }
+    if listed > CHANGES_LISTED && !detailed {
+        println!("...and {} more", listed - CHANGES_LISTED);
+    }
+    println!(
+        "{} added, {} changed, {} removed, {} only reordered or repeated flags",
+        added, changed, removed, equivalent
+    );
+    added + changed + removed > 0
+}
+
+/// If `dir` is inside an object directory (like objfre\amd64 or an obj root), the directory
+/// containing it, which is where the sources are.
+fn object_dir_parent(dir: &Path) -> Option<&Path> {
+    dir.ancestors().find_map(|ancestor| {
+        let name = ancestor.file_name()?.to_string_lossy().to_ascii_lowercase();
+        let is_object_dir =
+            name == "obj" || name.starts_with("objfre") || name.starts_with("objchk");
+        is_object_dir.then(|| ancestor.parent()).flatten()
+    })
+}
+
+/// The results of parsing a log.
+struct ParsedLog {
+    entries: Vec<CompileCommandsEntry>,
+    /// Every directory the log had a banner for
+    banner_dirs: HashSet<PathBuf>,
+    /// Directories and files `build /why` reported as up to date
+    up_to_date: HashSet<PathBuf>,
+    /// The launcher each file's command was wrapped in, for commands that had one
+    launchers: HashMap<String, String>,
+    /// The directory each thread was processing when the log ended
+    dirs: HashMap<String, PathBuf>,
+    /// The log's journal, which should be finished once the output is written
+    journal: Journal,
+    /// How many secrets were redacted from the entries
+    redactions: usize,
+    /// How long reading and parsing the log took, and how much was in it
+    timings: Timings,
+}
+
+/// The log at `main_log_path`, followed by its pass logs with their passes if `options` asks for
+/// them.
+fn log_group(main_log_path: &Path, options: &GenerateOptions) -> Vec<(Option<u32>, PathBuf)> {
+    let mut group = vec![(None, main_log_path.to_path_buf())];
+    if options.pass_logs {
+        let pass_logs = pass_logs(main_log_path);
+        if !pass_logs.is_empty() {
+            println!(
+                "Found {} pass logs for {}",
+                pass_logs.len(),
+                main_log_path.display()
+            );
+        }
+        group.extend(pass_logs.into_iter().map(|(pass, path)| (Some(pass), path)));
+    }
+    group
+}
+
+/// The pass logs Razzle wrote alongside the log at `log_path`, named for it with the pass added
+/// (`buildfre_pass0.log` for `buildfre.log`), with their passes in order.
+fn pass_logs(log_path: &Path) -> Vec<(u32, PathBuf)> {
+    let Some(stem) = log_path.file_stem() else {
+        return Vec::new();
+    };
+    let prefix = format!("{}_pass", stem.to_string_lossy());
+    let dir = match log_path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let Ok(dir_entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut logs: Vec<(u32, PathBuf)> = dir_entries
+        .filter_map(|dir_entry| {
+            let path = dir_entry.ok()?.path();
+            let pass = path
+                .file_name()?
+                .to_str()?
+                .strip_prefix(&prefix)?
+                .strip_suffix(".log")?
+                .parse()
+                .ok()?;
+            Some((pass, path))
+        })
+        .collect();
+    logs.sort();
+    logs
+}
+
+/// Parses the compile commands out of the log at `log_path`, starting from `context`, or resuming
+/// from its journal in `output_dir` if an earlier run was interrupted.
+fn parse_log(
+    log_path: &Path,
+    output_dir: &Path,
+    options: &GenerateOptions,
+    context: ParseContext,
+) -> ParsedLog {
+    let mut log = LogFile::open(log_path, options.io_mode);
+    let reading = Instant::now();
+
+    let absolute_log_path = paths::absolute(log_path);
+    let log_name = absolute_log_path.file_name().unwrap_or_default();
+    // Per-directory logs all have the same name, so the path tells their journals apart
+    let journal_path = output_dir.join(format!(
+        "compile_commands.{}.{:08x}.journal",
+        log_name.to_string_lossy(),
+        crc32fast::hash(absolute_log_path.as_os_str().as_encoded_bytes())
+    ));
+    // A trace covers the whole log, so it can't start from a checkpoint
+    let resumed = if options.no_resume || options.trace_parse.is_some() {
+        None
+    } else {
+        journal::resume(&journal_path, &absolute_log_path, |len| {
+            log.prefix_hasher(len).map(|hasher| hasher.finalize())
+        })
+    };
+
+    let mut parser = log_parser(&absolute_log_path, options, context);
+    let mut compile_commands = Vec::new();
+    let mut redactions = 0;
+    let mut offset = 0;
+    let mut line_number = 0;
+    let mut journal = match resumed {
+        Some(resumed) => {
+            println!(
+                "Resuming from checkpoint with {} compile commands",
+                resumed.entries.len()
+            );
+            parser.context = resumed.context;
+            compile_commands = resumed.entries;
+            redactions = resumed.redactions;
+            offset = resumed.offset;
+            line_number = resumed.line_number;
+            Journal::reopen(&journal_path)
+        }
+        None => Journal::create(&journal_path, &absolute_log_path),
+    };
+
+    let mut cache = options
+        .cache_dir
+        .as_deref()
+        .map(|dir| TransformCache::open(dir, options.redactor.clone()));
+    let mut trace = options.trace_parse.as_deref().map(ParseTrace::open);
+    let mut sanitizer = Sanitizer::new(&options.thread_prefix);
+    // CRC32 is streaming, so feeding it the log a line at a time gives the checksum of the whole
+    // prefix, and it's the same in every build of the tool, unlike the standard library's hasher
+    let mut hasher = log
+        .prefix_hasher(offset)
+        .expect("The log was shorter than when it was checked");
+    let mut seen_commands = HashSet::new();
+    let mut last_checkpoint = Instant::now();
+    let mut checkpointed_entries = compile_commands.len();
+    let mut checkpointed_redactions = redactions;
+    let mut timings = Timings::default();
+    let first_line = line_number;
+    // Time spent handling lines, and the part of it spent resolving commands into entries
+    let mut handling = Duration::ZERO;
+    let mut resolving = Duration::ZERO;
+    log.for_each_line(offset, |line| {
+        hasher.update(line.as_bytes());
+        offset += line.len();
+        line_number += 1;
+
+        let handling_started = options.timings.then(Instant::now);
+        sanitizer.sanitize(line.trim_end_matches(['\r', '\n']), |line| {
+            let from = parser.trace_state();
+            let logged = parser.parse_line(line_number, line);
+            if let Some(trace) = &mut trace {
+                let (thread, _) = parser.thread_prefix.split(line);
+                trace.record(&trace::Record {
+                    log: &absolute_log_path,
+                    line: line_number,
+                    text: &options.redactor.redacted(line),
+                    kind: parser.line_kind,
+                    thread,
+                    from,
+                    to: parser.trace_state(),
+                    dir: thread
+                        .and_then(|thread| parser.context.dirs.get(thread))
+                        .map(PathBuf::as_path),
+                    pass: parser.context.pass,
+                    completed: parser.completed,
+                    skipped: parser.completed.is_some() && logged.is_none(),
+                });
+            }
+            if let Some((pass, launcher, command)) = logged.map(|logged| {
+                (
+                    logged.pass,
+                    logged.launcher.clone(),
+                    logged.into_raw_command(),
+                )
+            }) && !seen_commands.contains(&command)
+            {
+                let resolving_started = options.timings.then(Instant::now);
+                let (entries, redacted) = resolve(&command, cache.as_mut(), &options.redactor);
+                redactions += redacted;
+                record_launcher(&mut parser.context, &entries, launcher);
+                compile_commands.extend(entries.into_iter().map(|entry| (entry, pass)));
+                seen_commands.insert(command);
+                timings.commands += 1;
+                if let Some(started) = resolving_started {
+                    resolving += started.elapsed();
+                }
+            }
+        });
+        if let Some(started) = handling_started {
+            handling += started.elapsed();
+        }
+
+        // Checkpoints can only be taken between commands, when the directory mapping is all the
+        // parser state there is to save
+        if parser.is_idle() && last_checkpoint.elapsed() >= CHECKPOINT_INTERVAL {
+            journal.checkpoint(
+                offset,
+                line_number,
+                hasher.clone().finalize(),
+                &parser.context,
+                &compile_commands[checkpointed_entries..],
+                redactions - checkpointed_redactions,
+            );
+            checkpointed_entries = compile_commands.len();
+            checkpointed_redactions = redactions;
+            last_checkpoint = Instant::now();
+        }
+    });
+    sanitizer.report();
+    if let Some(logged) = parser.finish() {
+        if options.drop_partial {
+            println!(
+                "Dropped the command on line {} that the log ends in, which may be incomplete",
+                logged.line_number
+            );
+        } else {
+            println!(
+                "Warning: the log ends in the command on line {}, which may be incomplete",
+                logged.line_number
+            );
+            let pass = logged.pass;
+            let launcher = logged.launcher.clone();
+            let command = logged.into_raw_command();
+            if !seen_commands.contains(&command) {
+                let (entries, redacted) = resolve(&command, cache.as_mut(), &options.redactor);
+                redactions += redacted;
+                record_launcher(&mut parser.context, &entries, launcher);
+                compile_commands.extend(entries.into_iter().map(|entry| (entry, pass)));
+                timings.commands += 1;
+            }
+        }
+    }
+    if let Some(cache) = cache {
+        cache.finish();
+    }
+    if let Some(trace) = trace {
+        trace.finish();
+    }
+    if parser.inferred_dirs > 0 {
+        println!(
+            "Inferred the directory of {} commands without a recent directory banner from their \
+             source files or object directories",
+            parser.inferred_dirs
+        );
+    }
+    if parser.stale_dirs > 0 {
+        println!(
+            "Warning: attributed {} commands to directory banners older than the banner window",
+            parser.stale_dirs
+        );
+    }
+    if parser.unverified_compilers > 0 {
+        println!(
+            "Skipped {} lines naming a compiler that doesn't exist on disk",
+            parser.unverified_compilers
+        );
+    }
+    if parser.front_end_calls > 0 {
+        println!(
+            "Skipped {} commands that front ends like nvcc ran on their own behalf",
+            parser.front_end_calls
+        );
+    }
+
+    // A file compiled in more than one pass gets the command from the last of them. Ordering the
+    // entries by pass makes it win the merge, and the sort is stable so that within a pass the
+    // later command still wins.
+    compile_commands.sort_by_key(|(_, pass)| *pass);
+
+    timings.lines = line_number - first_line;
+    if options.timings {
+        timings.add(Stage::Paths, resolving);
+        timings.add(Stage::Parse, handling.saturating_sub(resolving));
+        timings.add(Stage::Read, reading.elapsed().saturating_sub(handling));
+    }
+
+    ParsedLog {
+        entries: compile_commands
+            .into_iter()
+            .map(|(entry, _)| entry)
+            .collect(),
+        banner_dirs: parser.context.banner_dirs,
+        up_to_date: parser.context.up_to_date,
+        launchers: parser.context.launchers,
+        dirs: parser.context.dirs,
+        journal,
+        redactions,
+        timings,
+    }
+}
+
+/// The entries `command` resolves to, from `cache` if there is one, with their secrets redacted
+/// before the journal or the cache can hold them, and how many secrets there were.
+fn resolve(
+    command: &RawCommand,
+    cache: Option<&mut TransformCache>,
+    redactor: &Redactor,
+) -> (Vec<CompileCommandsEntry>, usize) {
+    if let Some(cache) = cache {
+        return cache.resolve(command);
+    }
+    let mut entries: Vec<_> = CompileCommandsEntry::from_raw_command(command).collect();
+    let redactions = entries
+        .iter_mut()
+        .map(|entry| redactor.redact_entry(entry))
+        .sum();
+    (entries, redactions)
+}
+
+/// Records the launcher, if any, that the command `entries` came from was wrapped in, or that a
+/// later command for the same files wasn't.
+fn record_launcher(
+    context: &mut ParseContext,
+    entries: &[CompileCommandsEntry],
+    launcher: Option<String>,
+) {
+    for entry in entries {
+        match &launcher {
+            Some(launcher) => context
+                .launchers
+                .insert(entry.file.clone(), launcher.clone()),
+            None => context.launchers.remove(&entry.file),
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The commands `LogParser` finds in `log`.
+    fn parse_commands(log: &str) -> Vec<LoggedCommand> {
+        let mut parser = LogParser::new(&[], Launchers::default(), ThreadPrefix::default());
+        let mut commands: Vec<LoggedCommand> = log
+            .lines()
+            .enumerate()
+            .filter_map(|(index, line)| parser.parse_line(index + 1, line))
+            .collect();
+        commands.extend(parser.finish());
+        commands
+    }
+
+    /// The commands `LogParser` finds in `log`, as their lines.
+    fn parse(log: &str) -> Vec<Vec<String>> {
+        parse_commands(log)
+            .into_iter()
+            .map(|command| command.lines)
+            .collect()
+    }
+
+    /// The directory each command in `log` ran in.
+    fn dirs(log: &str) -> Vec<Option<PathBuf>> {
+        parse_commands(log)
+            .into_iter()
+            .map(|command| command.dir)
+            .collect()
+    }
+
+    #[test]
+    fn banners_with_spaces_parentheses_and_non_ascii_text() {
+        let log = "\
+0001>BUILDMSG: Processing C:\\Users\\José (Work)\\src\\测试 dir   
+0001>cl /c a.cpp
+0002>BUILDMSG: Processing \"C:\\Program Files (x86)\\proj\"
+0002>cl /c b.cpp";
+        assert_eq!(
+            dirs(log),
+            [
+                Some(PathBuf::from(r"C:\Users\José (Work)\src\测试 dir")),
+                Some(PathBuf::from(r"C:\Program Files (x86)\proj")),
+            ]
+        );
+    }
+
+    #[test]
+    fn banners_of_each_kind() {
+        let log = "\
+0001>BUILDMSG: Processing (pass1) D:\\os\\src\\a
+0001>cl /c a.cpp
+0002>BUILDMSG: Pass2: Processing D:\\os\\src\\b
+0002>cl /c b.cpp
+0003>Compiling D:\\os\\src\\c *************
+0003>cl /c c.cpp
+0004>cl /c d.cpp";
+        assert_eq!(
+            dirs(log),
+            [
+                Some(PathBuf::from(r"D:\os\src\a")),
+                Some(PathBuf::from(r"D:\os\src\b")),
+                Some(PathBuf::from(r"D:\os\src\c")),
+                None,
+            ]
+        );
+    }
+
+    #[test]
+    fn entries_in_directories_with_spaces_and_non_ascii_text() {
+        let log = "\
+0001>BUILDMSG: Processing /home/José (Work)/src dir
+0001>cl /c /I\"inc dir\" \"测试 file.cpp\"";
+        let [command] = <[LoggedCommand; 1]>::try_from(parse_commands(log))
+            .ok()
+            .unwrap();
+        let entries: Vec<_> =
+            CompileCommandsEntry::from_raw_command(&command.into_raw_command()).collect();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].file, "/home/José (Work)/src dir/测试 file.cpp");
+        assert_eq!(
+            entries[0].directory,
+            PathBuf::from("/home/José (Work)/src dir")
+        );
+        assert_eq!(
+            cmdline::split(&entries[0].command),
+            ["cl", "/c", "/Iinc dir", "测试 file.cpp"]
+        );
+    }
+
+    /// The command `log`'s only command joins its lines into.
+    fn full_command(log: &str) -> String {
+        let [lines] = <[Vec<String>; 1]>::try_from(parse(log)).unwrap();
+        RawCommand {
+            dir: PathBuf::from(r"D:\os\src"),
+            lines,
+            compiler: CompilerDefinition::builtins()[0].compiler.clone(),
+        }
+        .full_command()
+    }
+
+    #[test]
+    fn joins_continuation_lines_with_a_space() {
+        let log = "\
+0003>BUILDMSG: Processing D:\\os\\src
+0003>cl /nologo /c /Zi /W4
+0003>   /DUNICODE foo.cpp
+0003>BUILD: Compile done";
+        assert_eq!(full_command(log), "cl /nologo /c /Zi /W4 /DUNICODE foo.cpp");
+    }
+
+    #[test]
+    fn joins_a_line_wrapped_inside_a_quoted_path_without_a_space() {
+        let log = r#"0002>BUILDMSG: Processing D:\os\src\foo
+0002>cl /nologo /c /I"D:\os\public\internal\onecore\inc\minwin\priv_sdk\inc\very\long\incl
+0002>   ude\path" /I"D:\os\obj\amd64fre\onecore\inc" foo.cpp
+0002>BUILDMSG: Processing D:\os\src\bar"#;
+        let command = full_command(log);
+        assert_eq!(
+            command,
+            r#"cl /nologo /c /I"D:\os\public\internal\onecore\inc\minwin\priv_sdk\inc\very\long\include\path" /I"D:\os\obj\amd64fre\onecore\inc" foo.cpp"#
+        );
+        assert_eq!(
+            cmdline::split(&command)[3],
+            r"/ID:\os\public\internal\onecore\inc\minwin\priv_sdk\inc\very\long\include\path"
+        );
+    }
+
+    #[test]
+    fn joins_a_path_wrapped_more_than_once() {
+        let log = r#"0001>BUILDMSG: Processing D:\os\src
+0001>cl /c /FI"C:\Users\build agent\AppData\Local\pre
+0001>   compiled\stdafx wi
+0001>   th spaces.h" a.cpp
+0001>BUILD: Compile done"#;
+        assert_eq!(
+            full_command(log),
+            r#"cl /c /FI"C:\Users\build agent\AppData\Local\precompiled\stdafx with spaces.h" a.cpp"#
+        );
+    }
+
+    #[test]
+    fn a_wrap_between_arguments_inside_quotes_keeps_both() {
+        // The quote closed on the first line, so the next line starts a new argument
+        let log = r#"0001>BUILDMSG: Processing D:\os\src
+0001>cl /c /I"C:\a b"
+0001>   /I"C:\c d" a.cpp
+0001>BUILD: Compile done"#;
+        assert_eq!(
+            cmdline::split(&full_command(log)),
+            ["cl", "/c", r"/IC:\a b", r"/IC:\c d", "a.cpp"]
+        );
+    }
+
+    #[test]
+    fn other_threads_end_a_command() {
+        let log = "\
+0001>BUILDMSG: Processing D:\\os\\src
+0002>BUILDMSG: Processing D:\\os\\src\\other
+0001>cl /c a.cpp
+0002>   /DNOT_A_CONTINUATION
+0001>   /DX b.cpp";
+        assert_eq!(full_command(log), "cl /c a.cpp");
+    }
+
+    #[test]
+    fn is_under_follows_the_key_case() {
+        let dir = Path::new(r"C:\Src\Net");
+        assert!(is_under(r"c:/src/net/tcp/a.cpp", dir, KeyCase::Insensitive));
+        assert!(is_under(r"C:\SRC\NET\a.cpp", dir, KeyCase::Auto));
+        assert!(!is_under(r"C:\SRC\NET\a.cpp", dir, KeyCase::Sensitive));
+        assert!(is_under(
+            "/src/Net/a.cpp",
+            Path::new("/src/Net"),
+            KeyCase::Sensitive
+        ));
+        assert!(!is_under(
+            "/src/Net/a.cpp",
+            Path::new("/src/net"),
+            KeyCase::Auto
+        ));
+        assert!(!is_under(
+            r"C:\Src\Network\a.cpp",
+            dir,
+            KeyCase::Insensitive
+        ));
+    }
+
+    #[test]
+    fn quoted_compiler_path_with_spaces() {
+        let commands = parse(r#"0001>"C:\Program Files\VC\bin\cl.exe" /c foo.cpp"#);
+        assert_eq!(
+            commands,
+            [[r#""C:\Program Files\VC\bin\cl.exe" /c foo.cpp"#]]
+        );
+    }
+
+    #[test]
+    fn quoted_compiler_path_without_spaces() {
+        let commands = parse(r#"0001>"C:\VC\bin\cl.exe" /c foo.cpp"#);
+        assert_eq!(commands, [[r#""C:\VC\bin\cl.exe" /c foo.cpp"#]]);
+    }
+
+    #[test]
+    fn unquoted_compiler_path() {
+        let commands = parse(r"0001>C:\VC\bin\CL.EXE /c foo.cpp");
+        assert_eq!(commands, [[r"C:\VC\bin\CL.EXE /c foo.cpp"]]);
+    }
+
+    #[test]
+    fn quoted_program_that_is_not_a_compiler() {
+        assert!(parse(r#"0001>"C:\Program Files\Tools\clean.exe" /c foo.cpp"#).is_empty());
+    }
 }
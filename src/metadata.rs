@@ -0,0 +1,187 @@
+//! The metadata sidecar, compile_commands.meta.json, which records information about each entry
+//! that has no place in compile_commands.json itself.
+
+use crate::compress::{self, Compression};
+use std::{
+    collections::{BTreeMap, HashSet},
+    fs, io,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// The version of this tool, recorded in the sidecar.
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+#[derive(serde::Serialize, serde::Deserialize, Default)]
+pub struct Metadata {
+    /// The version of the tool that last wrote the database
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    /// Digest of the options that affect entries, from the run that last wrote the database
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub options_digest: Option<String>,
+    /// Whether the database's commands are written as `arguments` arrays
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub arguments: bool,
+    /// Metadata for each entry, keyed by the entry's file
+    #[serde(default)]
+    pub entries: BTreeMap<String, EntryMetadata>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Default)]
+pub struct EntryMetadata {
+    /// When the entry's command was last seen in a log, in seconds since the Unix epoch
+    #[serde(default)]
+    pub last_seen: u64,
+    /// For entries synthesized by --template-missing, the file whose entry was cloned
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub templated_from: Option<String>,
+    /// Warnings the real compiler reported for the entry's translation unit in its last build
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub warnings: usize,
+    /// Errors the real compiler reported for the entry's translation unit in its last build
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub errors: usize,
+    /// The language standard the entry compiles as, such as c++17
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub std: Option<String>,
+    /// The launcher the entry's command was wrapped in, like timethis, which was peeled off
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub launcher: Option<String>,
+    /// How many runs of --prune-missing in a row found the entry's generated source missing
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub missing_runs: u32,
+    /// When the entry was last shown by the `query` subcommand, in seconds since the Unix epoch
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub last_queried: u64,
+}
+
+fn is_zero<T: Default + PartialEq>(n: &T) -> bool {
+    *n == T::default()
+}
+
+fn major(version: &str) -> Option<u64> {
+    version.split('.').next()?.parse().ok()
+}
+
+/// Whether this tool's version matches `required`, which may give just the leading components of
+/// a version, so that `1` matches any 1.x.y and `1.2` matches any 1.2.y.
+pub fn version_matches(required: &str) -> bool {
+    let mut actual = VERSION.split('.');
+    required
+        .split('.')
+        .all(|component| actual.next() == Some(component))
+}
+
+/// The sidecar's path for the compile_commands.json at `compile_commands_path`.
+pub fn path_for(compile_commands_path: &Path) -> PathBuf {
+    compile_commands_path.with_extension("meta.json")
+}
+
+/// The current time, in seconds since the Unix epoch.
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+/// The time a log's commands were seen, taken from when the log was last written.
+pub fn log_timestamp(log_path: &Path) -> u64 {
+    fs::metadata(log_path)
+        .and_then(|m| m.modified())
+        .unwrap_or_else(|_| SystemTime::now())
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+/// Parses an age given as a number and a unit: `s`, `m`, `h`, `d` or `w`, like `30d`.
+pub fn parse_age(s: &str) -> Result<Duration, String> {
+    let split = s.len() - s.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+    let (count, unit) = s.split_at(split);
+    let count: u64 = count
+        .parse()
+        .map_err(|_| format!("expected a number and a unit like 30d, got {s}"))?;
+    let unit_secs = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        "w" => 7 * 24 * 60 * 60,
+        _ => return Err(format!("expected a unit of s, m, h, d or w, got {unit:?}")),
+    };
+    Ok(Duration::from_secs(count * unit_secs))
+}
+
+/// Formats an age the way [`parse_age`] parses it, in the largest unit that divides it.
+pub fn format_age(age: Duration) -> String {
+    let secs = age.as_secs();
+    let (unit_secs, unit) = [
+        (7 * 24 * 60 * 60, "w"),
+        (24 * 60 * 60, "d"),
+        (60 * 60, "h"),
+        (60, "m"),
+    ]
+    .into_iter()
+    .find(|(unit_secs, _)| secs != 0 && secs.is_multiple_of(*unit_secs))
+    .unwrap_or((1, "s"));
+    format!("{}{}", secs / unit_secs, unit)
+}
+
+impl Metadata {
+    /// Reads the sidecar at `path`, compressed or not, or returns empty metadata if it doesn't
+    /// exist.
+    pub fn load(path: &Path) -> Metadata {
+        let json = match compress::read_to_string(path) {
+            Ok(json) => json,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Metadata::default(),
+            Err(_) => panic!("Failed to read metadata from {}", path.display()),
+        };
+        serde_json::from_str(&json)
+            .unwrap_or_else(|_| panic!("Failed to parse metadata from {}", path.display()))
+    }
+
+    /// Warns if the database was written by an older major version of the tool, or with options
+    /// that produce different entries, since its existing entries may not match new ones.
+    pub fn check_version(&self, options_digest: &str) {
+        let Some(version) = &self.version else {
+            return;
+        };
+        if major(version) < major(VERSION) {
+            println!(
+                "Warning: the existing database was written by version {}, and entries it has that \
+                 aren't rebuilt may differ from ones written by version {}",
+                version, VERSION
+            );
+        } else if self
+            .options_digest
+            .as_ref()
+            .is_some_and(|digest| digest != options_digest)
+        {
+            println!(
+                "Warning: the existing database was written with different options, so its entries \
+                 may not match new ones"
+            );
+        }
+    }
+
+    /// The files whose entries weren't seen in a log in the last `age`. Entries that were never
+    /// seen in one, like templated entries, have no age.
+    pub fn older_than(&self, age: Duration) -> HashSet<String> {
+        let cutoff = now().saturating_sub(age.as_secs());
+        self.entries
+            .iter()
+            .filter(|(_, entry)| entry.last_seen != 0 && entry.last_seen < cutoff)
+            .map(|(file, _)| file.clone())
+            .collect()
+    }
+
+    /// Writes the sidecar for `path`, replacing a link to another database's sidecar rather than
+    /// writing through it.
+    pub fn save(&self, path: &Path, compression: Option<Compression>) {
+        let json =
+            serde_json::to_string_pretty(self).expect("Failed to serialize metadata to JSON");
+        compress::write(path, json.as_bytes(), compression);
+    }
+}
@@ -0,0 +1,72 @@
+//! C++ modules and header units flags, such as `/ifcOutput`, `/reference` and `/headerUnit`,
+//! whose path arguments are relative to the directory cl.exe ran in.
+
+use crate::{CompileCommandsEntry, cmdline, paths};
+use std::path::Path;
+
+/// Module flags that take a path, or a `name=path` pair, as the following token.
+pub const MODULE_FLAGS: &[&str] = &[
+    "ifcOutput",
+    "ifcSearchDir",
+    "reference",
+    "headerUnit",
+    "headerUnit:quote",
+    "headerUnit:angle",
+];
+
+fn is_module_flag(token: &str) -> bool {
+    token
+        .strip_prefix(['/', '-'])
+        .is_some_and(|flag| MODULE_FLAGS.contains(&flag))
+}
+
+/// Resolves a module flag's value against `dir`. Only the path after the `=` of a `name=path`
+/// pair is resolved, since the name is a module or header name rather than a path.
+fn resolve_value(value: &str, dir: &Path) -> String {
+    let (name, value_path) = match value.split_once('=') {
+        Some((name, value_path)) => (Some(name), value_path),
+        None => (None, value),
+    };
+    let resolved = paths::absolute(dir.join(value_path))
+        .to_string_lossy()
+        .to_string();
+    match name {
+        Some(name) => format!("{}={}", name, resolved),
+        None => resolved,
+    }
+}
+
+/// Makes the paths passed to module flags absolute, so they don't depend on the directory the
+/// command is run from.
+pub fn resolve_paths(entry: &mut CompileCommandsEntry) {
+    let mut tokens = cmdline::split(&entry.command);
+    let mut changed = false;
+    for i in 1..tokens.len() {
+        if is_module_flag(&tokens[i - 1]) {
+            let resolved = resolve_value(&tokens[i], &entry.directory);
+            changed |= resolved != tokens[i];
+            tokens[i] = resolved;
+        }
+    }
+    if changed {
+        entry.command = cmdline::join(&tokens);
+    }
+}
+
+/// Removes module flags and their values, for consumers (like clangd) that don't understand them.
+pub fn strip(entry: &mut CompileCommandsEntry) {
+    let tokens = cmdline::split(&entry.command);
+    if !tokens.iter().any(|token| is_module_flag(token)) {
+        return;
+    }
+    let mut kept = Vec::new();
+    let mut tokens = tokens.into_iter();
+    while let Some(token) = tokens.next() {
+        if is_module_flag(&token) {
+            tokens.next();
+        } else {
+            kept.push(token);
+        }
+    }
+    entry.command = cmdline::join(&kept);
+}
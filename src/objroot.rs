@@ -0,0 +1,91 @@
+//! Rules inferring the directory a command ran in from the object directory it writes to, for
+//! commands whose thread has no banner. Razzle builds put objects under an object root that mirrors
+//! the source tree, so the object directory often names the source directory uniquely:
+//!
+//! ```toml
+//! [[objroot]]
+//! # D:\os\obj\amd64fre\net\tcp\objfre\amd64 holds the objects built in D:\os\src\net\tcp
+//! pattern = '(?i)^D:\\os\\obj\\[^\\]+\\(?P<dir>.+)\\obj(fre|chk)\\[^\\]+$'
+//! source = 'D:\os\src\${dir}'
+//! ```
+//!
+//! `pattern` is matched against the directory of the `/Fo` path as it appears in the command, and
+//! `source` may refer to its capture groups. Rules are tried in order, and the first giving an
+//! absolute path wins.
+
+use regex::Regex;
+use std::path::PathBuf;
+
+#[derive(serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ObjRootConfig {
+    pattern: String,
+    source: String,
+}
+
+#[derive(Clone)]
+pub struct ObjRoot {
+    pattern: Regex,
+    source: String,
+}
+
+impl ObjRoot {
+    pub fn from_config(config: &ObjRootConfig) -> ObjRoot {
+        ObjRoot {
+            pattern: Regex::new(&config.pattern)
+                .unwrap_or_else(|e| panic!("Invalid objroot pattern {}: {}", config.pattern, e)),
+            source: config.source.clone(),
+        }
+    }
+
+    /// The pattern and source, for digesting the options.
+    pub fn key(&self) -> (&str, &str) {
+        (self.pattern.as_str(), &self.source)
+    }
+}
+
+/// The source directory the first of `rules` matching `object_dir` maps it to, if any.
+pub fn infer(rules: &[ObjRoot], object_dir: &str) -> Option<PathBuf> {
+    rules.iter().find_map(|rule| {
+        let caps = rule.pattern.captures(object_dir)?;
+        let mut source = String::new();
+        caps.expand(&rule.source, &mut source);
+        let source = PathBuf::from(source);
+        source.is_absolute().then_some(source)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(pattern: &str, source: &str) -> ObjRoot {
+        ObjRoot::from_config(&ObjRootConfig {
+            pattern: pattern.to_string(),
+            source: source.to_string(),
+        })
+    }
+
+    #[test]
+    fn maps_object_dirs_to_source_dirs() {
+        let rules = [rule(
+            r"(?i)^/os/obj/[^/]+/(?P<dir>.+)/obj(fre|chk)/[^/]+$",
+            "/os/src/${dir}",
+        )];
+        assert_eq!(
+            infer(&rules, "/os/obj/amd64fre/net/tcp/objfre/amd64"),
+            Some(PathBuf::from("/os/src/net/tcp"))
+        );
+        assert_eq!(infer(&rules, "/os/src/net/tcp/objfre/amd64"), None);
+    }
+
+    #[test]
+    fn the_first_rule_giving_an_absolute_path_wins() {
+        let rules = [
+            rule(r"^/obj/(?P<dir>.+)$", "relative/${dir}"),
+            rule(r"^/obj/(?P<dir>.+)$", "/src/${dir}"),
+            rule(r"^/obj/(?P<dir>.+)$", "/other/${dir}"),
+        ];
+        assert_eq!(infer(&rules, "/obj/net"), Some(PathBuf::from("/src/net")));
+    }
+}
@@ -0,0 +1,125 @@
+//! The overrides file, compile_commands.overrides.json, which patches entries for files matching
+//! globs as the last step of every run. It lives next to compile_commands.json and can be checked
+//! in, so fixes for known-bad entries survive regeneration.
+//!
+//! ```json
+//! [
+//!   { "files": "src/legacy/**", "remove": ["/WX", "/analyze*"], "append": ["/DLEGACY"] },
+//!   { "files": "src/gen/*.cpp", "replace": "cl /nologo /std:c++17 {file}" }
+//! ]
+//! ```
+//!
+//! `files` is relative to the overrides file. Each matching override replaces the command (with
+//! `{file}` standing for the entry's file), then removes the arguments matching any of the
+//! `remove` globs, then appends the `append` arguments (before any `/link`).
+
+use crate::{CompileCommandsEntry, canonical, cmdline, paths};
+use std::{fs, path::Path};
+
+#[derive(serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+struct OverrideConfig {
+    files: String,
+    #[serde(default)]
+    replace: Option<String>,
+    #[serde(default)]
+    remove: Vec<String>,
+    #[serde(default)]
+    append: Vec<String>,
+}
+
+struct Override {
+    files: glob::Pattern,
+    replace: Option<String>,
+    remove: Vec<glob::Pattern>,
+    append: Vec<String>,
+}
+
+pub struct Overrides {
+    overrides: Vec<Override>,
+}
+
+/// The overrides file's name. There's one per output directory, shared by each flavor's database.
+pub const FILE_NAME: &str = "compile_commands.overrides.json";
+
+/// Joins `files` onto `root`, resolving `..` so the pattern can match the absolute, normalized
+/// paths of entries.
+fn files_pattern(root: &Path, files: &str) -> glob::Pattern {
+    pattern(&paths::normalize(&root.join(files)).to_string_lossy())
+}
+
+fn pattern(pattern: &str) -> glob::Pattern {
+    glob::Pattern::new(pattern).unwrap_or_else(|_| panic!("Invalid override pattern {}", pattern))
+}
+
+impl Overrides {
+    /// Reads the overrides file at `path`, or returns no overrides if it doesn't exist.
+    pub fn load(path: &Path) -> Overrides {
+        if !path.exists() {
+            return Overrides {
+                overrides: Vec::new(),
+            };
+        }
+        let json = fs::read_to_string(path)
+            .unwrap_or_else(|_| panic!("Failed to read overrides from {}", path.display()));
+        let overrides: Vec<OverrideConfig> = serde_json::from_str(&json)
+            .unwrap_or_else(|e| panic!("Failed to parse overrides from {}: {}", path.display(), e));
+        let root = path.parent().unwrap_or(Path::new(""));
+        Overrides {
+            overrides: overrides
+                .into_iter()
+                .map(|o| Override {
+                    files: files_pattern(root, &o.files),
+                    replace: o.replace,
+                    remove: o.remove.iter().map(|r| pattern(r)).collect(),
+                    append: o.append,
+                })
+                .collect(),
+        }
+    }
+
+    /// The position in the overrides file (counting from 1) of the last override for `file` that
+    /// adds an argument matching `matches`, by appending it or replacing the command with one
+    /// that has it.
+    pub fn adding(&self, file: &str, matches: impl Fn(&str) -> bool) -> Option<usize> {
+        let position = self.overrides.iter().rposition(|o| {
+            o.files.matches(file)
+                && (o.append.iter().any(|argument| matches(argument))
+                    || o.replace.as_deref().is_some_and(|replace| {
+                        canonical::arguments(replace).iter().any(|a| matches(a))
+                    }))
+        })?;
+        Some(position + 1)
+    }
+
+    /// Applies every override matching the entry's file, returning whether any did.
+    pub fn apply(&self, entry: &mut CompileCommandsEntry) -> bool {
+        let mut applied = false;
+        for o in &self.overrides {
+            if !o.files.matches(&entry.file) {
+                continue;
+            }
+            applied = true;
+            if let Some(replace) = &o.replace {
+                entry.command = replace.replace("{file}", &cmdline::quote(&entry.file));
+            }
+            if o.remove.is_empty() && o.append.is_empty() {
+                continue;
+            }
+            let mut tokens = cmdline::split(&entry.command);
+            // Never remove the program
+            let mut index = 0;
+            tokens.retain(|token| {
+                index += 1;
+                index == 1 || !o.remove.iter().any(|pattern| pattern.matches(token))
+            });
+            let link = tokens
+                .iter()
+                .position(|token| token.eq_ignore_ascii_case("/link"))
+                .unwrap_or(tokens.len());
+            tokens.splice(link..link, o.append.iter().cloned());
+            entry.command = cmdline::join(&tokens);
+        }
+        applied
+    }
+}
@@ -0,0 +1,58 @@
+//! Resolving paths to absolute ones. On network shares and subst'd drives the OS call behind
+//! `path::absolute` can fail transiently, which shouldn't end a run that's nearly done.
+
+use std::{
+    env,
+    path::{self, Component, Path, PathBuf},
+    thread,
+    time::Duration,
+};
+
+/// How many times resolving a path is tried before falling back to resolving it lexically.
+const ATTEMPTS: u32 = 3;
+const RETRY_DELAY: Duration = Duration::from_millis(50);
+
+/// `path` with `.` components removed and `..` components resolved against the component before
+/// them, the way Windows resolves paths.
+pub fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            Component::CurDir => {}
+            component => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+/// Resolves `path` against the current directory like `path::absolute`, retrying if that fails
+/// and then falling back to joining and normalizing it lexically.
+pub fn absolute(path: impl AsRef<Path>) -> PathBuf {
+    let path = path.as_ref();
+    let mut error = None;
+    for attempt in 0..ATTEMPTS {
+        if attempt > 0 {
+            thread::sleep(RETRY_DELAY);
+        }
+        match path::absolute(path) {
+            Ok(absolute) => return absolute,
+            Err(e) => error = Some(e),
+        }
+    }
+
+    let joined = match env::current_dir() {
+        Ok(dir) if !path.is_absolute() => dir.join(path),
+        _ => path.to_path_buf(),
+    };
+    let resolved = normalize(&joined);
+    println!(
+        "Warning: failed to resolve {} ({}), so resolved it lexically as {}",
+        path.display(),
+        error.expect("Resolving was attempted"),
+        resolved.display()
+    );
+    resolved
+}
@@ -0,0 +1,92 @@
+//! The prefix build.exe puts on each line naming the thread that wrote it, like `0012>`. Forks of
+//! build.exe that write another format, like `[T12] ` or `12: `, can describe it in the config
+//! file with a regex capturing the thread as `thread` and the rest of the line as `payload`:
+//!
+//! ```toml
+//! thread_prefix = '^\[T(?P<thread>\d+)\] (?P<payload>.*)$'
+//! ```
+//!
+//! A command's continuation lines are the lines from its thread whose payload is indented.
+
+use regex::Regex;
+
+/// build.exe's own prefix.
+const DEFAULT_PATTERN: &str = r"^(?P<thread>\d{4})>(?P<payload>.*)$";
+
+/// How many digits build.exe's prefix numbers the thread with.
+pub const DEFAULT_WIDTH: usize = 4;
+
+#[derive(Clone)]
+pub struct ThreadPrefix {
+    re: Regex,
+}
+
+impl Default for ThreadPrefix {
+    fn default() -> ThreadPrefix {
+        ThreadPrefix::new(DEFAULT_PATTERN)
+    }
+}
+
+impl ThreadPrefix {
+    pub fn new(pattern: &str) -> ThreadPrefix {
+        let re = Regex::new(pattern)
+            .unwrap_or_else(|e| panic!("Invalid thread prefix {}: {}", pattern, e));
+        for group in ["thread", "payload"] {
+            if !re.capture_names().any(|name| name == Some(group)) {
+                panic!("The thread prefix {} doesn't capture `{}`", pattern, group);
+            }
+        }
+        ThreadPrefix { re }
+    }
+
+    /// Whether this is build.exe's own prefix.
+    pub fn is_default(&self) -> bool {
+        self.re.as_str() == DEFAULT_PATTERN
+    }
+
+    /// Splits `line` into the thread that wrote it, if it has a prefix, and the rest of the line.
+    pub fn split<'a>(&self, line: &'a str) -> (Option<&'a str>, &'a str) {
+        match self.re.captures(line) {
+            Some(caps) => (
+                caps.name("thread").map(|thread| thread.as_str()),
+                caps.name("payload").map_or("", |payload| payload.as_str()),
+            ),
+            None => (None, line),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_build_exes_prefix() {
+        let prefix = ThreadPrefix::default();
+        assert!(prefix.is_default());
+        assert_eq!(
+            prefix.split("0012>cl /c a.cpp"),
+            (Some("0012"), "cl /c a.cpp")
+        );
+        assert_eq!(prefix.split("0012>"), (Some("0012"), ""));
+        // Only build.exe's width of digits is a prefix
+        assert_eq!(prefix.split("012>cl"), (None, "012>cl"));
+        assert_eq!(prefix.split("00012>cl"), (None, "00012>cl"));
+        assert_eq!(prefix.split("cl /c a.cpp"), (None, "cl /c a.cpp"));
+    }
+
+    #[test]
+    fn splits_other_formats() {
+        let prefix = ThreadPrefix::new(r"^\[T(?P<thread>\d+)\] (?P<payload>.*)$");
+        assert!(!prefix.is_default());
+        assert_eq!(prefix.split("[T3] cl /c a.cpp"), (Some("3"), "cl /c a.cpp"));
+        assert_eq!(prefix.split("[T123]   /DX"), (Some("123"), "  /DX"));
+        assert_eq!(prefix.split("0012>cl"), (None, "0012>cl"));
+    }
+
+    #[test]
+    #[should_panic(expected = "doesn't capture `payload`")]
+    fn requires_both_groups() {
+        ThreadPrefix::new(r"^(?P<thread>\d+): ");
+    }
+}
@@ -0,0 +1,65 @@
+//! The index priority file, compile_commands.priority.txt, which lists the database's files in the
+//! order clangd's background index should get to them, for the editor plugin to pass on: the files
+//! compiled in the latest builds first, since they're what's being worked on, then the files
+//! queried with the `query` subcommand, most recently queried first, and then everything else.
+
+use crate::{CompileCommandsEntry, metadata::Metadata};
+use std::{
+    cmp::Reverse,
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// How long before the newest entry was seen an entry counts as recently compiled.
+const RECENT_SECS: u64 = 24 * 60 * 60;
+
+/// The priority file's path for the compile_commands.json at `compile_commands_path`.
+pub fn path_for(compile_commands_path: &Path) -> PathBuf {
+    compile_commands_path.with_extension("priority.txt")
+}
+
+/// The files of `entries` in the order they should be indexed.
+pub fn order<'a>(entries: &'a [CompileCommandsEntry], metadata: &Metadata) -> Vec<&'a str> {
+    let times = |file: &str| {
+        metadata
+            .entries
+            .get(file)
+            .map_or((0, 0), |m| (m.last_seen, m.last_queried))
+    };
+    let newest = entries
+        .iter()
+        .map(|entry| times(&entry.file).0)
+        .max()
+        .unwrap_or_default();
+    let recent = newest.saturating_sub(RECENT_SECS);
+    let mut files: Vec<&str> = entries.iter().map(|entry| entry.file.as_str()).collect();
+    files.sort_by_cached_key(|file| {
+        let (last_seen, last_queried) = times(file);
+        let tier = if last_seen != 0 && last_seen >= recent {
+            0
+        } else if last_queried != 0 {
+            1
+        } else {
+            2
+        };
+        let time = if tier == 1 { last_queried } else { last_seen };
+        (tier, Reverse(time), *file)
+    });
+    files
+}
+
+/// Writes the priority file for the compile_commands.json at `compile_commands_path`.
+pub fn write(compile_commands_path: &Path, entries: &[CompileCommandsEntry], metadata: &Metadata) {
+    let path = path_for(compile_commands_path);
+    let mut contents = String::new();
+    for file in order(entries, metadata) {
+        contents.push_str(file);
+        contents.push('\n');
+    }
+    fs::write(&path, contents).unwrap_or_else(|_| panic!("Failed to write {}", path.display()));
+    println!(
+        "Wrote the indexing priority of {} files to {}",
+        entries.len(),
+        path.display()
+    );
+}
@@ -0,0 +1,160 @@
+//! The `query` subcommand, which shows a file's entry and can trace where one of its flags came
+//! from.
+
+use crate::{
+    CompileCommandsEntry, GenerateOptions, RawCommand, canonical, cmdline, compress, lock,
+    logged_commands,
+    metadata::{self, Metadata},
+    overrides::{self, Overrides},
+    paths, store,
+};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+/// The command that produced `target`'s entry, with the line it's on, by the same precedence
+/// generation uses: the last command in the latest pass wins.
+fn logged_command(
+    log_path: &Path,
+    target: &str,
+    options: &GenerateOptions,
+) -> Option<(usize, RawCommand)> {
+    let target = options.key_case.key(target);
+    let mut winner: Option<(u32, usize, RawCommand)> = None;
+    for logged in logged_commands(log_path, options) {
+        let (pass, line_number) = (logged.pass, logged.line_number);
+        let Some(dir) = logged.dir else {
+            continue;
+        };
+        let command = RawCommand {
+            dir,
+            lines: logged.lines,
+            compiler: logged.compiler,
+        };
+        let compiles_target = CompileCommandsEntry::from_raw_command(&command).any(|mut entry| {
+            for mapping in &options.source_root_map {
+                mapping.apply_to_entry(&mut entry);
+            }
+            options.key_case.key(&entry.file) == target
+        });
+        if compiles_target && winner.as_ref().is_none_or(|(p, _, _)| *p <= pass) {
+            winner = Some((pass, line_number, command));
+        }
+    }
+    winner.map(|(_, line_number, command)| (line_number, command))
+}
+
+/// The first response file `command` names that has an argument matching `matches`.
+fn response_file_with(
+    command: &str,
+    dir: &Path,
+    matches: impl Fn(&str) -> bool,
+) -> Option<PathBuf> {
+    cmdline::split(command)
+        .iter()
+        .filter_map(|token| token.strip_prefix('@'))
+        .map(|response_file| dir.join(response_file))
+        .find(|path| {
+            fs::read_to_string(path).is_ok_and(|contents| {
+                canonical::normalize_all(cmdline::split(&contents).into_iter())
+                    .iter()
+                    .any(|argument| matches(argument))
+            })
+        })
+}
+
+/// Records in the sidecar that `file`'s entry was queried, which --index-priority ranks it by.
+fn record_query(compile_commands_path: &Path, file: &str) {
+    let metadata_path = metadata::path_for(compile_commands_path);
+    let _lock = lock::acquire(
+        compile_commands_path,
+        Duration::from_secs(lock::DEFAULT_TIMEOUT),
+    );
+    let mut metadata = Metadata::load(&metadata_path);
+    if let Some(entry_metadata) = metadata.entries.get_mut(file) {
+        entry_metadata.last_queried = metadata::now();
+        metadata.save(&metadata_path, compress::written_with(&metadata_path));
+    }
+}
+
+pub fn query(
+    file: &str,
+    output_dir: &str,
+    log_path: Option<&str>,
+    trace_flag: Option<&str>,
+    options: &GenerateOptions,
+) {
+    let key_case = options.key_case;
+    let target = paths::absolute(file).to_string_lossy().to_string();
+    let output_dir = paths::absolute(output_dir);
+    let compile_commands_path = output_dir.join("compile_commands.json");
+    let Some(entry) = store::find_entry(&compile_commands_path, &target, key_case) else {
+        println!(
+            "{} has no entry for {}",
+            compile_commands_path.display(),
+            target
+        );
+        return;
+    };
+    record_query(&compile_commands_path, &entry.file);
+    println!("file:      {}", entry.file);
+    println!("directory: {}", entry.directory.display());
+    println!("command:   {}", entry.command);
+
+    let Some(flag) = trace_flag else {
+        return;
+    };
+    println!();
+    // A flag matches an argument that is it, or is it with a value, so /DFOO matches /DFOO=1
+    let flag = canonical::normalize(flag, None);
+    let matches = |argument: &str| argument.starts_with(&flag);
+    let Some(argument) = canonical::arguments(&entry.command)
+        .into_iter()
+        .find(|argument| matches(argument))
+    else {
+        match response_file_with(&entry.command, &entry.directory, matches) {
+            Some(path) => println!(
+                "{} comes from response file {}, which the entry names",
+                flag,
+                path.display()
+            ),
+            None => println!("{} is not in the entry", flag),
+        }
+        return;
+    };
+    println!("{} is in the entry as {}", flag, argument);
+
+    let overrides_path = output_dir.join(overrides::FILE_NAME);
+    if let Some(position) = Overrides::load(&overrides_path).adding(&entry.file, matches) {
+        println!(
+            "It comes from override {} in {}",
+            position,
+            overrides_path.display()
+        );
+        return;
+    }
+
+    let Some(log_path) = log_path else {
+        println!("Pass the log with --log to trace it further");
+        return;
+    };
+    let Some((line_number, command)) = logged_command(Path::new(log_path), &target, options) else {
+        println!("{} has no command for the file", log_path);
+        return;
+    };
+    let logged_arguments = canonical::arguments(&command.full_command());
+    if logged_arguments.iter().any(|argument| matches(argument)) {
+        println!(
+            "It comes from the command on line {} of {}",
+            line_number, log_path
+        );
+        return;
+    }
+    println!(
+        "It isn't in the logged command on line {} of {}, so it was added while generating \
+         the entry (for example by --default-std or --source-root-map)",
+        line_number, log_path
+    );
+}
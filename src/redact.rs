@@ -0,0 +1,191 @@
+//! Redacting secrets, like signing or telemetry tokens passed as `/DSIGN_TOKEN=...` or to a
+//! wrapper, from the commands written to the database, which developers sync around. The built-in
+//! patterns cover common token formats, and the config file can list more:
+//!
+//! ```toml
+//! redact = ['--telemetry-key (?P<secret>\S+)']
+//! ```
+//!
+//! The part of a match captured as `secret` is replaced, or the whole match if there's no such
+//! group. Commands are redacted as soon as they're resolved, so that the journal, the command
+//! cache and the parse trace never hold a secret either, and again with their environment before
+//! they're written, for secrets overrides or merged entries bring in.
+
+use crate::CompileCommandsEntry;
+use regex::{Captures, Regex};
+
+/// What a secret is replaced with.
+const REDACTED: &str = "<redacted>";
+
+const BUILTIN_PATTERNS: &[&str] = &[
+    // Variables named for a secret, set to something long enough to be one
+    r#"(?i)\b\w*(?:token|secret|password|passwd|api_?key|access_?key|private_?key)[=:](?:\\?")?(?P<secret>[^\s\\"<]{8,})"#,
+    // GitHub tokens
+    r"\bgh[pousr]_[A-Za-z0-9]{36,}\b",
+    // AWS access key ids
+    r"\b(?:AKIA|ASIA)[0-9A-Z]{16}\b",
+    // JSON web tokens
+    r"\beyJ[A-Za-z0-9_-]{10,}\.[A-Za-z0-9_-]{10,}\.[A-Za-z0-9_-]{10,}",
+    // Shared access signatures in Azure storage URLs
+    r"[?&]sig=(?P<secret>[A-Za-z0-9%+/=]{16,})",
+];
+
+#[derive(Clone)]
+pub struct Redactor {
+    patterns: Vec<Regex>,
+}
+
+impl Default for Redactor {
+    fn default() -> Redactor {
+        Redactor::new(&[])
+    }
+}
+
+impl Redactor {
+    /// A redactor for the built-in patterns and `extra_patterns`.
+    pub fn new(extra_patterns: &[String]) -> Redactor {
+        let patterns = BUILTIN_PATTERNS
+            .iter()
+            .copied()
+            .chain(extra_patterns.iter().map(String::as_str))
+            .map(|pattern| {
+                Regex::new(pattern)
+                    .unwrap_or_else(|e| panic!("Invalid redact pattern {}: {}", pattern, e))
+            })
+            .collect();
+        Redactor { patterns }
+    }
+
+    /// The patterns, for digesting the options.
+    pub fn key(&self) -> Vec<&str> {
+        self.patterns.iter().map(Regex::as_str).collect()
+    }
+
+    /// Redacts the secrets in `entry`'s command and environment, returning how many there were.
+    pub fn redact_entry(&self, entry: &mut CompileCommandsEntry) -> usize {
+        let mut redacted = self.redact(&mut entry.command);
+        for (name, value) in entry.environment.iter_mut().flatten() {
+            // Variables are matched as they'd be set, so that their names count
+            let mut assignment = format!("{name}={value}");
+            let count = self.redact(&mut assignment);
+            if count == 0 {
+                continue;
+            }
+            redacted += count;
+            // A match that took in the name as well takes the whole value with it
+            *value = match assignment.strip_prefix(&format!("{name}=")) {
+                Some(redacted_value) => redacted_value.to_string(),
+                None => REDACTED.to_string(),
+            };
+        }
+        redacted
+    }
+
+    /// `text` with its secrets redacted.
+    pub fn redacted(&self, text: &str) -> String {
+        let mut text = text.to_string();
+        self.redact(&mut text);
+        text
+    }
+
+    /// Redacts the secrets in `command`, returning how many there were.
+    pub fn redact(&self, command: &mut String) -> usize {
+        let mut redacted = 0;
+        for pattern in &self.patterns {
+            if !pattern.is_match(command) {
+                continue;
+            }
+            let replaced = pattern.replace_all(command, |caps: &Captures| {
+                let whole = caps.get(0).unwrap();
+                let secret = caps.name("secret").unwrap_or(whole);
+                // Entries merged from an earlier run were already redacted
+                if secret.as_str() == REDACTED {
+                    return whole.as_str().to_string();
+                }
+                redacted += 1;
+                format!(
+                    "{}{}{}",
+                    &whole.as_str()[..secret.start() - whole.start()],
+                    REDACTED,
+                    &whole.as_str()[secret.end() - whole.start()..]
+                )
+            });
+            *command = replaced.into_owned();
+        }
+        redacted
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn entry(command: &str, environment: &[(&str, &str)]) -> CompileCommandsEntry {
+        CompileCommandsEntry {
+            directory: PathBuf::from(r"C:\src"),
+            command: command.to_string(),
+            file: String::from(r"C:\src\a.cpp"),
+            environment: (!environment.is_empty()).then(|| {
+                environment
+                    .iter()
+                    .map(|(name, value)| (name.to_string(), value.to_string()))
+                    .collect()
+            }),
+        }
+    }
+
+    #[test]
+    fn redacts_the_command_and_the_environment() {
+        let redactor = Redactor::default();
+        let mut signed = entry(
+            "cl.exe /DSIGN_TOKEN=abcdef123456 /c a.cpp",
+            &[
+                ("_CL_", "/DAPI_KEY=0123456789abcdef"),
+                ("INCLUDE", r"C:\inc"),
+            ],
+        );
+        assert_eq!(redactor.redact_entry(&mut signed), 2);
+        assert_eq!(signed.command, "cl.exe /DSIGN_TOKEN=<redacted> /c a.cpp");
+        let environment = signed.environment.unwrap();
+        assert_eq!(environment["_CL_"], "/DAPI_KEY=<redacted>");
+        assert_eq!(environment["INCLUDE"], r"C:\inc");
+    }
+
+    #[test]
+    fn matches_variables_by_name() {
+        let redactor = Redactor::default();
+        let mut token = entry("cl.exe /c a.cpp", &[("SIGN_TOKEN", "abcdef123456")]);
+        assert_eq!(redactor.redact_entry(&mut token), 1);
+        assert_eq!(token.environment.unwrap()["SIGN_TOKEN"], "<redacted>");
+
+        let mut plain = entry("cl.exe /c a.cpp", &[("INCLUDE", r"C:\inc")]);
+        assert_eq!(redactor.redact_entry(&mut plain), 0);
+    }
+
+    #[test]
+    fn redacts_whole_assignments() {
+        // Patterns without a secret group take in the name, and short names mustn't lose part
+        // of the value instead
+        let redactor = Redactor::new(&[r"SIGN_TOKEN=\S+".to_string(), r"\bA=\S+".to_string()]);
+        let mut assigned = entry("cl.exe /c a.cpp", &[("SIGN_TOKEN", "x"), ("A", "abc")]);
+        assert_eq!(redactor.redact_entry(&mut assigned), 2);
+        let environment = assigned.environment.unwrap();
+        assert_eq!(environment["SIGN_TOKEN"], "<redacted>");
+        assert_eq!(environment["A"], "<redacted>");
+    }
+
+    #[test]
+    fn leaves_redacted_secrets_uncounted() {
+        let redactor = Redactor::new(&[r"--telemetry-key (?P<secret>\S+)".to_string()]);
+        let mut entry = entry(
+            "cl.exe /DSIGN_TOKEN=<redacted> --telemetry-key <redacted> /c a.cpp",
+            &[],
+        );
+        assert_eq!(redactor.redact_entry(&mut entry), 0);
+        assert_eq!(
+            entry.command,
+            "cl.exe /DSIGN_TOKEN=<redacted> --telemetry-key <redacted> /c a.cpp"
+        );
+    }
+}
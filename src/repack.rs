@@ -0,0 +1,110 @@
+//! Keeping commands under a length limit, for consumers that re-run them (CreateProcess limits a
+//! command line to 32,767 characters), by moving their flags into a response file.
+
+use crate::{CompileCommandsEntry, cmdline, paths};
+use std::{
+    collections::HashSet,
+    fs,
+    hash::{DefaultHasher, Hasher},
+    path::{Path, PathBuf},
+};
+
+/// The directory response files for the database at `database_path` are written to.
+pub fn dir_for(database_path: &Path) -> PathBuf {
+    database_path.with_extension("rsp")
+}
+
+/// A response file to write.
+pub struct ResponseFile {
+    path: PathBuf,
+    contents: String,
+}
+
+/// Moves the flags of `entry`'s command into a response file in `dir` if the command is longer
+/// than `max_length`, returning the response file to write. The program, the entry's source file
+/// and any response files it already names stay in the command, in their original order, with the
+/// new response file where the first flag was.
+pub fn repack(
+    entry: &mut CompileCommandsEntry,
+    max_length: usize,
+    dir: &Path,
+) -> Option<ResponseFile> {
+    if entry.command.chars().count() <= max_length {
+        return None;
+    }
+    let tokens = cmdline::split(&entry.command);
+    let is_source =
+        |token: &str| paths::absolute(entry.directory.join(token)).to_string_lossy() == entry.file;
+
+    let mut kept = Vec::new();
+    let mut moved = Vec::new();
+    let mut response_file_at = None;
+    for (i, token) in tokens.into_iter().enumerate() {
+        if i == 0 || token.starts_with('@') || is_source(&token) {
+            kept.push(token);
+        } else {
+            response_file_at.get_or_insert(kept.len());
+            moved.push(token);
+        }
+    }
+    let response_file_at = response_file_at?;
+
+    // Named by its contents, so entries with the same flags share one response file and the
+    // command doesn't change from run to run
+    let contents = moved
+        .iter()
+        .map(|token| cmdline::quote(token))
+        .collect::<Vec<_>>()
+        .join("\n");
+    let mut hasher = DefaultHasher::new();
+    hasher.write(contents.as_bytes());
+    let path = dir.join(format!("{:016x}.rsp", hasher.finish()));
+
+    kept.insert(response_file_at, format!("@{}", path.to_string_lossy()));
+    entry.command = cmdline::join(&kept);
+    Some(ResponseFile { path, contents })
+}
+
+impl ResponseFile {
+    /// Writes the response file, unless an identical one already exists. cl.exe reads response
+    /// files in the ANSI code page unless they're UTF-16, so any with non-ASCII characters are
+    /// written as UTF-16 with a byte order mark.
+    pub fn write(&self) {
+        if self.path.is_file() {
+            return;
+        }
+        let bytes: Vec<u8> = if self.contents.is_ascii() {
+            self.contents.clone().into_bytes()
+        } else {
+            [0xfeff]
+                .into_iter()
+                .chain(self.contents.encode_utf16())
+                .flat_map(u16::to_le_bytes)
+                .collect()
+        };
+        fs::write(&self.path, bytes)
+            .unwrap_or_else(|_| panic!("Failed to write response file {}", self.path.display()));
+    }
+}
+
+/// Removes the response files in `dir` that none of `entries` name anymore, returning how many
+/// were removed.
+pub fn collect_garbage(dir: &Path, entries: &[CompileCommandsEntry]) -> usize {
+    let Ok(files) = fs::read_dir(dir) else {
+        return 0;
+    };
+    let named: HashSet<PathBuf> = entries
+        .iter()
+        .flat_map(|entry| cmdline::split(&entry.command))
+        .filter_map(|token| token.strip_prefix('@').map(PathBuf::from))
+        .collect();
+    let mut removed = 0;
+    for file in files.filter_map(Result::ok).map(|file| file.path()) {
+        if file.extension().is_some_and(|extension| extension == "rsp") && !named.contains(&file) {
+            fs::remove_file(&file)
+                .unwrap_or_else(|_| panic!("Failed to remove {}", file.display()));
+            removed += 1;
+        }
+    }
+    removed
+}
@@ -0,0 +1,148 @@
+//! Re-rooting entries from logs of distributed or cloud builds, which record paths on the build
+//! agent (like `D:\work\1\s\...`), onto the local enlistment.
+
+use crate::CompileCommandsEntry;
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+/// How many entries with missing files are sampled when detecting the source root.
+const DETECTION_SAMPLE: usize = 200;
+
+/// A mapping from a path prefix on the build agent to the local path it corresponds to.
+#[derive(Clone)]
+pub struct RootMapping {
+    pub agent: String,
+    pub local: String,
+}
+
+/// Parses a mapping given as `<agent-prefix>=<local-prefix>`.
+pub fn parse_mapping(s: &str) -> Result<RootMapping, String> {
+    let (agent, local) = s
+        .split_once('=')
+        .ok_or_else(|| format!("expected <agent-prefix>=<local-prefix>, got {s}"))?;
+    Ok(RootMapping {
+        agent: agent.trim_end_matches(['\\', '/']).to_string(),
+        local: local.trim_end_matches(['\\', '/']).to_string(),
+    })
+}
+
+impl RootMapping {
+    /// Replaces every occurrence of the agent prefix in `s` that ends at a path boundary. Windows
+    /// paths are case-insensitive, so the prefix is matched case-insensitively.
+    pub fn apply<'a>(&self, s: &'a str) -> Cow<'a, str> {
+        let lower = s.to_ascii_lowercase();
+        let agent = self.agent.to_ascii_lowercase();
+        let mut rewritten = String::new();
+        let mut copied = 0;
+        for (start, _) in lower.match_indices(&agent) {
+            let end = start + agent.len();
+            let at_boundary = s[end..]
+                .chars()
+                .next()
+                .is_none_or(|c| matches!(c, '\\' | '/' | '"' | ' ' | '\t'));
+            if at_boundary && start >= copied {
+                rewritten.push_str(&s[copied..start]);
+                rewritten.push_str(&self.local);
+                copied = end;
+            }
+        }
+        if copied == 0 {
+            return Cow::Borrowed(s);
+        }
+        rewritten.push_str(&s[copied..]);
+        Cow::Owned(rewritten)
+    }
+
+    pub fn apply_to_entry(&self, entry: &mut CompileCommandsEntry) {
+        entry.file = self.apply(&entry.file).into_owned();
+        entry.command = self.apply(&entry.command).into_owned();
+        entry.directory = self.apply_to_path(&entry.directory);
+    }
+
+    pub fn apply_to_path(&self, path: &Path) -> PathBuf {
+        PathBuf::from(self.apply(&path.to_string_lossy()).into_owned())
+    }
+}
+
+/// Detects the agent's source root by finding, for entries whose files don't exist, the longest
+/// trailing part of the path that does exist under `local_root`. The agent prefix agreed on by
+/// the most entries wins.
+pub fn detect<'a>(
+    entries: impl IntoIterator<Item = &'a CompileCommandsEntry>,
+    local_root: &Path,
+) -> Option<RootMapping> {
+    let mut votes: HashMap<&str, usize> = HashMap::new();
+    let missing = entries
+        .into_iter()
+        .filter(|entry| !Path::new(&entry.file).exists())
+        .take(DETECTION_SAMPLE);
+    for entry in missing {
+        let file = entry.file.as_str();
+        // Separator positions, from the one right after the drive or root onwards
+        let separators = file.match_indices(['\\', '/']).map(|(i, _)| i);
+        for separator in separators {
+            let relative = &file[separator + 1..];
+            let candidate = relative
+                .split(['\\', '/'])
+                .fold(local_root.to_path_buf(), |path, component| {
+                    path.join(component)
+                });
+            if candidate.is_file() {
+                *votes.entry(&file[..separator]).or_default() += 1;
+                break;
+            }
+        }
+    }
+
+    let (agent, count) = votes
+        .into_iter()
+        .max_by(|(a, a_count), (b, b_count)| a_count.cmp(b_count).then(b.cmp(a)))?;
+    let local = local_root.to_string_lossy().to_string();
+    println!(
+        "Detected source root {} -> {} from {} files",
+        agent, local, count
+    );
+    Some(RootMapping {
+        agent: agent.to_string(),
+        local,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mapping(s: &str) -> RootMapping {
+        parse_mapping(s).unwrap()
+    }
+
+    #[test]
+    fn parses_mappings() {
+        let mapping = mapping(r"D:\work\1\s\=C:\src\");
+        assert_eq!(mapping.agent, r"D:\work\1\s");
+        assert_eq!(mapping.local, r"C:\src");
+        assert!(parse_mapping(r"D:\work\1\s").is_err());
+    }
+
+    #[test]
+    fn rewrites_the_prefix_at_a_segment_boundary() {
+        let mapping = mapping(r"D:\work\1\s=C:\src");
+        assert_eq!(mapping.apply(r"D:\work\1\s\net\a.cpp"), r"C:\src\net\a.cpp");
+        assert_eq!(mapping.apply(r"d:\WORK\1\s"), r"C:\src");
+        assert_eq!(
+            mapping.apply(r#"cl /I"D:\work\1\s\inc" /ID:\work\1\s/pub D:\work\1\s\a.cpp"#),
+            r#"cl /I"C:\src\inc" /IC:\src/pub C:\src\a.cpp"#
+        );
+    }
+
+    #[test]
+    fn leaves_paths_that_only_share_characters_alone() {
+        let mapping = mapping(r"D:\work\1\s=C:\src");
+        let path = r"D:\work\1\src\a.cpp";
+        assert!(matches!(mapping.apply(path), Cow::Borrowed(p) if p == path));
+        assert_eq!(mapping.apply(r"D:\work\1\s2\a.cpp"), r"D:\work\1\s2\a.cpp");
+    }
+}
@@ -0,0 +1,180 @@
+//! Cleanup of log lines before parsing. Logs captured through `tee` can contain console control
+//! sequences, and lines written concurrently by several threads can end up torn or interleaved.
+
+use crate::prefix::{self, ThreadPrefix};
+use regex::Regex;
+
+#[derive(Default)]
+struct SanitizeStats {
+    /// Lines that had control sequences stripped from them
+    control_sequences: usize,
+    /// Lines that contained another thread's line and were split in two
+    split_lines: usize,
+    /// Lines whose thread prefix was cut short, which can't be attributed to a thread
+    torn_prefixes: usize,
+}
+
+pub struct Sanitizer {
+    control_re: Regex,
+    /// These recognize build.exe's own prefix, and aren't used for other formats
+    embedded_prefix_re: Option<Regex>,
+    torn_prefix_re: Option<Regex>,
+    stats: SanitizeStats,
+}
+
+impl Sanitizer {
+    pub fn new(thread_prefix: &ThreadPrefix) -> Self {
+        let default_prefix = thread_prefix.is_default();
+        Sanitizer {
+            // CSI sequences (colors, cursor movement), OSC sequences (window titles), other
+            // two-character escapes, and any remaining C0 control characters other than tab
+            control_re: Regex::new(
+                r"\x1b\[[0-?]*[ -/]*[@-~]|\x1b\][^\x07\x1b]*(?:\x07|\x1b\\)|\x1b[@-_]|[\x00-\x08\x0a-\x1f\x7f]",
+            )
+            .unwrap(),
+            // Another thread's prefix only follows whitespace, unlike the digits in arguments like
+            // /DBUF=<1024> or std::array<char,4096>
+            embedded_prefix_re: default_prefix.then(|| {
+                Regex::new(&format!(r"\s(\d{{{}}}>)", prefix::DEFAULT_WIDTH)).unwrap()
+            }),
+            torn_prefix_re: default_prefix.then(|| {
+                Regex::new(&format!(r"^\d{{1,{}}}>", prefix::DEFAULT_WIDTH - 1)).unwrap()
+            }),
+            stats: SanitizeStats::default(),
+        }
+    }
+
+    /// Cleans up a line of the log, passing each resulting line to `f`.
+    pub fn sanitize(&mut self, line: &str, mut f: impl FnMut(&str)) {
+        let cleaned;
+        let mut line = line;
+        if line.bytes().any(|b| (b < 0x20 && b != b'\t') || b == 0x7f) {
+            cleaned = self.control_re.replace_all(line, "");
+            line = &cleaned;
+            self.stats.control_sequences += 1;
+        }
+
+        // Another thread's output starting in the middle of the line means two writes were
+        // interleaved; the rest of the line belongs to that thread
+        while let Some(split) = self.embedded_prefix(line) {
+            self.emit(&line[..split], &mut f);
+            line = &line[split..];
+            self.stats.split_lines += 1;
+        }
+        self.emit(line, &mut f);
+    }
+
+    /// Where another thread's prefix starts in the middle of `line`, if it does. Digits inside a
+    /// quoted argument are part of the argument.
+    fn embedded_prefix(&self, line: &str) -> Option<usize> {
+        self.embedded_prefix_re
+            .as_ref()?
+            .captures_iter(line)
+            .map(|caps| caps.get(1).unwrap().start())
+            .find(|&start| line[..start].matches('"').count().is_multiple_of(2))
+    }
+
+    /// Warns about any lines that needed cleaning up.
+    pub fn report(&self) {
+        let stats = &self.stats;
+        if stats.control_sequences > 0 {
+            println!(
+                "Stripped console control sequences from {} lines",
+                stats.control_sequences
+            );
+        }
+        if stats.split_lines > 0 {
+            println!(
+                "Split {} lines that were interleaved with another thread's output",
+                stats.split_lines
+            );
+        }
+        if stats.torn_prefixes > 0 {
+            println!(
+                "Warning: ignored {} lines whose thread prefix was cut short",
+                stats.torn_prefixes
+            );
+        }
+    }
+
+    fn emit(&mut self, line: &str, f: &mut impl FnMut(&str)) {
+        if self
+            .torn_prefix_re
+            .as_ref()
+            .is_some_and(|re| re.is_match(line))
+        {
+            self.stats.torn_prefixes += 1;
+        } else {
+            f(line);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The lines `sanitize` makes of `line`.
+    fn sanitize(line: &str) -> Vec<String> {
+        let mut sanitizer = Sanitizer::new(&ThreadPrefix::default());
+        let mut lines = Vec::new();
+        sanitizer.sanitize(line, |line| lines.push(line.to_string()));
+        lines
+    }
+
+    #[test]
+    fn strips_control_sequences() {
+        assert_eq!(
+            sanitize("\x1b[32m0001>cl /c a.cpp\x1b[0m\x1b]0;title\x07"),
+            ["0001>cl /c a.cpp"]
+        );
+        assert_eq!(
+            sanitize("0001>cl /c a.cpp\x1b[0m 0002>cl /c b.cpp"),
+            ["0001>cl /c a.cpp ", "0002>cl /c b.cpp"]
+        );
+    }
+
+    #[test]
+    fn splits_interleaved_writes() {
+        assert_eq!(
+            sanitize(r"0001>cl /c a.cpp 0002>BUILDMSG: Processing d:\src 0003>cl /c b.cpp"),
+            [
+                "0001>cl /c a.cpp ",
+                r"0002>BUILDMSG: Processing d:\src ",
+                "0003>cl /c b.cpp"
+            ]
+        );
+    }
+
+    #[test]
+    fn keeps_arguments_that_look_like_prefixes() {
+        for line in [
+            "0001>cl /c /DBUF=<1024> a.cpp",
+            "0001>cl /c /DARRAY=std::array<char,4096> a.cpp",
+            "0001>cl /c /DX=12345> a.cpp",
+            r#"0001>cl /c "/DMSG=see 0002>here" a.cpp"#,
+            // Too short to be another thread's prefix, like a redirection
+            "0001>tool a.txt 2>err.txt",
+        ] {
+            assert_eq!(sanitize(line), [line]);
+        }
+    }
+
+    #[test]
+    fn drops_torn_prefixes() {
+        assert!(sanitize("01>cl /c a.cpp").is_empty());
+        assert!(sanitize(r"123>BUILDMSG: Processing d:\src").is_empty());
+        assert_eq!(sanitize("0001>cl /c a.cpp"), ["0001>cl /c a.cpp"]);
+    }
+
+    #[test]
+    fn leaves_other_prefix_formats_alone() {
+        let thread_prefix = ThreadPrefix::new(r"^\[T(?P<thread>\d+)\] (?P<payload>.*)$");
+        let mut sanitizer = Sanitizer::new(&thread_prefix);
+        let mut lines = Vec::new();
+        sanitizer.sanitize("[T1] cl /c a.cpp 0002>x", |line| {
+            lines.push(line.to_string())
+        });
+        assert_eq!(lines, ["[T1] cl /c a.cpp 0002>x"]);
+    }
+}
@@ -0,0 +1,90 @@
+//! Dropping entries for scratch sources: .cpp files a tool generated into the temp directory or a
+//! temp directory under an object directory, compiled once and deleted, which clangd can never
+//! open again. Sources under the build's TEMP and TMP directories, under any user's or Windows'
+//! temp directory, or under a tmp or temp directory inside an object directory are scratch, and
+//! the config file can list more globs:
+//!
+//! ```toml
+//! scratch = ["**/gen_scratch/**"]
+//! ```
+//!
+//! Globs match the file with either separator and in any case. Unlike excluded files, scratch
+//! files are counted on their own, so the excluded count still shows the real coverage gaps.
+
+use crate::{
+    environment::{self, Environment},
+    object_dir_parent,
+};
+use std::path::Path;
+
+const BUILTIN_PATTERNS: &[&str] = &["**/AppData/Local/Temp/**", "**/Windows/Temp/**"];
+
+/// The variables naming the build's temp directories.
+const TEMP_VARIABLES: &[&str] = &["TEMP", "TMP"];
+
+const MATCH_OPTIONS: glob::MatchOptions = glob::MatchOptions {
+    case_sensitive: false,
+    require_literal_separator: false,
+    require_literal_leading_dot: false,
+};
+
+#[derive(Clone)]
+pub struct Scratch {
+    patterns: Vec<glob::Pattern>,
+}
+
+impl Default for Scratch {
+    fn default() -> Scratch {
+        Scratch::new(&[], &Environment::new())
+    }
+}
+
+impl Scratch {
+    /// The built-in patterns, the temp directories in `variables` and `extra_patterns`.
+    pub fn new(extra_patterns: &[String], variables: &Environment) -> Scratch {
+        let temp_dirs = TEMP_VARIABLES
+            .iter()
+            .filter_map(|name| environment::lookup(variables, name))
+            .filter(|dir| !dir.is_empty())
+            .map(|dir| {
+                let dir = dir.replace('\\', "/");
+                format!("{}/**", glob::Pattern::escape(dir.trim_end_matches('/')))
+            });
+        let patterns = BUILTIN_PATTERNS
+            .iter()
+            .map(|pattern| pattern.to_string())
+            .chain(temp_dirs)
+            .chain(extra_patterns.iter().cloned())
+            .map(|pattern| {
+                glob::Pattern::new(&pattern)
+                    .unwrap_or_else(|_| panic!("Invalid scratch pattern {}", pattern))
+            })
+            .collect();
+        Scratch { patterns }
+    }
+
+    /// The patterns, for digesting the options.
+    pub fn key(&self) -> Vec<&str> {
+        self.patterns.iter().map(glob::Pattern::as_str).collect()
+    }
+
+    /// Whether `file` is a scratch source.
+    pub fn contains(&self, file: &str) -> bool {
+        let normalized = file.replace('\\', "/");
+        self.patterns
+            .iter()
+            .any(|pattern| pattern.matches_with(&normalized, MATCH_OPTIONS))
+            || in_object_temp_dir(Path::new(&normalized))
+    }
+}
+
+/// Whether `file` is under a tmp or temp directory inside an object directory.
+fn in_object_temp_dir(file: &Path) -> bool {
+    file.ancestors().skip(1).any(|dir| {
+        let is_temp = dir.file_name().is_some_and(|name| {
+            let name = name.to_string_lossy();
+            name.eq_ignore_ascii_case("tmp") || name.eq_ignore_ascii_case("temp")
+        });
+        is_temp && dir.parent().and_then(object_dir_parent).is_some()
+    })
+}
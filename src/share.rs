@@ -0,0 +1,293 @@
+//! The `publish` and `fetch` subcommands, for sharing a database built on one machine, like a
+//! build lab's nightly build, with developers who haven't built everything themselves.
+//!
+//! A published database is a pair of versioned files, `compile_commands-<version>.json` and its
+//! sidecar, plus a `latest.json` pointer naming them and the enlistment their paths are in. The
+//! pointer is written last, so a fetch never sees a database that's only partly published. A
+//! fetch re-roots the entries onto the developer's enlistment and merges them into their
+//! database, where entries from their own builds win if they're newer.
+
+use crate::{
+    CompileCommandsEntry, compress,
+    key::KeyCase,
+    lock, merge_new_compile_commands,
+    metadata::{self, EntryMetadata, Metadata},
+    paths, read_compile_commands,
+    reroot::RootMapping,
+    sink,
+};
+use std::{
+    collections::BTreeMap,
+    env, fs,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+/// The pointer to the latest published version.
+const POINTER_NAME: &str = "latest.json";
+
+/// How many published versions are kept in a shared directory, counting the latest.
+const KEPT_VERSIONS: usize = 5;
+
+/// What `latest.json` says.
+#[derive(serde::Serialize, serde::Deserialize)]
+struct Published {
+    /// When the database was published, in seconds since the Unix epoch, which its files are
+    /// named for
+    version: u64,
+    /// The enlistment the database's paths are in
+    source_root: String,
+    database: String,
+    metadata: String,
+}
+
+/// Where databases are published: a directory, such as a UNC path on a share, or an HTTP
+/// endpoint that files are PUT under and fetched from with GET.
+enum Location {
+    Dir(PathBuf),
+    Http(String),
+}
+
+impl Location {
+    fn parse(location: &str) -> Location {
+        if location.starts_with("http://") || location.starts_with("https://") {
+            Location::Http(location.trim_end_matches('/').to_string())
+        } else {
+            Location::Dir(PathBuf::from(location))
+        }
+    }
+
+    fn read(&self, name: &str) -> String {
+        match self {
+            Location::Dir(dir) => {
+                let path = dir.join(name);
+                fs::read_to_string(&path)
+                    .unwrap_or_else(|_| panic!("Failed to read {}", path.display()))
+            }
+            Location::Http(url) => {
+                let url = format!("{url}/{name}");
+                ureq::get(&url)
+                    .call()
+                    .and_then(|mut response| {
+                        response
+                            .body_mut()
+                            .with_config()
+                            .limit(u64::MAX)
+                            .read_to_string()
+                    })
+                    .unwrap_or_else(|e| panic!("Failed to fetch {}: {}", url, e))
+            }
+        }
+    }
+
+    /// Writes `contents` as `name`. Files in a directory are written under a temporary name and
+    /// then renamed, so readers see either the old file or the whole new one.
+    fn write(&self, name: &str, contents: &str) {
+        match self {
+            Location::Dir(dir) => {
+                let path = dir.join(name);
+                let temporary = dir.join(format!("{name}.tmp"));
+                fs::write(&temporary, contents)
+                    .unwrap_or_else(|_| panic!("Failed to write {}", temporary.display()));
+                fs::rename(&temporary, &path)
+                    .unwrap_or_else(|_| panic!("Failed to replace {}", path.display()));
+            }
+            Location::Http(url) => {
+                let url = format!("{url}/{name}");
+                ureq::put(&url)
+                    .header("Content-Type", "application/json")
+                    .send(contents)
+                    .unwrap_or_else(|e| panic!("Failed to upload {}: {}", url, e));
+            }
+        }
+    }
+
+    fn display(&self) -> &str {
+        match self {
+            Location::Dir(dir) => dir.to_str().unwrap_or_default(),
+            Location::Http(url) => url,
+        }
+    }
+}
+
+/// The enlistment root: `root`, or the one the Razzle window is in.
+fn enlistment_root(root: Option<&Path>, flag: &str) -> String {
+    match root {
+        Some(root) => paths::absolute(root).to_string_lossy().to_string(),
+        None => env::var("SDXROOT").unwrap_or_else(|_| {
+            panic!("SDXROOT isn't set, so run this in a Razzle window or pass {flag}")
+        }),
+    }
+}
+
+/// Removes all but the newest `KEPT_VERSIONS` published versions in `dir`.
+fn remove_old_versions(dir: &Path) {
+    let Ok(dir_entries) = fs::read_dir(dir) else {
+        return;
+    };
+    let mut versions: Vec<u64> = dir_entries
+        .filter_map(|dir_entry| {
+            let name = dir_entry.ok()?.file_name().to_string_lossy().to_string();
+            name.strip_prefix("compile_commands-")?
+                .strip_suffix(".json")?
+                .parse()
+                .ok()
+        })
+        .collect();
+    versions.sort_unstable_by(|a, b| b.cmp(a));
+    for version in versions.into_iter().skip(KEPT_VERSIONS) {
+        for name in [database_name(version), metadata_name(version)] {
+            let _ = fs::remove_file(dir.join(name));
+        }
+    }
+}
+
+fn database_name(version: u64) -> String {
+    format!("compile_commands-{version}.json")
+}
+
+fn metadata_name(version: u64) -> String {
+    format!("compile_commands-{version}.meta.json")
+}
+
+/// The `publish` subcommand.
+pub fn publish(output_dir: &str, to: &str, source_root: Option<&Path>) {
+    let source_root = enlistment_root(source_root, "--source-root");
+    let compile_commands_path = paths::absolute(output_dir).join("compile_commands.json");
+    let location = Location::parse(to);
+    if let Location::Dir(dir) = &location {
+        fs::create_dir_all(dir)
+            .unwrap_or_else(|_| panic!("Failed to create directory {}", dir.display()));
+    }
+
+    let (entries, metadata) = {
+        let _lock = lock::acquire(
+            &compile_commands_path,
+            Duration::from_secs(lock::DEFAULT_TIMEOUT),
+        );
+        (
+            read_compile_commands(&compile_commands_path),
+            Metadata::load(&metadata::path_for(&compile_commands_path)),
+        )
+    };
+    if entries.is_empty() {
+        panic!("{} has no entries", compile_commands_path.display());
+    }
+
+    let version = metadata::now();
+    let published = Published {
+        version,
+        source_root,
+        database: database_name(version),
+        metadata: metadata_name(version),
+    };
+    location.write(
+        &published.database,
+        &serde_json::to_string(&entries).expect("Failed to serialize compile commands to JSON"),
+    );
+    location.write(
+        &published.metadata,
+        &serde_json::to_string(&metadata).expect("Failed to serialize metadata to JSON"),
+    );
+    location.write(
+        POINTER_NAME,
+        &serde_json::to_string_pretty(&published).expect("Failed to serialize the pointer"),
+    );
+    if let Location::Dir(dir) = &location {
+        remove_old_versions(dir);
+    }
+    println!(
+        "Published {} entries from {} as version {} to {}",
+        entries.len(),
+        published.source_root,
+        version,
+        location.display()
+    );
+}
+
+/// The `fetch` subcommand.
+pub fn fetch(output_dir: &str, from: &str, local_root: Option<&Path>, key_case: KeyCase) {
+    let local_root = enlistment_root(local_root, "--local-root");
+    let location = Location::parse(from);
+    let published: Published = serde_json::from_str(&location.read(POINTER_NAME))
+        .unwrap_or_else(|e| panic!("Failed to parse {} in {}: {}", POINTER_NAME, from, e));
+    let mut entries: Vec<CompileCommandsEntry> =
+        serde_json::from_str(&location.read(&published.database)).unwrap_or_else(|e| {
+            panic!("Failed to parse {} in {}: {}", published.database, from, e)
+        });
+    let published_metadata: Metadata = serde_json::from_str(&location.read(&published.metadata))
+        .unwrap_or_else(|e| panic!("Failed to parse {} in {}: {}", published.metadata, from, e));
+
+    let mapping = RootMapping {
+        agent: published
+            .source_root
+            .trim_end_matches(['\\', '/'])
+            .to_string(),
+        local: local_root.trim_end_matches(['\\', '/']).to_string(),
+    };
+    for entry in &mut entries {
+        mapping.apply_to_entry(entry);
+    }
+    let published_entries: BTreeMap<String, EntryMetadata> = published_metadata
+        .entries
+        .into_iter()
+        .map(|(file, mut entry_metadata)| {
+            entry_metadata.templated_from = entry_metadata
+                .templated_from
+                .map(|file| mapping.apply(&file).into_owned());
+            (mapping.apply(&file).into_owned(), entry_metadata)
+        })
+        .collect();
+
+    let compile_commands_path = paths::absolute(output_dir).join("compile_commands.json");
+    let metadata_path = metadata::path_for(&compile_commands_path);
+    let _lock = lock::acquire(
+        &compile_commands_path,
+        Duration::from_secs(lock::DEFAULT_TIMEOUT),
+    );
+    let existing = read_compile_commands(&compile_commands_path);
+    let mut metadata = Metadata::load(&metadata_path);
+    let existing_count = existing.len();
+    let fetched_count = entries.len();
+    // Entries are as fresh as the log they were last seen in on the build machine
+    let fetched: Vec<(CompileCommandsEntry, u64)> = entries
+        .into_iter()
+        .map(|entry| {
+            let seen = published_entries
+                .get(&entry.file)
+                .map_or(published.version, |m| m.last_seen);
+            (entry, seen)
+        })
+        .collect();
+    let merged = merge_new_compile_commands(existing, fetched, &mut metadata, key_case);
+    // The fetched entries that were taken keep what the build machine knew about them
+    let mut taken = 0;
+    for (file, published_entry) in published_entries {
+        if let Some(entry_metadata) = metadata.entries.get_mut(&file)
+            && entry_metadata.last_seen == published_entry.last_seen
+        {
+            *entry_metadata = published_entry;
+            taken += 1;
+        }
+    }
+    metadata
+        .version
+        .get_or_insert_with(|| metadata::VERSION.to_string());
+    sink::write_json(&compile_commands_path, &merged, metadata.arguments);
+    metadata.save(&metadata_path, compress::written_with(&metadata_path));
+    println!(
+        "Fetched version {} with {} entries from {}, re-rooted from {} to {}",
+        published.version,
+        fetched_count,
+        location.display(),
+        mapping.agent,
+        mapping.local
+    );
+    println!(
+        "Merged {} of them into the {} existing entries of {}, which now has {}",
+        taken,
+        existing_count,
+        compile_commands_path.display(),
+        merged.len()
+    );
+}
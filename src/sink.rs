@@ -0,0 +1,205 @@
+//! Where the database goes once it's been generated. Every sink gets the same merged entries, and
+//! the JSON file (the default) is the one merged into on the next run.
+
+use crate::{
+    CompileCommandsEntry, cmdline,
+    compress::{self, Compression},
+    environment::Environment,
+    key::KeyCase,
+    store::{self, Store},
+};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+#[derive(clap::ValueEnum, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SinkKind {
+    /// compile_commands.json
+    JsonFile,
+    /// compile_commands.ndjson, with one entry per line
+    Ndjson,
+    /// compile_commands.sqlite, the same store --sqlite-store keeps
+    Sqlite,
+    /// A JSON array POSTed to --sink-url
+    Http,
+}
+
+/// An entry as written, with its command split into `arguments` if asked for. Consumers split
+/// `command` with the same rules `cmdline::split` follows, so paths with spaces stay intact in
+/// either form.
+#[derive(serde::Serialize)]
+struct Written<'a> {
+    directory: &'a Path,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    command: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    arguments: Option<Vec<String>>,
+    file: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    environment: Option<&'a Environment>,
+}
+
+impl<'a> Written<'a> {
+    fn new(entry: &'a CompileCommandsEntry, arguments: bool) -> Written<'a> {
+        Written {
+            directory: &entry.directory,
+            command: (!arguments).then_some(entry.command.as_str()),
+            arguments: arguments.then(|| cmdline::split(&entry.command)),
+            file: &entry.file,
+            environment: entry.environment.as_ref(),
+        }
+    }
+}
+
+/// Writes `entries` to `path` as compile_commands.json, replacing rather than writing through a
+/// symlink to a flavor's database.
+pub fn write_json(path: &Path, entries: &[CompileCommandsEntry], arguments: bool) {
+    unlink(path);
+    let written: Vec<Written> = entries
+        .iter()
+        .map(|entry| Written::new(entry, arguments))
+        .collect();
+    let json = serde_json::to_string_pretty(&written)
+        .expect("Failed to serialize compile commands to JSON");
+    fs::write(path, json)
+        .unwrap_or_else(|_| panic!("Failed to write compile commands to {}", path.display()));
+}
+
+pub trait Sink {
+    /// Writes `entries`, replacing whatever the sink held before, and returns a description of
+    /// where they went.
+    fn write(&self, entries: &[CompileCommandsEntry]) -> String;
+}
+
+/// Creates the sink of `kind` for the database at `database_path`, which the file sinks write
+/// next to, with commands split into `arguments` in the file sinks if asked for, and the NDJSON
+/// compressed with `compression`.
+pub fn create(
+    kind: SinkKind,
+    database_path: &Path,
+    url: Option<&str>,
+    arguments: bool,
+    compression: Option<Compression>,
+    key_case: KeyCase,
+) -> Box<dyn Sink> {
+    match kind {
+        SinkKind::JsonFile => Box::new(JsonFile {
+            path: database_path.to_path_buf(),
+            arguments,
+        }),
+        SinkKind::Ndjson => Box::new(Ndjson {
+            path: database_path.with_extension("ndjson"),
+            arguments,
+            compression,
+        }),
+        SinkKind::Sqlite => Box::new(Sqlite {
+            path: store::path_for(database_path),
+            key_case,
+        }),
+        SinkKind::Http => Box::new(Http {
+            url: url.expect("The http sink needs --sink-url").to_string(),
+        }),
+    }
+}
+
+/// Removes `path` if it's a symlink, so that writing replaces it rather than writing through it
+/// to a flavor's database.
+fn unlink(path: &Path) {
+    if path.is_symlink() {
+        fs::remove_file(path).unwrap_or_else(|_| panic!("Failed to remove {}", path.display()));
+    }
+}
+
+struct JsonFile {
+    path: PathBuf,
+    arguments: bool,
+}
+
+impl Sink for JsonFile {
+    fn write(&self, entries: &[CompileCommandsEntry]) -> String {
+        write_json(&self.path, entries, self.arguments);
+        self.path.display().to_string()
+    }
+}
+
+struct Ndjson {
+    path: PathBuf,
+    arguments: bool,
+    compression: Option<Compression>,
+}
+
+impl Sink for Ndjson {
+    fn write(&self, entries: &[CompileCommandsEntry]) -> String {
+        let mut ndjson = String::new();
+        for entry in entries {
+            ndjson.push_str(
+                &serde_json::to_string(&Written::new(entry, self.arguments))
+                    .expect("Failed to serialize compile command"),
+            );
+            ndjson.push('\n');
+        }
+        compress::write(&self.path, ndjson.as_bytes(), self.compression)
+            .display()
+            .to_string()
+    }
+}
+
+struct Sqlite {
+    path: PathBuf,
+    key_case: KeyCase,
+}
+
+impl Sink for Sqlite {
+    fn write(&self, entries: &[CompileCommandsEntry]) -> String {
+        Store::open(&self.path, self.key_case).update(entries);
+        self.path.display().to_string()
+    }
+}
+
+struct Http {
+    url: String,
+}
+
+impl Sink for Http {
+    fn write(&self, entries: &[CompileCommandsEntry]) -> String {
+        let json =
+            serde_json::to_string(entries).expect("Failed to serialize compile commands to JSON");
+        ureq::post(&self.url)
+            .header("Content-Type", "application/json")
+            .send(json)
+            .unwrap_or_else(|e| panic!("Failed to post compile commands to {}: {}", self.url, e));
+        self.url.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn both_forms_keep_paths_with_spaces_intact() {
+        let entry = CompileCommandsEntry {
+            directory: PathBuf::from(r"C:\Users\José (Work)\src"),
+            command: String::from(r#"cl /c /I"C:\Program Files (x86)\inc\\" "测试 file.cpp""#),
+            file: String::from(r"C:\Users\José (Work)\src\测试 file.cpp"),
+            environment: None,
+        };
+        let command = serde_json::to_value(Written::new(&entry, false)).unwrap();
+        assert_eq!(command["command"], entry.command.as_str());
+        assert!(command.get("arguments").is_none());
+
+        let arguments = serde_json::to_value(Written::new(&entry, true)).unwrap();
+        assert_eq!(
+            arguments["arguments"],
+            serde_json::json!([
+                "cl",
+                "/c",
+                r"/IC:\Program Files (x86)\inc\",
+                "测试 file.cpp"
+            ])
+        );
+        assert!(arguments.get("command").is_none());
+        assert_eq!(arguments["directory"], r"C:\Users\José (Work)\src");
+    }
+}
@@ -0,0 +1,94 @@
+//! Working out the language standard each entry compiles as, and giving entries without a /std
+//! flag an explicit one so clangd doesn't fall back to a default of its own.
+
+use crate::{CompileCommandsEntry, cmdline, compiler::Dialect, has_extension};
+
+/// The standard cl.exe compiles C++ as when no /std flag is given.
+const MSVC_DEFAULT_CPP_STD: &str = "c++14";
+
+/// The standard the command explicitly selects. The last /std flag wins, like it does for cl.exe.
+fn explicit(command: &str) -> Option<String> {
+    cmdline::split(command)
+        .iter()
+        .skip(1)
+        .filter_map(|token| {
+            token
+                .strip_prefix("/std:")
+                .or_else(|| token.strip_prefix("-std:"))
+                .or_else(|| token.strip_prefix("-std="))
+        })
+        .next_back()
+        .map(str::to_string)
+}
+
+/// C files have no default standard worth recording or injecting, since cl.exe compiles them as
+/// its own dialect of C89 unless told otherwise.
+fn is_c(entry: &CompileCommandsEntry) -> bool {
+    has_extension(&entry.file, &["c"])
+}
+
+/// The standard the entry effectively compiles as, or `None` for a C file with no /std flag.
+pub fn effective(entry: &CompileCommandsEntry) -> Option<String> {
+    explicit(&entry.command).or_else(|| (!is_c(entry)).then(|| MSVC_DEFAULT_CPP_STD.to_string()))
+}
+
+/// Adds `/std:<std>`, or `-std=<std>` for a program with gcc-like flags, after the program in the
+/// commands of C++ entries that don't select a standard. Returns whether the command was changed.
+pub fn inject_default(entry: &mut CompileCommandsEntry, std: &str, dialect: Dialect) -> bool {
+    if is_c(entry) || explicit(&entry.command).is_some() {
+        return false;
+    }
+    let command = &entry.command;
+    let program_end = if let Some(quoted) = command.strip_prefix('"') {
+        quoted.find('"').map_or(command.len(), |i| i + 2)
+    } else {
+        command.find([' ', '\t']).unwrap_or(command.len())
+    };
+    let flag = match dialect {
+        Dialect::Msvc => format!("/std:{std}"),
+        Dialect::Gnu => format!("-std={std}"),
+    };
+    entry.command = format!(
+        "{} {}{}",
+        &command[..program_end],
+        flag,
+        &command[program_end..]
+    );
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn entry(command: &str) -> CompileCommandsEntry {
+        CompileCommandsEntry {
+            directory: PathBuf::from(r"C:\src"),
+            command: command.to_string(),
+            file: String::from(r"C:\src\a.cpp"),
+            environment: None,
+        }
+    }
+
+    #[test]
+    fn injects_the_flag_the_program_takes() {
+        let mut cl = entry(r#""C:\Program Files\VC\cl.exe" /c a.cpp"#);
+        assert!(inject_default(&mut cl, "c++17", Dialect::Msvc));
+        assert_eq!(
+            cl.command,
+            r#""C:\Program Files\VC\cl.exe" /std:c++17 /c a.cpp"#
+        );
+
+        let mut clang = entry("clang++ -x cuda -c a.cpp");
+        assert!(inject_default(&mut clang, "c++17", Dialect::Gnu));
+        assert_eq!(clang.command, "clang++ -std=c++17 -x cuda -c a.cpp");
+    }
+
+    #[test]
+    fn keeps_an_explicit_standard() {
+        let mut clang = entry("clang++ -std=c++20 -c a.cpp");
+        assert!(!inject_default(&mut clang, "c++17", Dialect::Gnu));
+        assert_eq!(clang.command, "clang++ -std=c++20 -c a.cpp");
+    }
+}
@@ -0,0 +1,60 @@
+//! The `stats` subcommand, which summarizes an existing compile_commands.json and its metadata.
+
+use crate::{metadata::Metadata, paths, read_compile_commands};
+use std::{
+    collections::{BTreeMap, HashSet},
+    path::PathBuf,
+};
+
+pub fn stats(output_dir: &str, with_diagnostics: bool) {
+    let compile_commands_path = paths::absolute(output_dir).join("compile_commands.json");
+    let compile_commands = read_compile_commands(&compile_commands_path);
+    let metadata = Metadata::load(&crate::metadata::path_for(&compile_commands_path));
+
+    let directories: HashSet<&PathBuf> = compile_commands
+        .iter()
+        .map(|entry| &entry.directory)
+        .collect();
+    let entry_metadata = || {
+        compile_commands
+            .iter()
+            .filter_map(|entry| metadata.entries.get(&entry.file).map(|m| (entry, m)))
+    };
+    let templated = entry_metadata()
+        .filter(|(_, m)| m.templated_from.is_some())
+        .count();
+    let mut diagnosed: Vec<_> = entry_metadata()
+        .filter(|(_, m)| m.warnings > 0 || m.errors > 0)
+        .collect();
+
+    println!("Entries:          {}", compile_commands.len());
+    println!("Directories:      {}", directories.len());
+    println!("Templated:        {}", templated);
+    println!("With diagnostics: {}", diagnosed.len());
+
+    let mut standards: BTreeMap<&str, usize> = BTreeMap::new();
+    for (_, m) in entry_metadata() {
+        *standards
+            .entry(m.std.as_deref().unwrap_or("unknown"))
+            .or_default() += 1;
+    }
+    let standards: Vec<String> = standards
+        .iter()
+        .map(|(std, count)| format!("{} ({})", std, count))
+        .collect();
+    println!("Standards:        {}", standards.join(", "));
+
+    if with_diagnostics {
+        // Most errors first, then most warnings, so the worst files are at the top
+        diagnosed.sort_by(|(a, am), (b, bm)| {
+            (bm.errors, bm.warnings)
+                .cmp(&(am.errors, am.warnings))
+                .then_with(|| a.file.cmp(&b.file))
+        });
+        println!();
+        println!("{:>8} {:>8}  file", "errors", "warnings");
+        for (entry, m) in diagnosed {
+            println!("{:>8} {:>8}  {}", m.errors, m.warnings, entry.file);
+        }
+    }
+}
@@ -0,0 +1,204 @@
+//! The SQLite store, which holds the database's entries keyed by normalized path. Runs update only
+//! the rows that changed and tools can look up a single file without loading every entry, which
+//! matters once there are 100k+ entries. compile_commands.json is exported from it.
+
+use crate::{CompileCommandsEntry, key::KeyCase, lock, sink};
+use rusqlite::{Connection, OptionalExtension};
+use std::{
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+/// Bumped when the schema changes. A store with an older schema is rebuilt from scratch, which
+/// is safe because the next run (or the JSON file) can always repopulate it.
+const SCHEMA_VERSION: i32 = 1;
+
+/// The store next to the database at `database_path`.
+pub fn path_for(database_path: &Path) -> PathBuf {
+    database_path.with_extension("sqlite")
+}
+
+pub struct Store {
+    path: PathBuf,
+    connection: Connection,
+    /// How files are keyed
+    key_case: KeyCase,
+}
+
+fn entry_from_row(row: &rusqlite::Row) -> rusqlite::Result<CompileCommandsEntry> {
+    let directory: String = row.get(1)?;
+    let environment: Option<String> = row.get(3)?;
+    Ok(CompileCommandsEntry {
+        file: row.get(0)?,
+        directory: PathBuf::from(directory),
+        command: row.get(2)?,
+        environment: environment.and_then(|environment| serde_json::from_str(&environment).ok()),
+    })
+}
+
+impl Store {
+    /// Opens the store at `path`, creating it if it doesn't exist, to key files by `key_case`.
+    pub fn open(path: &Path, key_case: KeyCase) -> Store {
+        let connection = Connection::open(path)
+            .unwrap_or_else(|e| panic!("Failed to open {}: {}", path.display(), e));
+        let store = Store {
+            path: path.to_path_buf(),
+            connection,
+            key_case,
+        };
+        let version: i32 = store
+            .connection
+            .pragma_query_value(None, "user_version", |row| row.get(0))
+            .unwrap_or_else(|e| store.fail(e));
+        if version != SCHEMA_VERSION {
+            store
+                .connection
+                .execute_batch(&format!(
+                    "DROP TABLE IF EXISTS compile_commands;
+                     CREATE TABLE compile_commands (
+                         key TEXT PRIMARY KEY,
+                         file TEXT NOT NULL,
+                         directory TEXT NOT NULL,
+                         command TEXT NOT NULL,
+                         environment TEXT
+                     );
+                     PRAGMA user_version = {SCHEMA_VERSION};"
+                ))
+                .unwrap_or_else(|e| store.fail(e));
+        }
+        store
+    }
+
+    fn fail(&self, e: rusqlite::Error) -> ! {
+        panic!("Failed to access {}: {}", self.path.display(), e)
+    }
+
+    /// Every entry, ordered by file.
+    pub fn entries(&self) -> Vec<CompileCommandsEntry> {
+        let mut select = self
+            .connection
+            .prepare(
+                "SELECT file, directory, command, environment FROM compile_commands ORDER BY key",
+            )
+            .unwrap_or_else(|e| self.fail(e));
+        select
+            .query_map((), entry_from_row)
+            .and_then(|rows| rows.collect())
+            .unwrap_or_else(|e| self.fail(e))
+    }
+
+    /// The entry for `file`, if it has one.
+    pub fn lookup(&self, file: &str) -> Option<CompileCommandsEntry> {
+        self.connection
+            .query_row(
+                "SELECT file, directory, command, environment FROM compile_commands WHERE key = ?1",
+                [self.key_case.key(file)],
+                entry_from_row,
+            )
+            .optional()
+            .unwrap_or_else(|e| self.fail(e))
+    }
+
+    /// Makes the store hold exactly `entries`, writing only the rows that changed. Returns how
+    /// many entries were added or changed, and how many were removed.
+    pub fn update(&mut self, entries: &[CompileCommandsEntry]) -> (usize, usize) {
+        let transaction = self
+            .connection
+            .transaction()
+            .unwrap_or_else(|e| panic!("Failed to access {}: {}", self.path.display(), e));
+        let fail =
+            |e: rusqlite::Error| -> ! { panic!("Failed to update {}: {}", self.path.display(), e) };
+        transaction
+            .execute_batch("CREATE TEMP TABLE current (key TEXT PRIMARY KEY)")
+            .unwrap_or_else(|e| fail(e));
+        let mut changed = 0;
+        {
+            let mut upsert = transaction
+                .prepare(
+                    "INSERT INTO compile_commands (key, file, directory, command, environment)
+                     VALUES (?1, ?2, ?3, ?4, ?5)
+                     ON CONFLICT (key) DO UPDATE SET
+                         file = excluded.file,
+                         directory = excluded.directory,
+                         command = excluded.command,
+                         environment = excluded.environment
+                     WHERE file IS NOT excluded.file
+                         OR directory IS NOT excluded.directory
+                         OR command IS NOT excluded.command
+                         OR environment IS NOT excluded.environment",
+                )
+                .unwrap_or_else(|e| fail(e));
+            let mut current = transaction
+                .prepare("INSERT OR IGNORE INTO current (key) VALUES (?1)")
+                .unwrap_or_else(|e| fail(e));
+            for entry in entries {
+                let key = self.key_case.key(&entry.file);
+                let environment = entry.environment.as_ref().map(|environment| {
+                    serde_json::to_string(environment).expect("Failed to serialize environment")
+                });
+                changed += upsert
+                    .execute((
+                        &key,
+                        &entry.file,
+                        entry.directory.to_string_lossy(),
+                        &entry.command,
+                        environment,
+                    ))
+                    .unwrap_or_else(|e| fail(e));
+                current.execute([&key]).unwrap_or_else(|e| fail(e));
+            }
+        }
+        let removed = transaction
+            .execute(
+                "DELETE FROM compile_commands WHERE key NOT IN (SELECT key FROM current)",
+                (),
+            )
+            .unwrap_or_else(|e| fail(e));
+        transaction
+            .execute_batch("DROP TABLE current")
+            .unwrap_or_else(|e| fail(e));
+        transaction.commit().unwrap_or_else(|e| fail(e));
+        (changed, removed)
+    }
+
+    /// Writes every entry to `json_path` as compile_commands.json, returning how many there are.
+    pub fn export(&self, json_path: &Path, arguments: bool) -> usize {
+        let entries = self.entries();
+        sink::write_json(json_path, &entries, arguments);
+        entries.len()
+    }
+}
+
+/// The entry for `file` in the database at `database_path`, looked up in the store if there is
+/// one, rather than by loading the whole JSON file.
+pub fn find_entry(
+    database_path: &Path,
+    file: &str,
+    key_case: KeyCase,
+) -> Option<CompileCommandsEntry> {
+    let store_path = path_for(database_path);
+    if store_path.is_file() {
+        return Store::open(&store_path, key_case).lookup(file);
+    }
+    let key = key_case.key(file);
+    crate::read_compile_commands(database_path)
+        .into_iter()
+        .find(|entry| key_case.key(&entry.file) == key)
+}
+
+/// The `export` subcommand, which regenerates compile_commands.json from the store.
+pub fn export(output_dir: &str, arguments: bool, key_case: KeyCase) {
+    let database_path = Path::new(output_dir).join("compile_commands.json");
+    let store_path = path_for(&database_path);
+    if !store_path.is_file() {
+        panic!("There is no store at {}", store_path.display());
+    }
+    let _lock = lock::acquire(&database_path, Duration::from_secs(lock::DEFAULT_TIMEOUT));
+    let exported = Store::open(&store_path, key_case).export(&database_path, arguments);
+    println!(
+        "Exported {} entries from {} to {}",
+        exported,
+        store_path.display(),
+        database_path.display()
+    );
+}
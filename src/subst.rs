@@ -0,0 +1,107 @@
+//! Translating paths on subst'd and mapped network drives, like the W: a Razzle window substs for
+//! its enlistment, to the physical paths they stand for, which tools run outside the window can
+//! resolve.
+
+use crate::reroot::RootMapping;
+
+/// The drives mapped to another path on this machine, as mappings from each drive to its target.
+pub fn mappings() -> Vec<RootMapping> {
+    let mappings: Vec<RootMapping> = ('A'..='Z')
+        .filter_map(|letter| {
+            let drive = format!("{letter}:");
+            let target = target(&query_dos_device(&drive)?)?;
+            Some(RootMapping {
+                agent: drive,
+                local: target.trim_end_matches('\\').to_string(),
+            })
+        })
+        .collect();
+    if mappings.is_empty() {
+        println!("Warning: found no subst'd or mapped drives to resolve");
+    }
+    for mapping in &mappings {
+        println!("Resolving {} to {}", mapping.agent, mapping.local);
+    }
+    mappings
+}
+
+/// The path the DOS device target `device` names, for subst'd drives (`\??\C:\os\src`, or
+/// `\??\UNC\server\share` for a subst'd share) and mapped network drives
+/// (`\Device\LanmanRedirector\;W:0000000000012345\server\share`). Drives backed by a volume, like
+/// `\Device\HarddiskVolume3`, don't name a path.
+fn target(device: &str) -> Option<String> {
+    if let Some(path) = device.strip_prefix(r"\??\") {
+        return Some(match path.strip_prefix(r"UNC\") {
+            Some(share) => format!(r"\\{share}"),
+            None => path.to_string(),
+        });
+    }
+    // The redirector's own components end with the one naming the drive and logon session
+    let components: Vec<&str> = device.split('\\').collect();
+    let session = components.iter().rposition(|c| c.starts_with(';'))?;
+    let share = &components[session + 1..];
+    (share.len() >= 2).then(|| format!(r"\\{}", share.join(r"\")))
+}
+
+/// The current target of the DOS device `name`, like `W:`.
+#[cfg(windows)]
+fn query_dos_device(name: &str) -> Option<String> {
+    #[link(name = "kernel32")]
+    unsafe extern "system" {
+        fn QueryDosDeviceW(device_name: *const u16, target_path: *mut u16, max: u32) -> u32;
+    }
+
+    let name: Vec<u16> = name.encode_utf16().chain([0]).collect();
+    let mut buffer = vec![0u16; 1024];
+    // SAFETY: `name` is NUL-terminated and `buffer` is as long as the length passed
+    let length =
+        unsafe { QueryDosDeviceW(name.as_ptr(), buffer.as_mut_ptr(), buffer.len() as u32) };
+    if length == 0 {
+        return None;
+    }
+    // The buffer holds NUL-terminated targets, the first of which is the current one
+    let current = buffer.split(|&c| c == 0).next()?;
+    Some(String::from_utf16_lossy(current))
+}
+
+/// Drives are only mapped on Windows.
+#[cfg(not(windows))]
+fn query_dos_device(_name: &str) -> Option<String> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_subst_targets() {
+        assert_eq!(target(r"\??\C:\os\src").as_deref(), Some(r"C:\os\src"));
+        assert_eq!(
+            target(r"\??\UNC\server\share\os").as_deref(),
+            Some(r"\\server\share\os")
+        );
+    }
+
+    #[test]
+    fn reads_mapped_network_drives_as_unc_paths() {
+        assert_eq!(
+            target(r"\Device\LanmanRedirector\;W:0000000000012345\server\share").as_deref(),
+            Some(r"\\server\share")
+        );
+        assert_eq!(
+            target(r"\Device\LanmanRedirector\;W:0000000000012345\server\share\os\src").as_deref(),
+            Some(r"\\server\share\os\src")
+        );
+        // A share needs a server and a share name
+        assert_eq!(
+            target(r"\Device\LanmanRedirector\;W:0000000000012345\server"),
+            None
+        );
+    }
+
+    #[test]
+    fn volumes_dont_name_a_path() {
+        assert_eq!(target(r"\Device\HarddiskVolume3"), None);
+    }
+}
@@ -0,0 +1,277 @@
+//! Synthesizes entries for source files that exist on disk but haven't been compiled yet, such as
+//! files created since the last build, by cloning the command of a sibling in the same directory.
+
+use crate::{
+    CompileCommandsEntry, SOURCE_EXTENSIONS, cmdline, has_extension, key::KeyCase,
+    metadata::Metadata,
+};
+use std::{
+    collections::{BTreeMap, HashSet},
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// Creates entries for source files without one in directories that already have entries,
+/// recording the sibling each was cloned from in `metadata`. A file has an entry if one's file has
+/// the same key under `key_case`.
+pub fn template_missing(
+    entries: &[CompileCommandsEntry],
+    metadata: &mut Metadata,
+    key_case: KeyCase,
+) -> Vec<CompileCommandsEntry> {
+    let known: HashSet<String> = entries
+        .iter()
+        .map(|entry| key_case.key(&entry.file))
+        .collect();
+
+    // Only entries that came from a real command are used as templates
+    let mut by_dir: BTreeMap<PathBuf, Vec<&CompileCommandsEntry>> = BTreeMap::new();
+    for entry in entries {
+        let templated = metadata
+            .entries
+            .get(&entry.file)
+            .is_some_and(|m| m.templated_from.is_some());
+        if let Some(dir) = Path::new(&entry.file).parent()
+            && !templated
+        {
+            by_dir.entry(dir.to_path_buf()).or_default().push(entry);
+        }
+    }
+
+    let mut templated = Vec::new();
+    for (dir, siblings) in by_dir {
+        let Ok(dir_entries) = fs::read_dir(&dir) else {
+            continue;
+        };
+        let mut missing: Vec<PathBuf> = dir_entries
+            .filter_map(|dir_entry| dir_entry.ok().map(|e| e.path()))
+            .filter(|path| path.is_file())
+            .filter(|path| has_extension(&path.to_string_lossy(), SOURCE_EXTENSIONS))
+            .filter(|path| !known.contains(&key_case.key(&path.to_string_lossy())))
+            .collect();
+        missing.sort();
+
+        for path in missing {
+            let sibling = nearest_sibling(&path, &siblings);
+            let Some(entry) = clone_for(sibling, &path) else {
+                continue;
+            };
+            metadata
+                .entries
+                .entry(entry.file.clone())
+                .or_default()
+                .templated_from = Some(sibling.file.clone());
+            templated.push(entry);
+        }
+    }
+    templated
+}
+
+/// The sibling most likely to be compiled the same way as `path`: one with the same extension if
+/// possible, then the one sharing the longest file name prefix.
+fn nearest_sibling<'a>(
+    path: &Path,
+    siblings: &[&'a CompileCommandsEntry],
+) -> &'a CompileCommandsEntry {
+    let name = file_name(path);
+    let extension = path.extension();
+    siblings
+        .iter()
+        .max_by_key(|sibling| {
+            let sibling_path = Path::new(&sibling.file);
+            let same_extension = sibling_path
+                .extension()
+                .zip(extension)
+                .is_some_and(|(a, b)| a.eq_ignore_ascii_case(b));
+            let common_prefix = file_name(sibling_path)
+                .chars()
+                .zip(name.chars())
+                .take_while(|(a, b)| a.eq_ignore_ascii_case(b))
+                .count();
+            // Ties go to the first sibling alphabetically, for stable output
+            (
+                same_extension,
+                common_prefix,
+                std::cmp::Reverse(&sibling.file),
+            )
+        })
+        .expect("Every tracked directory has at least one sibling")
+}
+
+/// Clones `sibling` for `path` by substituting its file name in the command, if the sibling's file
+/// name can be found there. The command is re-quoted, since the new name may need quotes (for
+/// spaces) where the sibling's didn't. The sibling's own outputs are renamed for `path` too, so
+/// the clone doesn't claim to write the sibling's object file.
+fn clone_for(sibling: &CompileCommandsEntry, path: &Path) -> Option<CompileCommandsEntry> {
+    let sibling_name = file_name(Path::new(&sibling.file));
+    let name = file_name(path);
+
+    // The sibling's file name as a whole argument, or the end of a path in one
+    let mut tokens = cmdline::split(&sibling.command);
+    let token = tokens.iter_mut().skip(1).rev().find(|token| {
+        token
+            .strip_suffix(sibling_name.as_str())
+            .is_some_and(|dir| dir.is_empty() || dir.ends_with(['\\', '/']))
+    })?;
+    token.truncate(token.len() - sibling_name.len());
+    token.push_str(&name);
+    let tokens = rename_outputs(
+        tokens,
+        &file_stem(&sibling.file),
+        &file_stem(&path.to_string_lossy()),
+    );
+
+    Some(CompileCommandsEntry {
+        directory: sibling.directory.clone(),
+        command: cmdline::join(&tokens),
+        file: path.to_string_lossy().to_string(),
+        environment: sibling.environment.clone(),
+    })
+}
+
+/// `tokens` with each /Fo and /Fd value naming a file after the sibling, like `obj\a.obj` for
+/// `a.cpp`, renamed after `stem` instead. A /Fo naming some other file is dropped, since the clone
+/// doesn't write that object. Values naming a directory are the same for every file, and a /Fd
+/// naming some other file is a PDB the directory's files share, so those are kept.
+fn rename_outputs(tokens: Vec<String>, sibling_stem: &str, stem: &str) -> Vec<String> {
+    let mut renamed = Vec::new();
+    let mut tokens = tokens.into_iter();
+    while let Some(token) = tokens.next() {
+        let Some((flag, rest)) = ["/Fo", "-Fo", "/Fd", "-Fd"]
+            .into_iter()
+            .find_map(|flag| Some((flag, token.strip_prefix(flag)?)))
+        else {
+            renamed.push(token);
+            continue;
+        };
+        // The value can be written `/Fovalue`, `/Fo:value` or `/Fo: value`
+        let (colon, value) = match rest.strip_prefix(':') {
+            Some(value) => (":", value),
+            None => ("", rest),
+        };
+        let separate = value.is_empty();
+        let value = if separate {
+            tokens.next().unwrap_or_default()
+        } else {
+            value.to_string()
+        };
+        let name = value.rsplit(['\\', '/']).next().unwrap_or_default();
+        let (name_stem, extension) = name.rsplit_once('.').unwrap_or((name, ""));
+        let value = if name.is_empty() {
+            value
+        } else if name_stem.eq_ignore_ascii_case(sibling_stem) {
+            let dir = value.strip_suffix(name).unwrap_or_default();
+            let dot = if extension.is_empty() { "" } else { "." };
+            format!("{dir}{stem}{dot}{extension}")
+        } else if flag.ends_with("Fo") {
+            continue;
+        } else {
+            value
+        };
+        if separate {
+            renamed.extend([format!("{flag}{colon}"), value]);
+        } else {
+            renamed.push(format!("{flag}{colon}{value}"));
+        }
+    }
+    renamed
+}
+
+fn file_stem(file: &str) -> String {
+    Path::new(file)
+        .file_stem()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .to_string()
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(dir: &Path, name: &str, command: &str) -> CompileCommandsEntry {
+        CompileCommandsEntry {
+            directory: dir.to_path_buf(),
+            command: command.to_string(),
+            file: dir.join(name).to_string_lossy().to_string(),
+            environment: None,
+        }
+    }
+
+    #[test]
+    fn renames_the_siblings_outputs() {
+        let rename =
+            |command: &str| cmdline::join(&rename_outputs(cmdline::split(command), "a", "b"));
+        assert_eq!(
+            rename(r"cl /c /Foobj\a.obj /Fd:obj\A.pdb b.cpp"),
+            r"cl /c /Foobj\b.obj /Fd:obj\b.pdb b.cpp"
+        );
+        assert_eq!(rename("cl /c /Fo: obj/a b.cpp"), "cl /c /Fo: obj/b b.cpp");
+        // Directories and shared PDBs are the same for every file, other objects aren't
+        assert_eq!(
+            rename(r"cl /c /Foobj\ /Fdobj\vc143.pdb b.cpp"),
+            r"cl /c /Foobj\ /Fdobj\vc143.pdb b.cpp"
+        );
+        assert_eq!(
+            rename(r"cl /c /Foobj\main.obj /Fo: x.obj b.cpp"),
+            "cl /c b.cpp"
+        );
+    }
+
+    #[test]
+    fn templates_files_without_an_entry() {
+        let dir = std::env::temp_dir().join(format!(
+            "buildexe-to-compilecommands.{}.template",
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        for name in ["a.cpp", "b.cpp", "b.h"] {
+            fs::write(dir.join(name), "").unwrap();
+        }
+        let entries = [entry(&dir, "a.cpp", r"cl /c /Foobj\a.obj a.cpp")];
+
+        let mut metadata = Metadata::default();
+        let templated = template_missing(&entries, &mut metadata, KeyCase::Sensitive);
+        assert_eq!(templated.len(), 1);
+        let file = dir.join("b.cpp").to_string_lossy().to_string();
+        assert_eq!(templated[0].file, file);
+        assert_eq!(templated[0].command, r"cl /c /Foobj\b.obj b.cpp");
+        assert_eq!(
+            metadata.entries[&file].templated_from.as_deref(),
+            Some(entries[0].file.as_str())
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn templates_files_without_an_entry_under_the_key_case() {
+        let dir = std::env::temp_dir().join(format!(
+            "buildexe-to-compilecommands.{}.template-key",
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        for name in ["a.cpp", "b.cpp"] {
+            fs::write(dir.join(name), "").unwrap();
+        }
+        // The log spelled the file's name differently than it is on disk
+        let entries = [entry(&dir, "A.cpp", r"cl /c /Foobj\A.obj A.cpp")];
+
+        let templated = template_missing(&entries, &mut Metadata::default(), KeyCase::Insensitive);
+        assert_eq!(templated.len(), 1);
+        assert_eq!(templated[0].file, dir.join("b.cpp").to_string_lossy());
+        assert_eq!(templated[0].command, r"cl /c /Foobj\b.obj b.cpp");
+
+        let templated = template_missing(&entries, &mut Metadata::default(), KeyCase::Sensitive);
+        assert_eq!(templated.len(), 2);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,130 @@
+//! Performance counters for --timings: the wall time each stage of a run took and how much it
+//! processed, to attach to a report of a slow run and to track regressions. Runs over several
+//! logs or projects add up their counters, so stages of projects run in parallel can add up to
+//! more than the run took.
+
+use serde_json::json;
+use std::{
+    fs,
+    path::Path,
+    time::{Duration, Instant},
+};
+
+#[derive(Clone, Copy)]
+pub enum Stage {
+    /// Reading the logs, apart from handling their lines
+    Read,
+    /// Cleaning up and parsing log lines
+    Parse,
+    /// Turning commands into entries and resolving their paths and variables
+    Paths,
+    /// Reading the existing database, merging the new entries into it and adjusting the result
+    Merge,
+    /// Moving long commands' flags into response files and writing them
+    ResponseFiles,
+    /// Writing the database and its sidecar
+    Serialize,
+}
+
+impl Stage {
+    const ALL: [Stage; 6] = [
+        Stage::Read,
+        Stage::Parse,
+        Stage::Paths,
+        Stage::Merge,
+        Stage::ResponseFiles,
+        Stage::Serialize,
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            Stage::Read => "read",
+            Stage::Parse => "parse",
+            Stage::Paths => "paths",
+            Stage::Merge => "merge",
+            Stage::ResponseFiles => "response_files",
+            Stage::Serialize => "serialize",
+        }
+    }
+}
+
+#[derive(Default, Clone, Copy)]
+pub struct Timings {
+    stages: [Duration; Stage::ALL.len()],
+    /// Log lines read
+    pub lines: usize,
+    /// Distinct commands found in the logs
+    pub commands: usize,
+    /// Entries in the databases written
+    pub entries: usize,
+}
+
+impl Timings {
+    pub fn add(&mut self, stage: Stage, duration: Duration) {
+        self.stages[stage as usize] += duration;
+    }
+
+    /// Adds the time since `since` to `stage`, and starts timing the next stage from now.
+    pub fn lap(&mut self, stage: Stage, since: &mut Instant) {
+        let now = Instant::now();
+        self.add(stage, now - *since);
+        *since = now;
+    }
+
+    pub fn combine(&mut self, other: &Timings) {
+        for stage in Stage::ALL {
+            self.add(stage, other.stages[stage as usize]);
+        }
+        self.lines += other.lines;
+        self.commands += other.commands;
+        self.entries += other.entries;
+    }
+
+    fn total(&self) -> Duration {
+        self.stages.iter().sum()
+    }
+
+    /// Prints the counters, and writes them as JSON to `json_path` if given.
+    pub fn report(&self, json_path: Option<&Path>) {
+        println!();
+        println!("{:<16} {:>10} {:>6}", "stage", "seconds", "%");
+        let total = self.total().as_secs_f64();
+        for stage in Stage::ALL {
+            let seconds = self.stages[stage as usize].as_secs_f64();
+            let percent = if total > 0.0 {
+                seconds / total * 100.0
+            } else {
+                0.0
+            };
+            println!("{:<16} {:>10.3} {:>6.1}", stage.name(), seconds, percent);
+        }
+        println!("{:<16} {:>10.3}", "total", total);
+        println!(
+            "{} lines, {} commands, {} entries",
+            self.lines, self.commands, self.entries
+        );
+
+        let Some(json_path) = json_path else {
+            return;
+        };
+        let stages: serde_json::Map<String, serde_json::Value> = Stage::ALL
+            .into_iter()
+            .map(|stage| {
+                let seconds = self.stages[stage as usize].as_secs_f64();
+                (stage.name().to_string(), json!(seconds))
+            })
+            .collect();
+        let report = json!({
+            "version": crate::metadata::VERSION,
+            "seconds": stages,
+            "total_seconds": total,
+            "lines": self.lines,
+            "commands": self.commands,
+            "entries": self.entries,
+        });
+        let json = serde_json::to_string_pretty(&report).expect("Failed to serialize timings");
+        fs::write(json_path, json)
+            .unwrap_or_else(|_| panic!("Failed to write {}", json_path.display()));
+        println!("Wrote the timings to {}", json_path.display());
+    }
+}
@@ -0,0 +1,98 @@
+//! The --trace-parse output, which records how the parser classified each line of a log and what
+//! that did to its state, one JSON record per line. It's for finding the lines a new build
+//! wrapper writes that the parser mishandles.
+
+use std::{
+    fs::{File, OpenOptions},
+    io::{BufWriter, Write},
+    path::Path,
+};
+
+/// What the parser took a line to be.
+#[derive(serde::Serialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum LineKind {
+    /// A banner naming the directory its thread is processing
+    Banner,
+    /// The first line of a compiler invocation
+    CommandStart,
+    /// A line continuing the command being read
+    Continuation,
+    /// A marker for the build pass the log is in
+    Pass,
+    /// `build /why` saying a target is out of date
+    WhyOutOfDate,
+    /// `build /why` saying a directory or target is up to date
+    WhyUpToDate,
+    /// Anything else
+    #[default]
+    Ignored,
+}
+
+/// The parser's state, as recorded in the trace.
+#[derive(serde::Serialize, Clone, Copy)]
+#[serde(rename_all = "kebab-case")]
+pub enum State {
+    LookingForCommand,
+    ReadingCommand,
+}
+
+/// How the parser handled one line.
+#[derive(serde::Serialize)]
+pub struct Record<'a> {
+    pub log: &'a Path,
+    pub line: usize,
+    pub text: &'a str,
+    pub kind: LineKind,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub thread: Option<&'a str>,
+    /// The state before the line
+    pub from: State,
+    /// The state after the line
+    pub to: State,
+    /// The directory the line's thread is in after the line
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dir: Option<&'a Path>,
+    pub pass: u32,
+    /// The line the command this line completed started on, if it completed one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub completed: Option<usize>,
+    /// Whether the completed command was skipped, as one a front end like nvcc ran on its own
+    /// behalf
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub skipped: bool,
+}
+
+pub struct ParseTrace {
+    file: BufWriter<File>,
+}
+
+/// Empties the trace at `path`, so that a run's logs are appended to a fresh one.
+pub fn reset(path: &Path) {
+    File::create(path).unwrap_or_else(|_| panic!("Failed to create {}", path.display()));
+}
+
+impl ParseTrace {
+    /// Opens the trace at `path` for appending.
+    pub fn open(path: &Path) -> ParseTrace {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .unwrap_or_else(|_| panic!("Failed to open {}", path.display()));
+        ParseTrace {
+            file: BufWriter::new(file),
+        }
+    }
+
+    pub fn record(&mut self, record: &Record) {
+        serde_json::to_writer(&mut self.file, record).expect("Failed to write the parse trace");
+        self.file
+            .write_all(b"\n")
+            .expect("Failed to write the parse trace");
+    }
+
+    pub fn finish(mut self) {
+        self.file.flush().expect("Failed to write the parse trace");
+    }
+}